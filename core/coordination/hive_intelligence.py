@@ -15,18 +15,14 @@ import structlog
 from prometheus_client import Counter, Histogram, Gauge
 import numpy as np
 
+from .hive_memory import HiveMemory, HiveMemoryFragment, HiveMemoryType, MemoryBackend, backend_from_config
+
 class HiveDecisionMethod(Enum):
     CONSENSUS = "consensus"           # Require majority agreement
     WEIGHTED_VOTING = "weighted"     # Weight votes by agent expertise  
     QUORUM = "quorum"               # Minimum participation threshold
     EMERGENT = "emergent"           # Let patterns emerge naturally
 
-class HiveMemoryType(Enum):
-    WORKING = "working"             # Short-term task memory
-    EPISODIC = "episodic"          # Experience-based memory
-    SEMANTIC = "semantic"          # Knowledge and facts
-    COLLECTIVE = "collective"      # Shared hive memory
-
 @dataclass
 class HiveNode:
     node_id: str
@@ -37,17 +33,6 @@ class HiveNode:
     connections: Set[str] = field(default_factory=set)
     memory_contribution: Dict[str, Any] = field(default_factory=dict)
 
-@dataclass
-class HiveMemoryFragment:
-    fragment_id: str
-    memory_type: HiveMemoryType
-    content: Any
-    contributors: Set[str]
-    confidence_score: float
-    access_count: int = 0
-    last_accessed: float = field(default_factory=time.time)
-    relevance_decay: float = 1.0
-
 @dataclass
 class HiveDecision:
     decision_id: str
@@ -73,14 +58,14 @@ class HiveIntelligence:
     collective_confidence = Gauge('hive_collective_confidence', 'Overall hive confidence')
     memory_fragments_gauge = Gauge('hive_memory_fragments', 'Number of memory fragments')
     
-    def __init__(self, swarm_manager=None):
-        """Initialize hive intelligence system"""
+    def __init__(self, swarm_manager=None, memory_backend: Optional[MemoryBackend] = None):
+        """Initialize hive intelligence system; memory goes to the configured backend unless one is given"""
         self.logger = structlog.get_logger(__name__)
         self.swarm_manager = swarm_manager
         
         # Hive state
         self.nodes: Dict[str, HiveNode] = {}
-        self.memory_store: Dict[str, HiveMemoryFragment] = {}
+        self.memory = HiveMemory(memory_backend if memory_backend is not None else backend_from_config())
         self.active_decisions: Dict[str, HiveDecision] = {}
         self.decision_history: List[HiveDecision] = []
         
@@ -205,9 +190,9 @@ class HiveIntelligence:
             confidence_score=confidence
         )
         
-        self.memory_store[fragment_id] = fragment
+        self.memory.store(fragment)
         self.memory_operations.labels(operation="store").inc()
-        self.memory_fragments_gauge.set(len(self.memory_store))
+        self.memory_fragments_gauge.set(len(self.memory.list()))
         
         # Update node contributions
         for contributor_id in contributors:
@@ -235,45 +220,10 @@ class HiveIntelligence:
     ) -> List[HiveMemoryFragment]:
         """Recall relevant memory fragments from collective memory"""
         
-        relevant_fragments = []
-        
-        for fragment in self.memory_store.values():
-            # Filter by type if specified
-            if memory_type and fragment.memory_type != memory_type:
-                continue
-            
-            # Filter by confidence threshold
-            if fragment.confidence_score < min_confidence:
-                continue
-            
-            # Calculate relevance (simple string matching for now)
-            relevance = self._calculate_memory_relevance(query, fragment)
-            
-            if relevance > 0.3:  # Relevance threshold
-                fragment.access_count += 1
-                fragment.last_accessed = time.time()
-                relevant_fragments.append(fragment)
-        
-        # Sort by relevance and confidence
-        relevant_fragments.sort(
-            key=lambda f: f.confidence_score * f.relevance_decay,
-            reverse=True
-        )
-        
+        relevant_fragments = self.memory.recall(query, memory_type, min_confidence)
         self.memory_operations.labels(operation="recall").inc()
         
-        return relevant_fragments[:10]  # Return top 10 most relevant
-    
-    def _calculate_memory_relevance(self, query: str, fragment: HiveMemoryFragment) -> float:
-        """Calculate relevance of memory fragment to query"""
-        query_words = set(query.lower().split())
-        content_words = set(str(fragment.content).lower().split())
-        
-        if not query_words or not content_words:
-            return 0.0
-        
-        intersection = query_words.intersection(content_words)
-        return len(intersection) / len(query_words.union(content_words))
+        return relevant_fragments
     
     async def initiate_hive_decision(
         self,
@@ -675,7 +625,7 @@ class HiveIntelligence:
         current_time = time.time()
         decayed_fragments = []
         
-        for fragment in self.memory_store.values():
+        for fragment in self.memory.list():
             # Calculate time-based decay
             time_since_access = current_time - fragment.last_accessed
             decay_factor = self.memory_decay_rate ** (time_since_access / 3600)  # Decay per hour
@@ -685,13 +635,15 @@ class HiveIntelligence:
             # Remove fragments that have decayed too much
             if fragment.relevance_decay < 0.1:
                 decayed_fragments.append(fragment.fragment_id)
+            else:
+                self.memory.store(fragment)
         
         # Remove decayed fragments
         for fragment_id in decayed_fragments:
-            del self.memory_store[fragment_id]
+            self.memory.delete(fragment_id)
             self.memory_operations.labels(operation="decay").inc()
         
-        self.memory_fragments_gauge.set(len(self.memory_store))
+        self.memory_fragments_gauge.set(len(self.memory.list()))
         
         if decayed_fragments:
             self.logger.info("memory_decay_applied", removed_fragments=len(decayed_fragments))
@@ -701,7 +653,7 @@ class HiveIntelligence:
         
         return {
             "nodes": len(self.nodes),
-            "memory_fragments": len(self.memory_store),
+            "memory_fragments": len(self.memory.list()),
             "active_decisions": len(self.active_decisions),
             "decision_history": len(self.decision_history),
             "collective_confidence": self._calculate_collective_confidence(),
@@ -0,0 +1,262 @@
+"""
+Hive Memory storage for AgentNativeFramework
+Backends only keep fragments; HiveMemory does the recall ranking, so it is the same for every store
+"""
+
+import json
+import logging
+import os
+import sqlite3
+import tempfile
+import time
+from abc import ABC, abstractmethod
+from dataclasses import dataclass, field
+from enum import Enum
+from pathlib import Path
+from typing import Any, Dict, List, Optional, Set
+
+try:
+    import tomllib
+except ModuleNotFoundError:  # Python < 3.11
+    try:
+        import tomli as tomllib
+    except ModuleNotFoundError:
+        tomllib = None
+
+logger = logging.getLogger(__name__)
+
+class HiveMemoryType(Enum):
+    WORKING = "working"             # Short-term task memory
+    EPISODIC = "episodic"          # Experience-based memory
+    SEMANTIC = "semantic"          # Knowledge and facts
+    COLLECTIVE = "collective"      # Shared hive memory
+
+@dataclass
+class HiveMemoryFragment:
+    fragment_id: str
+    memory_type: HiveMemoryType
+    content: Any
+    contributors: Set[str]
+    confidence_score: float
+    access_count: int = 0
+    last_accessed: float = field(default_factory=time.time)
+    relevance_decay: float = 1.0
+
+class MemoryBackend(ABC):
+    """Where hive memory fragments are kept"""
+
+    @abstractmethod
+    def store(self, fragment: HiveMemoryFragment) -> None:
+        """Insert the fragment, or replace the one with the same id"""
+
+    @abstractmethod
+    def recall(
+        self,
+        memory_type: Optional[HiveMemoryType] = None,
+        min_confidence: float = 0.0
+    ) -> List[HiveMemoryFragment]:
+        """Fragments of `memory_type` (any, when None) with at least `min_confidence`, unranked"""
+
+    @abstractmethod
+    def list(self) -> List[HiveMemoryFragment]:
+        """Every fragment"""
+
+    @abstractmethod
+    def delete(self, fragment_id: str) -> bool:
+        """Remove a fragment; False when there was none"""
+
+class InMemoryBackend(MemoryBackend):
+    """Fragments in a dict, gone when the bridge stops; for tests and throwaway runs"""
+
+    def __init__(self):
+        self.fragments: Dict[str, HiveMemoryFragment] = {}
+
+    def store(self, fragment: HiveMemoryFragment) -> None:
+        self.fragments[fragment.fragment_id] = fragment
+
+    def recall(self, memory_type=None, min_confidence=0.0) -> List[HiveMemoryFragment]:
+        return [
+            fragment for fragment in self.fragments.values()
+            if (memory_type is None or fragment.memory_type == memory_type)
+            and fragment.confidence_score >= min_confidence
+        ]
+
+    def list(self) -> List[HiveMemoryFragment]:
+        return list(self.fragments.values())
+
+    def delete(self, fragment_id: str) -> bool:
+        return self.fragments.pop(fragment_id, None) is not None
+
+class SQLiteBackend(MemoryBackend):
+    """Fragments in a SQLite file, kept across bridge restarts; content is stored as JSON"""
+
+    def __init__(self, path: str):
+        if path != ":memory:":
+            Path(path).parent.mkdir(parents=True, exist_ok=True)
+        self.connection = sqlite3.connect(path)
+        self.connection.execute(
+            """CREATE TABLE IF NOT EXISTS fragments (
+                fragment_id TEXT PRIMARY KEY,
+                memory_type TEXT NOT NULL,
+                content TEXT NOT NULL,
+                contributors TEXT NOT NULL,
+                confidence_score REAL NOT NULL,
+                access_count INTEGER NOT NULL,
+                last_accessed REAL NOT NULL,
+                relevance_decay REAL NOT NULL
+            )"""
+        )
+        self.connection.commit()
+
+    def store(self, fragment: HiveMemoryFragment) -> None:
+        self.connection.execute(
+            "INSERT OR REPLACE INTO fragments VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            (
+                fragment.fragment_id,
+                fragment.memory_type.value,
+                # Anything that isn't JSON data is kept as its string form
+                json.dumps(fragment.content, default=str),
+                json.dumps(sorted(fragment.contributors)),
+                fragment.confidence_score,
+                fragment.access_count,
+                fragment.last_accessed,
+                fragment.relevance_decay,
+            )
+        )
+        self.connection.commit()
+
+    def recall(self, memory_type=None, min_confidence=0.0) -> List[HiveMemoryFragment]:
+        query = "SELECT * FROM fragments WHERE confidence_score >= ?"
+        args: List[Any] = [min_confidence]
+        if memory_type is not None:
+            query += " AND memory_type = ?"
+            args.append(memory_type.value)
+        return [self._fragment(row) for row in self.connection.execute(query + " ORDER BY rowid", args)]
+
+    def list(self) -> List[HiveMemoryFragment]:
+        return [self._fragment(row) for row in self.connection.execute("SELECT * FROM fragments ORDER BY rowid")]
+
+    def delete(self, fragment_id: str) -> bool:
+        cursor = self.connection.execute("DELETE FROM fragments WHERE fragment_id = ?", (fragment_id,))
+        self.connection.commit()
+        return cursor.rowcount > 0
+
+    @staticmethod
+    def _fragment(row) -> HiveMemoryFragment:
+        fragment_id, memory_type, content, contributors, confidence, access_count, last_accessed, decay = row
+        return HiveMemoryFragment(
+            fragment_id=fragment_id,
+            memory_type=HiveMemoryType(memory_type),
+            content=json.loads(content),
+            contributors=set(json.loads(contributors)),
+            confidence_score=confidence,
+            access_count=access_count,
+            last_accessed=last_accessed,
+            relevance_decay=decay
+        )
+
+def anf_dir() -> Path:
+    """
+    The ANF directory the Rust side uses: ~/.anf when there is a writable home,
+    then <temp>/anf-<user>
+    """
+    candidates = []
+    if os.environ.get("HOME"):
+        candidates.append(Path(os.environ["HOME"]) / ".anf")
+    candidates.append(Path(tempfile.gettempdir()) / f"anf-{os.environ.get('USER') or 'user'}")
+    for directory in candidates:
+        try:
+            directory.mkdir(parents=True, exist_ok=True)
+        except OSError:
+            continue
+        if os.access(directory, os.W_OK):
+            return directory
+    return candidates[-1]
+
+def default_memory_path() -> str:
+    """<anf dir>/state/hive.db, matching where the Rust side keeps its state"""
+    return str(anf_dir() / "state" / "hive.db")
+
+def _hive_settings(config_path: Path) -> Dict[str, Any]:
+    """The `[hive]` table of config.toml; unreadable files are reported and skipped"""
+    if not config_path.is_file():
+        return {}
+    if tomllib is None:
+        logger.warning("Skipping %s: reading TOML needs Python 3.11+ or the tomli package", config_path)
+        return {}
+    try:
+        with open(config_path, "rb") as f:
+            return dict(tomllib.load(f).get("hive", {}))
+    except (OSError, ValueError, TypeError) as e:  # TOMLDecodeError is a ValueError
+        logger.warning("Skipping hive settings in %s: %s", config_path, e)
+        return {}
+
+def backend_from_config(config: Optional[Dict[str, Any]] = None) -> MemoryBackend:
+    """
+    The backend named by the `[hive]` settings: `memory_backend` is "sqlite" (the default)
+    or "memory", and `memory_path` is the SQLite file. Without `config`, they are read from
+    <anf dir>/config.toml, with ANF_HIVE_MEMORY_BACKEND / ANF_HIVE_MEMORY_PATH taking precedence.
+    """
+    if config is None:
+        config = _hive_settings(anf_dir() / "config.toml")
+        for key in ("memory_backend", "memory_path"):
+            value = os.environ.get(f"ANF_HIVE_{key.upper()}")
+            if value:
+                config[key] = value
+
+    kind = config.get("memory_backend", "sqlite")
+    if kind == "memory":
+        return InMemoryBackend()
+    if kind == "sqlite":
+        return SQLiteBackend(config.get("memory_path") or default_memory_path())
+    raise ValueError(f"Unknown hive memory backend '{kind}' (expected sqlite or memory)")
+
+class HiveMemory:
+    """Collective memory over any backend; recall ranking lives here, not in the stores"""
+
+    # Recall drops fragments whose word overlap with the query is at or below this
+    relevance_threshold = 0.3
+
+    def __init__(self, backend: MemoryBackend):
+        self.backend = backend
+
+    def store(self, fragment: HiveMemoryFragment) -> None:
+        self.backend.store(fragment)
+
+    def recall(
+        self,
+        query: str,
+        memory_type: Optional[HiveMemoryType] = None,
+        min_confidence: float = 0.5,
+        limit: int = 10
+    ) -> List[HiveMemoryFragment]:
+        """The `limit` relevant fragments with the highest decayed confidence; recalling counts as an access"""
+        relevant = [
+            fragment for fragment in self.backend.recall(memory_type, min_confidence)
+            if self.relevance(query, fragment) > self.relevance_threshold
+        ]
+        for fragment in relevant:
+            fragment.access_count += 1
+            fragment.last_accessed = time.time()
+            self.backend.store(fragment)
+
+        relevant.sort(key=lambda f: f.confidence_score * f.relevance_decay, reverse=True)
+        return relevant[:limit]
+
+    def list(self) -> List[HiveMemoryFragment]:
+        return self.backend.list()
+
+    def delete(self, fragment_id: str) -> bool:
+        return self.backend.delete(fragment_id)
+
+    @staticmethod
+    def relevance(query: str, fragment: HiveMemoryFragment) -> float:
+        """Jaccard overlap between the query's words and the content's"""
+        query_words = set(query.lower().split())
+        content_words = set(str(fragment.content).lower().split())
+
+        if not query_words or not content_words:
+            return 0.0
+
+        intersection = query_words.intersection(content_words)
+        return len(intersection) / len(query_words.union(content_words))
@@ -15,6 +15,7 @@ from prometheus_client import Counter, Histogram, Gauge
 
 from .swarm_manager import SwarmManager, SwarmTopology, SwarmState
 from .hive_intelligence import HiveIntelligence, HiveDecisionMethod, HiveMemoryType
+from .hive_memory import MemoryBackend
 from ..agents.agent_manager import AgentManager
 
 class CoordinationMode(Enum):
@@ -44,14 +45,14 @@ class SwarmHiveCoordinator:
     hybrid_decisions = Counter('hybrid_decisions_total', 'Hybrid mode decisions', ['primary_mode'])
     coordination_efficiency = Histogram('coordination_efficiency_score', 'Coordination efficiency')
     
-    def __init__(self, agent_manager: Optional[AgentManager] = None):
-        """Initialize the swarm-hive coordinator"""
+    def __init__(self, agent_manager: Optional[AgentManager] = None, memory_backend: Optional[MemoryBackend] = None):
+        """Initialize the swarm-hive coordinator; hive memory goes to the configured backend unless one is given"""
         self.logger = structlog.get_logger(__name__)
         
         # Core components
         self.agent_manager = agent_manager or AgentManager()
         self.swarm_manager = SwarmManager(self.agent_manager)
-        self.hive_intelligence = HiveIntelligence(self.swarm_manager)
+        self.hive_intelligence = HiveIntelligence(self.swarm_manager, memory_backend)
         
         # Coordination state
         self.active_coordinations: Dict[str, CoordinationTask] = {}
@@ -48,6 +48,15 @@ class SwarmState:
     consensus_threshold: float = 0.75
     health_score: float = 1.0
     created_at: float = field(default_factory=time.time)
+    running_tasks: int = 0
+    completed_tasks: int = 0
+    failed_tasks: int = 0
+    # Tasks coordinated at once; the rest wait in task_queue
+    max_running: int = 4
+    slots: asyncio.Semaphore = field(init=False, repr=False)
+
+    def __post_init__(self):
+        self.slots = asyncio.Semaphore(self.max_running)
 
 class SwarmManager:
     """
@@ -176,17 +185,29 @@ class SwarmManager:
         
         swarm = self.active_swarms[swarm_id]
         
-        # Add task to swarm queue
+        # Queued until one of the swarm's coordination slots is free
         task["task_id"] = f"{swarm_id}_{int(time.time())}"
         swarm.task_queue.append(task)
+        async with swarm.slots:
+            swarm.task_queue.remove(task)
+            return await self._run_swarm_task(swarm, task)
+    
+    async def _run_swarm_task(self, swarm: SwarmState, task: Dict[str, Any]) -> Dict[str, Any]:
+        """Run a dequeued task through the swarm's coordination pattern, tallying the outcome"""
         
         start_time = time.time()
+        swarm.running_tasks += 1
         
         try:
             # Use appropriate coordination pattern
             coordination_func = self.coordination_patterns[swarm.topology]
             result = await coordination_func(swarm, task)
             
+            if "error" in result:
+                swarm.failed_tasks += 1
+            else:
+                swarm.completed_tasks += 1
+            
             # Record coordination time
             coordination_duration = time.time() - start_time
             self.consensus_time.observe(coordination_duration)
@@ -206,9 +227,13 @@ class SwarmManager:
             return result
             
         except Exception as e:
-            self.logger.error("swarm_coordination_failed", swarm_id=swarm_id, error=str(e))
+            swarm.failed_tasks += 1
+            self.logger.error("swarm_coordination_failed", swarm_id=swarm.swarm_id, error=str(e))
             self.collective_decisions.labels(outcome="failed").inc()
             return {"error": f"Swarm coordination failed: {e}"}
+        
+        finally:
+            swarm.running_tasks -= 1
     
     async def _hierarchical_coordination(self, swarm: SwarmState, task: Dict[str, Any]) -> Dict[str, Any]:
         """Queen-led hierarchical coordination pattern"""
@@ -574,6 +599,9 @@ class SwarmManager:
                 for agent in swarm.active_agents.values()
             ],
             "task_queue_size": len(swarm.task_queue),
+            "running_tasks": swarm.running_tasks,
+            "completed_tasks": swarm.completed_tasks,
+            "failed_tasks": swarm.failed_tasks,
             "health_score": swarm.health_score,
             "uptime": time.time() - swarm.created_at,
             "memory_size": len(swarm.collective_memory)
@@ -15,6 +15,9 @@ import structlog
 from .agents.agent_manager import AgentManager
 from .coordination.swarm_hive_coordinator import SwarmHiveCoordinator, CoordinationTask, CoordinationMode
 
+# Frames stay under 16MiB, so a length prefix always starts with a zero byte
+MAX_FRAME_BYTES = (1 << 24) - 1
+
 class DaemonBridge:
     """
     Bridge between Rust daemon and Python agent coordination system
@@ -63,23 +66,19 @@ class DaemonBridge:
         try:
             while not reader.at_eof():
                 # Read command from client
-                data = await reader.readline()
+                data, framed = await self.read_message(reader)
                 if not data:
                     break
                 
                 try:
                     command = json.loads(data.decode().strip())
                     response = await self.process_command(command)
-                    
-                    # Send response back
-                    response_data = json.dumps(response) + "\n"
-                    writer.write(response_data.encode())
-                    await writer.drain()
-                    
                 except json.JSONDecodeError:
-                    error_response = {"error": "Invalid JSON command"}
-                    writer.write(json.dumps(error_response).encode() + b"\n")
-                    await writer.drain()
+                    response = {"error": "Invalid JSON command"}
+                
+                # Send response back in the framing the command came in
+                writer.write(self.encode_message(json.dumps(response).encode(), framed))
+                await writer.drain()
                     
         except Exception as e:
             self.logger.error("client_handler_error", error=str(e))
@@ -87,6 +86,25 @@ class DaemonBridge:
             writer.close()
             await writer.wait_closed()
     
+    @staticmethod
+    async def read_message(reader: asyncio.StreamReader) -> tuple:
+        """Read one command: a 4-byte big-endian length and body, or a newline-terminated line"""
+        first = await reader.read(1)
+        if not first:
+            return b"", False
+        if first == b"\x00":
+            length = int.from_bytes(first + await reader.readexactly(3), "big")
+            if 0 < length <= MAX_FRAME_BYTES:
+                return await reader.readexactly(length), True
+            raise ValueError(f"Frame length {length} out of range")
+        return first + await reader.readline(), False
+    
+    @staticmethod
+    def encode_message(body: bytes, framed: bool) -> bytes:
+        if framed:
+            return len(body).to_bytes(4, "big") + body
+        return body + b"\n"
+    
     async def process_command(self, command: Dict[str, Any]) -> Dict[str, Any]:
         """Process commands from the Rust daemon"""
         
@@ -160,7 +160,7 @@ port = 8765
 log_level = "info"
 max_agents = 50
 auto_start = true
-socket_path = "/tmp/anf.sock"
+# socket_path = "/tmp/anf.sock"
 
 [interface]
 theme = "hacker"
@@ -16,7 +16,7 @@ NC='\033[0m' # No Color
 SCRIPT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")" && pwd)"
 PROJECT_ROOT="$(dirname "$SCRIPT_DIR")"
 PYTHON_SOCKET="/tmp/anf_python.sock"
-DAEMON_SOCKET="/tmp/anf.sock"
+DAEMON_SOCKET="${ANF_SOCKET:-${XDG_RUNTIME_DIR:-/tmp}/anf.sock}"
 LOG_DIR="$PROJECT_ROOT/logs"
 PID_DIR="$PROJECT_ROOT/.pids"
 
@@ -0,0 +1,57 @@
+// AgentNativeFramework Daemon - Background agent coordination service
+// The pool, protocol and socket server live in `anf::daemon`; this only wires them to the config
+
+use std::path::PathBuf;
+use std::time::Duration;
+use clap::Parser;
+use tracing::{info, warn};
+use anf::config::Config;
+use anf::daemon::{AgentDaemon, AgentPool, ConnectionLimits, LogControl, LOG_CONTROL};
+use anf::logfile::LogFile;
+use anf::wal::CommandLog;
+
+#[derive(Parser)]
+#[command(name = "anfd", about = "Agent Native Framework daemon")]
+struct Args {
+    /// Accept daemon.tcp_listen in plaintext when no TLS certificate is configured
+    #[arg(long)]
+    insecure: bool,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    // Artifacts, logs and the command log all live there: refuse to start without one
+    anf::paths::try_anf_dir()?;
+    let resolved = Config::load(&[])?;
+    let config = resolved.config.clone();
+    let log_path = config.daemon.log_file.as_ref().map_or_else(anf::logfile::default_path, PathBuf::from);
+    let log_file = LogFile::open(&log_path);
+    let _ = LOG_CONTROL.set(LogControl::install(&config.daemon.log_level, log_file.as_ref().ok().cloned()));
+    if let Err(e) = log_file {
+        warn!("Logging to stderr only, could not open {}: {}", log_path.display(), e);
+    }
+    
+    let pool = AgentPool::configured(&resolved);
+    let pool = match CommandLog::open(CommandLog::default_path()) {
+        Ok(log) => pool.with_command_log(log),
+        Err(e) => {
+            warn!("Running without a command log, state will not survive a crash: {}", e);
+            pool
+        },
+    };
+    let mut daemon = AgentDaemon::new(config.daemon.socket_path.clone())
+        .with_pool(pool)
+        .with_bridge_socket(config.daemon.bridge_socket_path.clone())
+        .with_limits(ConnectionLimits::from_config(&config))
+        .with_checkpoint_interval(Duration::from_secs(config.daemon.checkpoint_secs.max(1)))
+        .with_grace_period(Duration::from_secs(config.daemon.shutdown_grace_secs));
+    if let Some(addr) = &config.daemon.tcp_listen {
+        daemon = daemon.with_tcp(addr.clone(), anf::transport::listen_security(&config.daemon, args.insecure)?);
+    }
+    
+    info!("🤖 Agent Native Framework Daemon starting...");
+    daemon.start().await?;
+    
+    Ok(())
+}
@@ -0,0 +1,257 @@
+// ANF Response Cache - Agent answers reused when the same request comes back
+// Keyed by everything the executor sees besides the task's identity; held in the
+// daemon's memory only, within entry and byte caps, least recently used out first.
+// Off unless `agents.response_cache` is set.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::daemon::{AgentTask, TaskOutput};
+
+/// What an answer depends on: two tasks with the same key get the same answer
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub agent_id: String,
+    pub model: Option<String>,
+    pub task_type: String,
+    /// The task's context object as JSON (context files, system prompt, ...)
+    pub context: String,
+    pub prompt: String,
+}
+
+impl CacheKey {
+    /// The key for `task` when `agent_id` runs it on `model`
+    pub fn for_task(task: &AgentTask, agent_id: &str, model: Option<&str>) -> Self {
+        Self {
+            agent_id: agent_id.to_string(),
+            model: model.map(str::to_string),
+            task_type: task.task_type.clone(),
+            context: task.context.to_string(),
+            prompt: task.prompt.clone(),
+        }
+    }
+
+    /// `<agent>/<hash of the rest>`, short enough to list; lookups compare the whole key
+    pub fn id(&self) -> String {
+        let mut hasher = DefaultHasher::new();
+        (&self.model, &self.task_type, &self.context, &self.prompt).hash(&mut hasher);
+        format!("{}/{:016x}", self.agent_id, hasher.finish())
+    }
+
+    fn len(&self) -> usize {
+        self.agent_id.len() + self.model.as_ref().map_or(0, String::len) + self.task_type.len() + self.context.len() + self.prompt.len()
+    }
+}
+
+/// Bounds on what the cache holds; past either, least recently used entries go
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheLimits {
+    pub max_entries: usize,
+    pub max_bytes: usize,
+}
+
+impl Default for CacheLimits {
+    fn default() -> Self {
+        Self { max_entries: 1000, max_bytes: 64 * 1024 * 1024 }
+    }
+}
+
+/// One cached answer as `cache_list` shows it
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheEntry {
+    pub key: String,
+    pub agent_id: String,
+    pub stored_at: DateTime<Utc>,
+    /// Bytes held: the output text and the request it answers
+    pub size: usize,
+    #[serde(skip)]
+    output: TaskOutput,
+    /// Tick of the last insert or hit, for eviction
+    #[serde(skip)]
+    used: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub bytes: usize,
+    pub hits: u64,
+    pub misses: u64,
+    /// Entries dropped to stay within the limits
+    pub evictions: u64,
+    /// Share of lookups answered from the cache; `None` before the first lookup
+    pub hit_ratio: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+pub struct ResponseCache {
+    limits: CacheLimits,
+    entries: Mutex<HashMap<CacheKey, CacheEntry>>,
+    ticks: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl ResponseCache {
+    pub fn new(limits: CacheLimits) -> Self {
+        Self { limits, ..Self::default() }
+    }
+
+    /// The stored answer for `key`, counted as a hit or a miss
+    pub fn get(&self, key: &CacheKey) -> Option<TaskOutput> {
+        let output = self.lock().get_mut(key).map(|entry| {
+            entry.used = self.tick();
+            entry.output.clone()
+        });
+        let counter = if output.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        output
+    }
+
+    pub fn insert(&self, key: CacheKey, output: TaskOutput) {
+        self.insert_at(key, output, Utc::now());
+    }
+
+    fn insert_at(&self, key: CacheKey, output: TaskOutput, stored_at: DateTime<Utc>) {
+        let size = output.text.len() + key.len();
+        // Never cached, rather than evicting everything else to make room
+        if size > self.limits.max_bytes || self.limits.max_entries == 0 {
+            return;
+        }
+        let entry = CacheEntry { key: key.id(), agent_id: key.agent_id.clone(), stored_at, size, output, used: self.tick() };
+        let mut entries = self.lock();
+        entries.insert(key, entry);
+        let mut bytes: usize = entries.values().map(|entry| entry.size).sum();
+        while entries.len() > self.limits.max_entries || bytes > self.limits.max_bytes {
+            let Some(oldest) = entries.iter().min_by_key(|(_, entry)| entry.used).map(|(key, _)| key.clone()) else { break };
+            bytes -= entries.remove(&oldest).map_or(0, |entry| entry.size);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Every entry, oldest first
+    pub fn list(&self) -> Vec<CacheEntry> {
+        let mut entries: Vec<CacheEntry> = self.lock().values().cloned().collect();
+        entries.sort_by(|a, b| a.stored_at.cmp(&b.stored_at).then_with(|| a.key.cmp(&b.key)));
+        entries
+    }
+
+    /// Drop the entries for `agent_id` (any agent when None) stored at least
+    /// `older_than` ago (any age when None); returns how many went
+    pub fn clear(&self, agent_id: Option<&str>, older_than: Option<Duration>) -> usize {
+        self.clear_at(agent_id, older_than, Utc::now())
+    }
+
+    fn clear_at(&self, agent_id: Option<&str>, older_than: Option<Duration>, now: DateTime<Utc>) -> usize {
+        // An age too long to represent is older than anything stored
+        let cutoff = older_than.map(|age| chrono::Duration::from_std(age).ok()
+            .and_then(|age| now.checked_sub_signed(age))
+            .unwrap_or(DateTime::<Utc>::MIN_UTC));
+        let mut entries = self.lock();
+        let before = entries.len();
+        entries.retain(|_, entry| {
+            let matches = agent_id.is_none_or(|agent| entry.agent_id == agent)
+                && cutoff.is_none_or(|cutoff| entry.stored_at <= cutoff);
+            !matches
+        });
+        before - entries.len()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.lock();
+        let (hits, misses) = (self.hits.load(Ordering::Relaxed), self.misses.load(Ordering::Relaxed));
+        CacheStats {
+            entries: entries.len(),
+            bytes: entries.values().map(|entry| entry.size).sum(),
+            hits,
+            misses,
+            evictions: self.evictions.load(Ordering::Relaxed),
+            hit_ratio: (hits + misses > 0).then(|| hits as f64 / (hits + misses) as f64),
+        }
+    }
+
+    fn tick(&self) -> u64 {
+        self.ticks.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<CacheKey, CacheEntry>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(agent_id: &str, prompt: &str) -> CacheKey {
+        CacheKey::for_task(&AgentTask::new(agent_id, "exec", prompt), agent_id, None)
+    }
+
+    #[test]
+    fn test_clear_by_agent_and_age_is_exact() {
+        let cache = ResponseCache::default();
+        let now = Utc::now();
+        let hour = chrono::Duration::hours(1);
+        let answer = |text: &str| TaskOutput::from(text.to_string());
+        cache.insert_at(key("coder", "old"), answer("old answer"), now - hour * 3);
+        cache.insert_at(key("coder", "new"), answer("new"), now);
+        cache.insert_at(key("rust-pro", "old"), answer("rust"), now - hour * 3);
+
+        // Exactly three hours old counts as older than three hours
+        assert_eq!(cache.clear_at(Some("coder"), Some(Duration::from_secs(3 * 3600)), now), 1);
+        assert_eq!(cache.clear_at(Some("coder"), Some(Duration::from_secs(3600)), now), 0);
+        assert_eq!(cache.clear_at(None, Some(Duration::MAX), now), 0);
+        assert_eq!(cache.clear_at(None, Some(Duration::from_secs(i64::MAX as u64 / 1000)), now), 0);
+        let left: Vec<String> = cache.list().into_iter().map(|entry| entry.agent_id).collect();
+        assert_eq!(left, vec!["rust-pro", "coder"]);
+        assert_eq!(cache.stats().bytes, "rust".len() + key("rust-pro", "old").len() + "new".len() + key("coder", "new").len());
+    }
+
+    #[test]
+    fn test_key_covers_context_task_type_and_model() {
+        let cache = ResponseCache::default();
+        let mut first = AgentTask::new("coder", "exec", "review this");
+        first.context = serde_json::json!({"files": {"a.rs": "fn a() {}"}});
+        let mut second = first.clone();
+        second.context = serde_json::json!({"files": {"b.rs": "fn b() {}"}});
+        cache.insert(CacheKey::for_task(&first, "coder", None), TaskOutput::from("a.rs looks fine".to_string()));
+
+        assert!(cache.get(&CacheKey::for_task(&second, "coder", None)).is_none());
+        assert!(cache.get(&CacheKey::for_task(&first, "coder", Some("gpt-4o"))).is_none());
+        let mut other_type = first.clone();
+        other_type.task_type = "chat".to_string();
+        assert!(cache.get(&CacheKey::for_task(&other_type, "coder", None)).is_none());
+        assert_eq!(cache.get(&CacheKey::for_task(&first.clone(), "coder", None)).unwrap().text, "a.rs looks fine");
+    }
+
+    #[test]
+    fn test_least_recently_used_entries_go_past_the_limits() {
+        let cache = ResponseCache::new(CacheLimits { max_entries: 2, max_bytes: 1024 });
+        let answer = || TaskOutput::from("ok".to_string());
+        cache.insert(key("coder", "one"), answer());
+        cache.insert(key("coder", "two"), answer());
+        // A hit makes "one" the most recently used, so "two" goes first
+        assert!(cache.get(&key("coder", "one")).is_some());
+        cache.insert(key("coder", "three"), answer());
+        assert!(cache.get(&key("coder", "two")).is_none());
+        assert!(cache.get(&key("coder", "one")).is_some());
+        assert_eq!(cache.stats().evictions, 1);
+
+        let small = ResponseCache::new(CacheLimits { max_entries: 10, max_bytes: 2 * (key("coder", "one").len() + 2) });
+        for prompt in ["one", "two", "six"] {
+            small.insert(key("coder", prompt), answer());
+        }
+        assert_eq!((small.stats().entries, small.stats().evictions), (2, 1));
+        // Bigger than the whole cache: not stored, nothing else evicted
+        small.insert(key("coder", &"x".repeat(100)), answer());
+        assert_eq!((small.stats().entries, small.stats().evictions), (2, 1));
+    }
+}
@@ -0,0 +1,133 @@
+// ANF Capability Ontology - Synonyms and implications used when matching agents
+// Loaded from ~/.anf/capabilities.toml, falling back to a small built-in default
+
+use std::collections::{BTreeMap, BTreeSet};
+use serde::{Deserialize, Serialize};
+
+/// ```toml
+/// [synonyms]
+/// rs = "rust"
+///
+/// [implies]
+/// systems = ["performance"]
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Ontology {
+    /// Alias -> canonical capability name
+    pub synonyms: BTreeMap<String, String>,
+    /// An agent with the key capability also counts as having each listed one
+    pub implies: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for Ontology {
+    fn default() -> Self {
+        let synonyms = [
+            ("rs", "rust"),
+            ("ts", "typescript"),
+            ("js", "javascript"),
+            ("py", "python"),
+            ("perf", "performance"),
+            ("optimisation", "optimization"),
+            ("arch", "architecture"),
+        ];
+        let implies = [
+            ("systems", vec!["performance"]),
+            ("profiling", vec!["performance"]),
+            ("optimization", vec!["performance"]),
+        ];
+        Self {
+            synonyms: synonyms.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            implies: implies.into_iter()
+                .map(|(k, v)| (k.to_string(), v.into_iter().map(str::to_string).collect()))
+                .collect(),
+        }
+    }
+}
+
+impl Ontology {
+    pub fn path() -> std::path::PathBuf {
+        crate::paths::anf_path("capabilities.toml")
+    }
+
+    /// The user's ontology file, or the built-in default when there is none
+    pub fn load() -> anyhow::Result<Self> {
+        let path = Self::path();
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => Self::parse(&contents)
+                .map_err(|e| anyhow::anyhow!("Invalid capability ontology {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(anyhow::anyhow!("Failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn parse(contents: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(contents)?)
+    }
+
+    /// Lowercased canonical name
+    pub fn normalize(&self, capability: &str) -> String {
+        let capability = capability.trim().to_ascii_lowercase();
+        self.synonyms.get(&capability).map(|c| c.to_ascii_lowercase()).unwrap_or(capability)
+    }
+
+    /// Declared capabilities plus everything they imply, transitively. Each
+    /// capability is expanded once, so a cycle in `implies` ends.
+    pub fn expand(&self, capabilities: &[String]) -> BTreeSet<String> {
+        let mut expanded = BTreeSet::new();
+        let mut pending: Vec<String> = capabilities.iter().map(|c| self.normalize(c)).collect();
+        while let Some(capability) = pending.pop() {
+            if !expanded.insert(capability.clone()) {
+                continue;
+            }
+            if let Some(implied) = self.implies.get(&capability) {
+                pending.extend(implied.iter().map(|c| self.normalize(c)));
+            }
+        }
+        expanded
+    }
+
+    /// Whether an agent declaring `capabilities` satisfies `required`
+    pub fn provides(&self, capabilities: &[String], required: &str) -> bool {
+        self.expand(capabilities).contains(&self.normalize(required))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_implied_capability_matches() {
+        let ontology = Ontology::parse(r#"
+[synonyms]
+sys = "systems"
+
+[implies]
+rust = ["systems"]
+systems = ["low-level"]
+"#).unwrap();
+        let rust_only = vec!["Rust".to_string()];
+
+        assert!(ontology.provides(&rust_only, "systems"));
+        assert!(ontology.provides(&rust_only, "SYS"));
+        assert!(ontology.provides(&rust_only, "low-level"));
+        assert!(!ontology.provides(&rust_only, "performance"));
+
+        // The built-in default knows nothing about rust -> systems
+        assert!(!Ontology::default().provides(&rust_only, "systems"));
+        assert!(Ontology::default().provides(&["systems".to_string()], "perf"));
+    }
+
+    #[test]
+    fn test_cyclic_implications_terminate() {
+        let ontology = Ontology::parse(r#"
+[implies]
+a = ["b"]
+b = ["a", "c"]
+"#).unwrap();
+        let expanded = ontology.expand(&["a".to_string()]);
+        assert_eq!(expanded.into_iter().collect::<Vec<_>>(), vec!["a", "b", "c"]);
+        assert!(ontology.provides(&["b".to_string()], "a"));
+    }
+}
@@ -1,50 +1,201 @@
 // AgentNativeFramework CLI - Terminal interface for agent coordination
 // Jarvis-style command interface with keyboard shortcuts and rich output
 
-use std::collections::HashMap;
-use std::path::PathBuf;
-use clap::{Parser, Subcommand, Args};
+use std::collections::BTreeMap;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use clap::{Parser, Subcommand};
 use serde::{Deserialize, Serialize};
-use tokio::net::UnixStream;
 use crossterm::{
     execute,
-    style::{Color, Print, ResetColor, SetForegroundColor, Stylize},
-    cursor::{MoveTo, MoveToNextLine},
-    terminal::{Clear, ClearType, size},
+    style::{Color, Print, ResetColor, SetForegroundColor},
+    terminal::size,
 };
-use console::{Key, Term};
+use console::Term;
 use indicatif::{ProgressBar, ProgressStyle};
+use anf::config::{format_size, parse_duration, parse_size, Config, ConfigEntry, OutputEncoding, TimeoutPolicy};
+use anf::error::{AnfError, Reported};
+use anf::framing;
+use anf::glyphs::Glyphs;
+use anf::cursor::{Cursor, CursorStore};
+use anf::daemon::{AgentOverrides, AgentPool, EmbeddedDaemon};
+use anf::logfile::Follower;
+use anf::metrics::BenchReport;
+use anf::plan::{CostModel, Plan};
+use anf::redact::Redactor;
+use anf::session::{ActiveState, Session};
+use anf::transport::{self, DaemonStream, Endpoint};
+use anf::swarm::{aggregate, value_descriptions, AggregationStrategy, Aggregation, CollaborationMode, MemberResult, SwarmStatus, Topology};
+use anf::walk::{self, Skipped, WalkLimits};
+use anf::wave_integration::{SplitDirection, WaveIntegration};
+use anf::workflow::{StepConfirmation, StepEvent, StepState, Workflow};
 
 #[derive(Parser)]
 #[command(name = "anf")]
 #[command(about = "Agent Native Framework - Terminal-based agent coordination")]
 #[command(version = "1.0.0")]
+#[command(after_help = "Exit codes: 0 ok, 1 error, 2 usage, 3 daemon unreachable, 4 not found, 5 timeout, 6 rate limited")]
 pub struct Cli {
     #[command(subcommand)]
-    pub command: Commands,
+    pub command: Option<Commands>,
+    
+    /// Save spawned agents, active context and selected agent to ~/.anf/sessions/<name>.json
+    #[arg(long, global = true, value_name = "NAME")]
+    pub save_session: Option<String>,
+    
+    /// Re-spawn and re-select everything from a saved session before running the command
+    #[arg(long, global = true, value_name = "NAME")]
+    pub restore_session: Option<String>,
     
     #[arg(short, long, global = true)]
     pub verbose: bool,
     
+    /// One JSON object per command on stdout, errors included, and no decoration
     #[arg(short, long, global = true)]
     pub json: bool,
+    
+    /// Print only results, without status decoration
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+    
+    /// Stable tab-separated output for scripts (see `porcelain_rows` for the columns)
+    #[arg(long, global = true)]
+    pub porcelain: bool,
+    
+    /// One line per status instead of boxes and progress bars
+    #[arg(long, global = true)]
+    pub compact: bool,
+    
+    /// Daemon socket, or tls://host:port (tcp://host:port in plaintext); overrides
+    /// ANF_SOCKET and daemon.socket_path
+    #[arg(long, global = true, value_name = "PATH")]
+    pub socket: Option<PathBuf>,
+    
+    /// Run this one command on an agent pool inside the CLI instead of the daemon;
+    /// swarm, hive and streaming commands still need anfd
+    #[arg(long, global = true, conflicts_with = "socket")]
+    pub no_daemon: bool,
 }
 
+// Parsed once per run, so the size of `Ask` doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 pub enum Commands {
+    /// Create ~/.anf with a starter config, a sample agent and a sample workflow
+    Init {
+        /// Overwrite starter files that already exist
+        #[arg(long)]
+        force: bool,
+    },
+    
     /// Ask an agent a question
     Ask {
         /// The question to ask
-        prompt: String,
+        #[arg(required_unless_present = "prompt_file", conflicts_with = "prompt_file")]
+        prompt: Option<String>,
+        
+        /// Read the prompt from a file (`-` for stdin), newlines preserved
+        #[arg(long, value_name = "PATH")]
+        prompt_file: Option<PathBuf>,
         
         #[arg(short, long)]
         agent: Option<String>,
         
+        /// Ask several agents at once and show their answers together; exits
+        /// nonzero when any of them fails, after showing every answer
+        #[arg(long, value_delimiter = ',', conflicts_with_all = ["agent", "repeat_until"])]
+        agents: Vec<String>,
+        
+        /// How to lay out `--agents` answers
+        #[arg(long, value_enum, default_value_t = FanOutLayout::Stacked)]
+        layout: FanOutLayout,
+        
+        /// After `--agents` answers, print which agents answered and which failed
+        #[arg(long)]
+        summary: bool,
+        
+        /// Daemon connections shared by the `--agents` asks
+        #[arg(long, default_value_t = 4)]
+        max_concurrency: usize,
+        
         #[arg(short, long)]
         context: Option<PathBuf>,
         
+        /// Language of the context (detected from the file extension if omitted)
+        #[arg(long)]
+        context_lang: Option<String>,
+        
+        /// Files matching a pattern (`*`, `?`, `**`) as context; repeatable, needs --merge-context
+        #[arg(long, value_name = "PATTERN", requires = "merge_context")]
+        context_glob: Vec<String>,
+        
+        /// Read context from stdin, needs --merge-context
+        #[arg(long, requires = "merge_context")]
+        context_stdin: bool,
+        
+        /// Combine --context, --context-glob and --context-stdin in that order,
+        /// dropping repeated contents, within agents.max_context_bytes
+        #[arg(long)]
+        merge_context: bool,
+        
+        /// Directory levels a --context directory or `**` glob descends into
+        #[arg(long, default_value_t = WalkLimits::default().max_depth)]
+        max_depth: usize,
+        
+        /// Files a --context directory or glob may add before the rest are skipped
+        #[arg(long, default_value_t = WalkLimits::default().max_files)]
+        max_files: usize,
+        
+        /// Mask secrets matching redact.patterns in the context before sending it
+        #[arg(long)]
+        context_redact: bool,
+        
+        /// System instruction sent alongside the prompt; repeat to add more, in order
+        #[arg(long, visible_alias = "prompt-prefix", value_name = "TEXT")]
+        system: Vec<String>,
+        
         #[arg(long)]
         background: bool,
+        
+        /// Agents to retry with if the primary agent fails
+        #[arg(long, value_delimiter = ',')]
+        fallback: Vec<String>,
+        
+        /// Model to run on; must be one the agent lists (defaults to its first)
+        #[arg(long)]
+        model: Option<String>,
+        
+        /// Deadline for each attempt, e.g. 30s or 2m; --on-timeout decides what follows
+        #[arg(long, value_parser = parse_duration)]
+        timeout: Option<Duration>,
+        
+        /// What to do when an attempt times out (defaults to agents.on_timeout)
+        #[arg(long, value_enum)]
+        on_timeout: Option<TimeoutPolicy>,
+        
+        /// Re-run until the output matches: contains:<s>, regex:<r> or exit-zero
+        #[arg(long)]
+        repeat_until: Option<String>,
+        
+        #[arg(long, default_value_t = 5)]
+        max_iterations: usize,
+        
+        /// Append the previous output to the prompt on each repeat
+        #[arg(long)]
+        feed_back: bool,
+        
+        /// Print the response through a template: {agent} {result} {duration} {cost} {task_id}.
+        /// {cost} is the reply's token count priced at the planning rate, in USD
+        #[arg(long)]
+        output_template: Option<String>,
+        
+        /// Schedule this ask with the agent's priority, max_concurrent_tasks or
+        /// memory_limit replaced, e.g. priority=10; repeat for more fields
+        #[arg(long = "override", visible_alias = "agent-config-override", value_name = "KEY=VALUE")]
+        overrides: Vec<String>,
     },
     
     /// Spawn an agent
@@ -57,6 +208,10 @@ pub enum Commands {
         
         #[arg(long)]
         pipe_to: Option<String>,
+        
+        /// Memory limit for this spawn instead of the agent's configured one, e.g. 512MB or 2GB
+        #[arg(long, value_parser = parse_size)]
+        mem_limit: Option<u64>,
     },
     
     /// Run a workflow
@@ -69,6 +224,22 @@ pub enum Commands {
         
         #[arg(long)]
         save_as: Option<String>,
+        
+        /// Ask y/N/a before submitting each step
+        #[arg(long)]
+        step_confirm: bool,
+        
+        /// Approve every step without asking (required for --step-confirm without a TTY)
+        #[arg(long)]
+        yes: bool,
+        
+        /// Copy the artifacts the run produced into this directory when it finishes
+        #[arg(long)]
+        output_dir: Option<PathBuf>,
+        
+        /// Check agents, variables, dependencies and --output-dir, then stop without submitting
+        #[arg(long)]
+        dry_run: bool,
     },
     
     /// Agent management
@@ -93,6 +264,14 @@ pub enum Commands {
         
         #[arg(long)]
         workflows: bool,
+        
+        /// Follow the daemon's task and agent events until interrupted
+        #[arg(long)]
+        live: bool,
+        
+        /// With --live, how each event is printed
+        #[arg(long, value_enum, default_value_t, requires = "live")]
+        format: LineFormat,
     },
     
     /// Context management
@@ -101,12 +280,54 @@ pub enum Commands {
         action: ContextCommands,
     },
     
+    /// Configuration inspection
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommands,
+    },
+    
+    /// The daemon's cache of agent responses (`agents.response_cache`)
+    Cache {
+        #[command(subcommand)]
+        action: CacheCommands,
+    },
+    
+    /// Daemon runtime controls
+    Daemon {
+        #[command(subcommand)]
+        action: DaemonCommands,
+    },
+    
+    /// List tasks known to the daemon
+    Tasks,
+    
+    /// Look up a single task
+    Task {
+        #[command(subcommand)]
+        action: TaskCommands,
+    },
+    
     /// Quick shortcuts
     Quick,
     
+    /// Send protocol commands straight to the daemon
+    Raw {
+        /// One JSON command
+        #[arg(required_unless_present = "stdin_json", conflicts_with = "stdin_json")]
+        command: Option<String>,
+        
+        /// Relay NDJSON commands from stdin over one connection, replies as NDJSON on stdout
+        #[arg(long)]
+        stdin_json: bool,
+    },
+    
     /// Chat with an agent
     Chat {
         agent: String,
+        
+        /// Send each stdin line as a turn over one connection, printing replies as they come
+        #[arg(long)]
+        pipe: bool,
     },
     
     /// Swarm coordination commands
@@ -121,19 +342,42 @@ pub enum Commands {
         action: HiveCommands,
     },
     
+    /// Wave Terminal (or tmux) pane layout
+    Wave {
+        #[command(subcommand)]
+        action: WaveCommands,
+    },
+    
     /// Multi-agent collaboration
     Collaborate {
         /// Task description
-        task: String,
+        #[arg(required_unless_present = "list_modes")]
+        task: Option<String>,
         
         #[arg(long)]
         agents: Option<String>,
         
+        #[arg(long, value_enum)]
+        mode: Option<CollaborationMode>,
+        
+        #[arg(long, value_enum)]
+        topology: Option<Topology>,
+        
+        /// Print the valid --mode values and exit
+        #[arg(long)]
+        list_modes: bool,
+        
+        /// Deadline for the whole collaboration, in seconds
         #[arg(long)]
-        mode: Option<String>,
+        timeout: Option<u64>,
+        
+        /// Deadline for each member, in seconds; whichever limit fires first wins
+        #[arg(long)]
+        timeout_per_agent: Option<u64>,
         
+        /// Copy the artifacts the members produced into this directory when they finish
         #[arg(long)]
-        topology: Option<String>,
+        output_dir: Option<PathBuf>,
     },
 }
 
@@ -144,9 +388,11 @@ pub enum AgentCommands {
         #[arg(long)]
         category: Option<String>,
         
+        /// Only agents with a free task slot, below their max_concurrent_tasks
         #[arg(long)]
         available: bool,
         
+        /// Only agents that have been spawned
         #[arg(long)]
         active: bool,
     },
@@ -164,13 +410,117 @@ pub enum AgentCommands {
     
     /// Create custom agent
     Create {
-        name: String,
+        #[arg(required_unless_present = "config")]
+        name: Option<String>,
         
         #[arg(long)]
         base: Option<String>,
         
         #[arg(long)]
         capabilities: Vec<String>,
+        
+        /// A complete agent config as JSON, saved as ~/.anf/agents/<id>.json
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["name", "base", "capabilities"])]
+        config: Option<PathBuf>,
+    },
+    
+    /// Undo the last create or reload, restoring the registry and definition files
+    Undo,
+    
+    /// Find agents with a capability (synonyms and implied capabilities included)
+    Search {
+        capability: String,
+    },
+    
+    /// Check an agent definition file without installing it
+    Verify {
+        file: PathBuf,
+    },
+    
+    /// Rank agents for the capabilities a task needs
+    Rank {
+        capabilities: Vec<String>,
+        
+        /// Weight the ranking by strengths learned from past task outcomes
+        #[arg(long)]
+        use_learned: bool,
+    },
+    
+    /// Finished tasks, oldest first
+    History {
+        /// Only tasks run by this agent
+        #[arg(long)]
+        agent: Option<String>,
+        
+        /// Only tasks finished since the previous `--since-last` call, then move the cursor
+        #[arg(long)]
+        since_last: bool,
+        
+        /// Forget the cursor first, so `--since-last` starts from the beginning
+        #[arg(long)]
+        reset_cursor: bool,
+        
+        /// Cursor to use; separate scripts polling independently should pick different names
+        #[arg(long, default_value = "default")]
+        profile: String,
+        
+        #[arg(long, value_enum, default_value_t)]
+        format: LineFormat,
+    },
+    
+    /// Show a task, or with --explain-schedule why it hasn't started yet
+    Task {
+        task_id: String,
+        
+        #[arg(long)]
+        explain_schedule: bool,
+    },
+    
+    /// Show the lifecycle timeline of a task
+    Trace {
+        task_id: String,
+    },
+    
+    /// Poll a background task until it finishes; exits nonzero unless it completed
+    Wait {
+        task_id: String,
+        
+        #[arg(long, default_value = "1s", value_parser = parse_duration)]
+        interval: Duration,
+        
+        #[arg(long, default_value = "5m", value_parser = parse_duration)]
+        timeout: Duration,
+    },
+    
+    /// Queue one task per line of a file (`-` for stdin) without waiting for them.
+    /// Each line is a JSON object with `agent_id` and `prompt`, plus any `exec` options.
+    Batch {
+        file: PathBuf,
+        
+        /// Exit zero even when some lines were rejected
+        #[arg(long)]
+        keep_going: bool,
+    },
+    
+    /// List artifacts produced by a task
+    Artifacts {
+        task_id: String,
+    },
+    
+    /// Artifact retrieval
+    Artifact {
+        #[command(subcommand)]
+        action: ArtifactCommands,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ArtifactCommands {
+    /// Write an artifact's contents to stdout
+    Get {
+        task_id: String,
+        
+        name: String,
     },
 }
 
@@ -193,6 +543,95 @@ pub enum ContextCommands {
     List,
 }
 
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// Print the effective configuration and where each value came from
+    Show,
+}
+
+#[derive(Subcommand)]
+pub enum CacheCommands {
+    /// Cached responses, oldest first, with their age and size
+    List,
+    
+    /// Drop cached responses; all of them unless narrowed down
+    Clear {
+        /// Only this agent's responses
+        #[arg(long)]
+        agent: Option<String>,
+        
+        /// Only responses cached at least this long ago, such as 30m or 2h
+        #[arg(long, value_parser = parse_duration)]
+        older_than: Option<Duration>,
+    },
+    
+    /// Entries, size and how many lookups the cache answered
+    Stats,
+}
+
+#[derive(Subcommand)]
+pub enum DaemonCommands {
+    /// Show the daemon's log level, or change it without a restart
+    LogLevel {
+        /// trace, debug, info, warn, error or off
+        level: Option<String>,
+    },
+    
+    /// Rewrite the write-ahead log without finished tasks; safe while the daemon runs
+    Compact,
+    
+    /// Show the configuration the running daemon is using, secrets redacted
+    Config,
+    
+    /// Check that the daemon is up and answering
+    Ping,
+    
+    /// Print the end of the daemon's log file (daemon.log_file)
+    Logs {
+        /// Keep printing new lines, across log rotation, until Ctrl+C
+        #[arg(short, long)]
+        follow: bool,
+        
+        #[arg(short = 'n', long, default_value_t = 20)]
+        lines: usize,
+    },
+    
+    /// Time trivial tasks through the scheduler on a scratch pool inside the daemon
+    Bench {
+        #[arg(long, default_value_t = 1000)]
+        tasks: usize,
+        
+        /// Workers running tasks at once, still within each agent's max_concurrent_tasks
+        #[arg(long, default_value_t = 16)]
+        concurrency: usize,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TaskCommands {
+    /// Show a task's status, agent and timestamps; `--json` prints the whole task
+    Status {
+        task_id: String,
+    },
+    
+    /// Print a completed task's output; `--json` prints the output with its format and usage
+    Result {
+        task_id: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum WaveCommands {
+    /// Split the current pane and start an agent in the new one
+    Split {
+        agent: String,
+        
+        /// horizontal or vertical
+        #[arg(long, default_value = "vertical")]
+        direction: SplitDirection,
+    },
+}
+
 #[derive(Subcommand)]
 pub enum SwarmCommands {
     /// Create a new swarm
@@ -200,8 +639,8 @@ pub enum SwarmCommands {
         /// Swarm ID
         id: String,
         
-        #[arg(long)]
-        topology: Option<String>,
+        #[arg(long, value_enum)]
+        topology: Option<Topology>,
         
         #[arg(long)]
         agents: Vec<String>,
@@ -210,6 +649,9 @@ pub enum SwarmCommands {
         task: Option<String>,
     },
     
+    /// Print the valid --topology values
+    Topologies,
+    
     /// List active swarms
     List {
         #[arg(long)]
@@ -226,6 +668,22 @@ pub enum SwarmCommands {
         
         #[arg(long)]
         timeout: Option<u64>,
+        
+        /// Deadline for each member, in seconds; whichever limit fires first wins
+        #[arg(long)]
+        timeout_per_agent: Option<u64>,
+        
+        /// Show the per-member tasks and estimates without submitting anything
+        #[arg(long)]
+        dry_run: bool,
+        
+        /// How to pick the result from the members' outputs
+        #[arg(long, value_enum, default_value_t)]
+        aggregate: AggregationStrategy,
+        
+        /// Show every member's output with its score and why the result won
+        #[arg(long)]
+        explain: bool,
     },
     
     /// Dissolve a swarm
@@ -317,21 +775,45 @@ pub struct AgentResponse {
     pub agent_id: String,
     pub status: String,
     pub message: String,
+    #[serde(default)]
     pub data: Option<serde_json::Value>,
 }
 
 pub struct TerminalUI {
     term: Term,
+    glyphs: Glyphs,
+    compact: bool,
+}
+
+impl Default for TerminalUI {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TerminalUI {
     pub fn new() -> Self {
         Self {
             term: Term::stdout(),
+            glyphs: Glyphs::new(OutputEncoding::Auto),
+            compact: false,
         }
     }
 
+    pub fn with_encoding(mut self, encoding: OutputEncoding) -> Self {
+        self.glyphs = Glyphs::new(encoding);
+        self
+    }
+
+    pub fn with_compact(mut self, compact: bool) -> Self {
+        self.compact = compact;
+        self
+    }
+
     pub async fn display_agent_status(&self, agent_id: &str, status: &str) -> anyhow::Result<()> {
+        if self.compact {
+            return self.print_compact(&self.compact_agent_line(agent_id, status, None));
+        }
         self.term.clear_screen()?;
         
         // Header
@@ -360,14 +842,70 @@ impl TerminalUI {
         Ok(())
     }
 
+    /// `load` is the `status` of an `agent_info` reply; numbers it doesn't carry are left out
+    fn compact_agent_line(&self, agent_id: &str, status: &str, load: Option<&serde_json::Value>) -> String {
+        let mut parts = vec![agent_id.to_string(), status.to_string()];
+        if let Some(load) = load {
+            if let (Some(running), Some(cap)) = (load["running_tasks"].as_u64(), load["max_concurrent_tasks"].as_u64()) {
+                parts.push(format!("tasks {}/{}", running, cap));
+            }
+            if let Some(queued) = load["queued_tasks"].as_u64() {
+                parts.push(format!("queue {}", queued));
+            }
+            if let Some(available) = load.get("available_memory") {
+                parts.push(available.as_u64().map_or_else(|| "mem no limit".to_string(), |bytes| format!("mem {} free", format_size(bytes))));
+            }
+            if load["throttled"] == true {
+                parts.push("throttled".to_string());
+            }
+        }
+        parts.join(" | ")
+    }
+
+    fn compact_swarm_line(&self, swarm: &SwarmStatus) -> String {
+        format!(
+            "{} ({}) | agents {} | running {} | queued {} | done {} | failed {} | efficiency {}",
+            swarm.id, swarm.topology, swarm.members.len(), swarm.running, swarm.queued, swarm.completed, swarm.failed, percent_or_na(swarm.efficiency)
+        )
+    }
+
+    fn compact_hive_line(&self, nodes: usize, decisions: usize, memory_fragments: usize, confidence: Option<f64>) -> String {
+        format!("hive | nodes {} | decisions {} | memory {} | confidence {}", nodes, decisions, memory_fragments, percent_or_na(confidence))
+    }
+
+    /// A compact status line, in the color its box would have had
+    fn print_compact(&self, line: &str) -> anyhow::Result<()> {
+        execute!(
+            &self.term,
+            SetForegroundColor(Color::Blue),
+            Print(self.glyphs.render(&format!("{}\n", line))),
+            ResetColor
+        )?;
+
+        Ok(())
+    }
+
+    /// `width` columns wide, or `MIN_WIDTH` in a narrower terminal; a title too
+    /// long for that is elided
+    fn header_line(&self, title: &str, width: usize) -> String {
+        let width = effective_width(width);
+        let title = elide(title, width - 5);
+        self.glyphs.render(&format!("┌─ {} {}\n", title, "─".repeat(width - title.chars().count() - 4)))
+    }
+
+    fn box_line(&self, content: &str, width: usize) -> String {
+        let width = effective_width(width);
+        let content = elide(content, width - 4);
+        let padding = " ".repeat(width - content.chars().count() - 4);
+        self.glyphs.render(&format!("│ {}{} │\n", content, padding))
+    }
+
     fn print_header(&self, title: &str) -> anyhow::Result<()> {
         let (width, _) = size()?;
-        let border = "─".repeat(width as usize);
-        
         execute!(
-            self.term,
+            &self.term,
             SetForegroundColor(Color::Cyan),
-            Print(format!("┌─ {} {}\n", title, "─".repeat((width as usize).saturating_sub(title.len() + 4)))),
+            Print(self.header_line(title, width as usize)),
             ResetColor
         )?;
         
@@ -376,12 +914,10 @@ impl TerminalUI {
 
     fn print_box(&self, content: &str) -> anyhow::Result<()> {
         let (width, _) = size()?;
-        let padding = " ".repeat((width as usize).saturating_sub(content.len() + 2));
-        
         execute!(
-            self.term,
+            &self.term,
             SetForegroundColor(Color::Blue),
-            Print(format!("│ {}{} │\n", content, padding)),
+            Print(self.box_line(content, width as usize)),
             ResetColor
         )?;
         
@@ -399,11 +935,11 @@ impl TerminalUI {
         );
         
         execute!(
-            self.term,
+            &self.term,
             SetForegroundColor(Color::Yellow),
-            Print("🔄 "),
+            Print(self.glyphs.render("🔄 ")),
             ResetColor,
-            Print(format!("{} - [{}] {}%\n", task, bar, percent))
+            Print(self.glyphs.render(&format!("{} - [{}] {}%\n", task, bar, percent)))
         )?;
         
         Ok(())
@@ -411,16 +947,16 @@ impl TerminalUI {
 
     fn print_section(&self, title: &str, items: Vec<&str>) -> anyhow::Result<()> {
         execute!(
-            self.term,
+            &self.term,
             SetForegroundColor(Color::Green),
-            Print(format!("{}\n", title)),
+            Print(self.glyphs.render(&format!("{}\n", title))),
             ResetColor
         )?;
         
         for item in items {
             execute!(
-                self.term,
-                Print(format!("{}\n", item))
+                &self.term,
+                Print(self.glyphs.render(&format!("{}\n", item)))
             )?;
         }
         
@@ -431,40 +967,40 @@ impl TerminalUI {
         let (width, _) = size()?;
         
         execute!(
-            self.term,
+            &self.term,
             SetForegroundColor(Color::DarkGrey),
-            Print(format!("└{}\n", "─".repeat(width as usize - 2))),
-            Print("[Enter] Continue │ [Ctrl+C] Interrupt │ [Ctrl+D] Background\n"),
+            Print(self.glyphs.render(&format!("└{}\n", "─".repeat(effective_width(width as usize) - 1)))),
+            Print(self.glyphs.render("[Enter] Continue │ [Ctrl+C] Interrupt │ [Ctrl+D] Background\n")),
             ResetColor
         )?;
         
         Ok(())
     }
 
-    pub async fn interactive_mode(&self, agent_id: Option<&str>) -> anyhow::Result<()> {
+    pub async fn interactive_mode(&self, client: &DaemonClient, agent_id: Option<&str>) -> anyhow::Result<()> {
         self.term.clear_screen()?;
         
         execute!(
-            self.term,
+            &self.term,
             SetForegroundColor(Color::Magenta),
-            Print("🤖 Agent Native Framework - Interactive Mode\n"),
+            Print(self.glyphs.render("🤖 Agent Native Framework - Interactive Mode\n")),
             ResetColor
         )?;
 
         if let Some(agent) = agent_id {
             execute!(
-                self.term,
+                &self.term,
                 SetForegroundColor(Color::Cyan),
-                Print(format!("Connected to: {}\n\n", agent)),
+                Print(self.glyphs.render(&format!("Connected to: {}\n\n", agent))),
                 ResetColor
             )?;
         }
 
         loop {
             execute!(
-                self.term,
+                &self.term,
                 SetForegroundColor(Color::Yellow),
-                Print("ANF> "),
+                Print(self.glyphs.render("ANF> ")),
                 ResetColor
             )?;
 
@@ -475,14 +1011,14 @@ impl TerminalUI {
             }
 
             // Process command
-            self.process_interactive_command(&input).await?;
+            self.process_interactive_command(client, &input).await?;
         }
 
         Ok(())
     }
 
-    async fn process_interactive_command(&self, input: &str) -> anyhow::Result<()> {
-        let parts: Vec<&str> = input.trim().split_whitespace().collect();
+    async fn process_interactive_command(&self, client: &DaemonClient, input: &str) -> anyhow::Result<()> {
+        let parts: Vec<&str> = input.split_whitespace().collect();
         
         if parts.is_empty() {
             return Ok(());
@@ -490,12 +1026,14 @@ impl TerminalUI {
 
         match parts[0] {
             "help" => self.show_help()?,
-            "list" => self.list_agents().await?,
+            "list" => {
+                self.list_agents(client, &AgentQuery::default()).await?;
+            },
             "spawn" => {
                 if parts.len() > 1 {
                     self.spawn_agent(parts[1]).await?;
                 } else {
-                    execute!(self.term, Print("Usage: spawn <agent_name>\n"))?;
+                    execute!(&self.term, Print(self.glyphs.render("Usage: spawn <agent_name>\n")))?;
                 }
             },
             "ask" => {
@@ -504,9 +1042,9 @@ impl TerminalUI {
             },
             _ => {
                 execute!(
-                    self.term,
+                    &self.term,
                     SetForegroundColor(Color::Red),
-                    Print(format!("Unknown command: {}\n", parts[0])),
+                    Print(self.glyphs.render(&format!("Unknown command: {}\n", parts[0]))),
                     ResetColor
                 )?;
             }
@@ -532,57 +1070,48 @@ Keyboard shortcuts:
 "#;
 
         execute!(
-            self.term,
+            &self.term,
             SetForegroundColor(Color::Green),
-            Print(help_text),
+            Print(self.glyphs.render(help_text)),
             ResetColor
         )?;
 
         Ok(())
     }
 
-    async fn list_agents(&self) -> anyhow::Result<()> {
-        // Connect to daemon and get agent list
-        execute!(
-            self.term,
-            SetForegroundColor(Color::Cyan),
-            Print("📋 Available Agents:\n\n"),
-            ResetColor
-        )?;
-
-        let agents = vec![
-            ("rust-pro", "Rust Expert", "development"),
-            ("backend-typescript-architect", "Backend TypeScript Architect", "development"), 
-            ("performance-optimizer", "Performance Optimizer", "optimization"),
-            ("security-auditor", "Security Auditor", "security"),
-        ];
-
-        for (id, name, category) in agents {
-            execute!(
-                self.term,
-                SetForegroundColor(Color::Yellow),
-                Print("🤖 "),
-                ResetColor,
-                Print(format!("{:<25} │ {:<35} │ {}\n", id, name, category))
-            )?;
+    /// The daemon's agents matching `query` as a table; returns how many were listed
+    pub async fn list_agents(&self, client: &DaemonClient, query: &AgentQuery) -> anyhow::Result<usize> {
+        let rows = client.fetch_agent_rows(query).await?;
+        if rows.is_empty() {
+            execute!(&self.term, Print(self.glyphs.render("No agents match\n")))?;
+        } else {
+            self.print_table(&["ID", "NAME", "TYPE", "PRIORITY"], &rows)?;
         }
+        Ok(rows.len())
+    }
 
+    /// `render_table` at the terminal's width
+    pub fn print_table(&self, headers: &[&str], rows: &[Vec<String>]) -> anyhow::Result<()> {
+        let width = size().map_or(120, |(width, _)| width as usize);
+        execute!(&self.term, Print(render_table(headers, rows, width, self.glyphs)))?;
         Ok(())
     }
 
     async fn spawn_agent(&self, agent_id: &str) -> anyhow::Result<()> {
         execute!(
-            self.term,
+            &self.term,
             SetForegroundColor(Color::Green),
-            Print(format!("🚀 Spawning agent: {}\n", agent_id)),
+            Print(self.glyphs.render(&format!("🚀 Spawning agent: {}\n", agent_id))),
             ResetColor
         )?;
 
         // Show progress
         let pb = ProgressBar::new(100);
-        pb.set_style(ProgressStyle::default_bar()
-            .template("{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos:>3}/{len:3} {msg}")?
-            .progress_chars("#>-"));
+        let style = bar_style_or_default(
+            "{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {pos:>3}/{len:3} {msg}",
+            |e| eprintln!("Warning: invalid progress template ({}); using the default bar", e),
+        ).progress_chars("#>-");
+        pb.set_style(if self.glyphs.is_ascii() { style.tick_chars("|/-\\ ") } else { style });
 
         for i in 0..=100 {
             pb.set_position(i);
@@ -596,72 +1125,75 @@ Keyboard shortcuts:
             tokio::time::sleep(tokio::time::Duration::from_millis(30)).await;
         }
 
-        pb.finish_with_message("Agent spawned successfully! 🎉");
+        pb.finish_with_message(self.glyphs.render("Agent spawned successfully! 🎉"));
 
         Ok(())
     }
 
     async fn ask_agent(&self, question: &str) -> anyhow::Result<()> {
         execute!(
-            self.term,
+            &self.term,
             SetForegroundColor(Color::Blue),
-            Print(format!("❓ Question: {}\n", question)),
+            Print(self.glyphs.render(&format!("❓ Question: {}\n", question))),
             ResetColor
         )?;
 
         // Simulate agent thinking
         execute!(
-            self.term,
+            &self.term,
             SetForegroundColor(Color::Yellow),
-            Print("🤔 Agent is thinking...\n"),
+            Print(self.glyphs.render("🤔 Agent is thinking...\n")),
             ResetColor
         )?;
 
         tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
 
         execute!(
-            self.term,
+            &self.term,
             SetForegroundColor(Color::Green),
-            Print("🤖 Agent: That's a great question! Based on my analysis...\n"),
-            Print("   • First, I'd recommend looking at the performance implications\n"),
-            Print("   • Second, consider the security aspects\n"),
-            Print("   • Finally, think about maintainability\n\n"),
+            Print(self.glyphs.render("🤖 Agent: That's a great question! Based on my analysis...\n")),
+            Print(self.glyphs.render("   • First, I'd recommend looking at the performance implications\n")),
+            Print(self.glyphs.render("   • Second, consider the security aspects\n")),
+            Print(self.glyphs.render("   • Finally, think about maintainability\n\n")),
             ResetColor
         )?;
 
         Ok(())
     }
     
-    pub async fn display_swarm_status(&self, swarm_id: &str, topology: &str, agents: usize) -> anyhow::Result<()> {
+    pub async fn display_swarm_status(&self, swarm: &SwarmStatus) -> anyhow::Result<()> {
+        if self.compact {
+            return self.print_compact(&self.compact_swarm_line(swarm));
+        }
         self.term.clear_screen()?;
         
         // Swarm header
-        self.print_header(&format!("Swarm: {} ({})", swarm_id, topology))?;
+        self.print_header(&format!("Swarm: {} ({})", swarm.id, swarm.topology))?;
         
         // Status box
+        let efficiency = swarm.efficiency
+            .map_or_else(|| "n/a".to_string(), |e| format!("{:.0}%", e * 100.0));
         self.print_box(&format!(
-            "Agents: {} │ Status: Active │ Tasks: 3 │ Efficiency: 87%",
-            agents
+            "Agents: {} │ Running: {} │ Queued: {} │ Done: {} │ Failed: {} │ Efficiency: {}",
+            swarm.members.len(), swarm.running, swarm.queued, swarm.completed, swarm.failed, efficiency
         ))?;
         
         // Coordination progress
         execute!(
-            self.term,
+            &self.term,
             SetForegroundColor(Color::Green),
-            Print("🐛 Swarm Coordination:\n"),
+            Print(self.glyphs.render("🐛 Swarm Coordination:\n")),
             ResetColor
         )?;
         
-        self.print_progress("Task distribution", 90)?;
-        self.print_progress("Result aggregation", 65)?;
-        self.print_progress("Consensus building", 45)?;
+        let total = swarm.total_tasks();
+        let finished = swarm.completed + swarm.failed;
+        let percent = (finished * 100).checked_div(total).unwrap_or(0) as u8;
+        self.print_progress("Tasks finished", percent)?;
         
         // Agent activity
-        self.print_section("Active Agents:", vec![
-            "🤖 rust-expert - Analyzing code patterns",
-            "🔒 security-auditor - Scanning vulnerabilities", 
-            "⚡ performance-optimizer - Benchmarking solutions",
-        ])?;
+        let members: Vec<String> = swarm.members.iter().map(|id| format!("🤖 {}", id)).collect();
+        self.print_section("Active Agents:", members.iter().map(|s| s.as_str()).collect())?;
         
         // Controls
         self.print_controls()?;
@@ -669,7 +1201,11 @@ Keyboard shortcuts:
         Ok(())
     }
     
-    pub async fn display_hive_status(&self, nodes: usize, decisions: usize, memory_fragments: usize) -> anyhow::Result<()> {
+    /// `confidence` is the bridge's `collective_confidence`, 0 to 1, when known
+    pub async fn display_hive_status(&self, nodes: usize, decisions: usize, memory_fragments: usize, confidence: Option<f64>) -> anyhow::Result<()> {
+        if self.compact {
+            return self.print_compact(&self.compact_hive_line(nodes, decisions, memory_fragments, confidence));
+        }
         self.term.clear_screen()?;
         
         // Hive header
@@ -677,15 +1213,15 @@ Keyboard shortcuts:
         
         // Status box
         self.print_box(&format!(
-            "Nodes: {} │ Decisions: {} │ Memory: {} │ Confidence: 89%",
-            nodes, decisions, memory_fragments
+            "Nodes: {} │ Decisions: {} │ Memory: {} │ Confidence: {}",
+            nodes, decisions, memory_fragments, percent_or_na(confidence)
         ))?;
         
         // Collective intelligence
         execute!(
-            self.term,
+            &self.term,
             SetForegroundColor(Color::Magenta),
-            Print("🧠 Collective Intelligence:\n"),
+            Print(self.glyphs.render("🧠 Collective Intelligence:\n")),
             ResetColor
         )?;
         
@@ -720,26 +1256,26 @@ Keyboard shortcuts:
         
         // Phase progress
         execute!(
-            self.term,
+            &self.term,
             SetForegroundColor(Color::Blue),
-            Print("🚀 Collaboration Phases:\n"),
+            Print(self.glyphs.render("🚀 Collaboration Phases:\n")),
             ResetColor
         )?;
         
         execute!(
-            self.term,
+            &self.term,
             SetForegroundColor(Color::Green),
-            Print("✓ "),
+            Print(self.glyphs.render("✓ ")),
             ResetColor,
-            Print("Phase 1: Hive Planning - Complete\n")
+            Print(self.glyphs.render("Phase 1: Hive Planning - Complete\n"))
         )?;
         
         self.print_progress("Phase 2: Swarm Execution", 67)?;
         
         execute!(
-            self.term,
+            &self.term,
             SetForegroundColor(Color::DarkGrey),
-            Print("⏳ Phase 3: Hive Validation - Pending\n"),
+            Print(self.glyphs.render("⏳ Phase 3: Hive Validation - Pending\n")),
             ResetColor
         )?;
         
@@ -763,124 +1299,2585 @@ Keyboard shortcuts:
     }
 }
 
+fn unreachable_daemon(socket: &str, error: std::io::Error) -> anyhow::Error {
+    let reason = match error.kind() {
+        std::io::ErrorKind::NotFound | std::io::ErrorKind::ConnectionRefused => {
+            format!("daemon not running ({}), start it with `anfd`", error)
+        },
+        _ => error.to_string(),
+    };
+    AnfError::DaemonUnreachable { socket: socket.to_string(), reason }.into()
+}
+
+/// Connect to the daemon at `address`, a socket path or a `tcp://`/`tls://` one
+async fn connect_daemon(address: &str, tls: Option<&Arc<rustls::ClientConfig>>) -> anyhow::Result<Box<dyn DaemonStream>> {
+    let endpoint = Endpoint::parse(address).map_err(|e| AnfError::Usage(e.to_string()))?;
+    transport::connect(&endpoint, tls).await.map_err(|e| unreachable_daemon(address, e))
+}
+
+/// What `ping` reports about a live daemon
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct DaemonPing {
+    pub uptime_secs: u64,
+    pub agents_loaded: usize,
+    pub queue_depth: usize,
+}
+
+#[derive(Clone)]
 pub struct DaemonClient {
     socket_path: String,
+    /// Verifies a `tls://` daemon, from daemon.tls_ca
+    tls: Option<Arc<rustls::ClientConfig>>,
+    pool: Option<Arc<ConnectionPool>>,
+    /// `--no-daemon`: commands are answered in-process and nothing is connected to
+    embedded: Option<Arc<EmbeddedDaemon>>,
 }
 
 impl DaemonClient {
     pub fn new(socket_path: String) -> Self {
-        Self { socket_path }
+        Self { socket_path, tls: None, pool: None, embedded: None }
     }
 
-    pub async fn connect(&self) -> anyhow::Result<UnixStream> {
-        let stream = UnixStream::connect(&self.socket_path).await?;
-        Ok(stream)
+    /// Trust the certificates in the PEM file `ca` for a `tls://` daemon address
+    pub fn with_tls_ca(mut self, ca: &Path) -> anyhow::Result<Self> {
+        self.tls = Some(transport::client_config(ca)?);
+        Ok(self)
     }
 
-    pub async fn send_command(&self, command: &str) -> anyhow::Result<String> {
-        let _stream = self.connect().await?;
-        // Implement command protocol
-        Ok(format!("Response to: {}", command))
+    /// A client for `daemon` that never touches a socket
+    pub fn embedded(daemon: EmbeddedDaemon) -> Self {
+        Self { socket_path: "(no daemon)".to_string(), tls: None, pool: None, embedded: Some(Arc::new(daemon)) }
     }
-}
 
-pub async fn run_cli(cli: Cli) -> anyhow::Result<()> {
-    let ui = TerminalUI::new();
-    let client = DaemonClient::new("/tmp/anf.sock".to_string());
+    /// Share at most `max_connections` connections between concurrent commands
+    /// instead of connecting once per command
+    pub fn with_pool(mut self, max_connections: usize) -> Self {
+        self.pool = Some(Arc::new(ConnectionPool::new(self.socket_path.clone(), max_connections).with_tls(self.tls.clone())));
+        self
+    }
+
+    pub async fn connect(&self) -> anyhow::Result<Box<dyn DaemonStream>> {
+        if self.embedded.is_some() {
+            return Err(AnfError::Usage("This command streams over the daemon socket; run it without --no-daemon".to_string()).into());
+        }
+        connect_daemon(&self.socket_path, self.tls.as_ref()).await
+    }
 
-    match cli.command {
-        Commands::Ask { prompt, agent, context: _, background: _ } => {
-            if let Some(agent_id) = agent {
-                ui.display_agent_status(&agent_id, "Processing").await?;
+    /// Send one length-prefixed command and wait for its reply. A pooled
+    /// connection that fails partway is discarded rather than reused.
+    pub async fn send_command(&self, command: &str) -> anyhow::Result<String> {
+        if let Some(daemon) = &self.embedded {
+            return Ok(daemon.dispatch(command).await.to_string());
+        }
+        if let Some(pool) = &self.pool {
+            let mut connection = pool.checkout().await?;
+            let reply = Self::exchange(connection.stream(), command).await;
+            if reply.is_err() {
+                connection.discard();
             }
-            
-            let response = client.send_command(&format!("ask:{}", prompt)).await?;
-            println!("🤖 {}", response);
-        },
+            return reply;
+        }
+        Self::exchange(&mut self.connect().await?, command).await
+    }
 
-        Commands::Spawn { agent, background: _, pipe_to: _ } => {
-            ui.spawn_agent(&agent).await?;
-        },
+    async fn exchange(stream: &mut Box<dyn DaemonStream>, command: &str) -> anyhow::Result<String> {
+        framing::write_frame(stream, command.as_bytes()).await?;
+        let reply = framing::read_frame(stream).await?
+            .ok_or_else(|| anyhow::anyhow!("Daemon closed the connection without replying"))?;
+        Ok(String::from_utf8_lossy(&reply).trim_end().to_string())
+    }
 
-        Commands::Interactive { agent } => {
-            ui.interactive_mode(agent.as_deref()).await?;
-        },
+    pub async fn ping(&self) -> anyhow::Result<DaemonPing> {
+        let command = serde_json::json!({"action": "ping", "params": {}});
+        let response: serde_json::Value = serde_json::from_str(&self.send_command(&command.to_string()).await?)
+            .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+        if let Some(error) = AnfError::from_response(&response) {
+            return Err(error.into());
+        }
+        serde_json::from_value(response).map_err(|e| anyhow::anyhow!("Unexpected ping response: {}", e))
+    }
+
+    /// Reserve an agent on the daemon; a refusal (unknown agent, no memory) is the error
+    pub async fn spawn_agent(&self, agent_id: &str, mem_limit: Option<u64>) -> anyhow::Result<AgentResponse> {
+        let command = serde_json::json!({"action": "spawn_agent", "params": {"agent_id": agent_id, "mem_limit": mem_limit}});
+        let response: serde_json::Value = serde_json::from_str(&self.send_command(&command.to_string()).await?)
+            .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+        if let Some(error) = AnfError::from_response(&response) {
+            return Err(error.into());
+        }
+        serde_json::from_value(response).map_err(|e| anyhow::anyhow!("Unexpected spawn response: {}", e))
+    }
+
+    /// The daemon's agent definitions matching `query`, sorted by id
+    pub async fn fetch_agents(&self, query: &AgentQuery) -> anyhow::Result<Vec<serde_json::Value>> {
+        let command = query.command();
+        let response: serde_json::Value = serde_json::from_str(&self.send_command(&command.to_string()).await?)
+            .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+        if let Some(error) = AnfError::from_response(&response) {
+            return Err(error.into());
+        }
+        let mut agents = response["agents"].as_array().cloned().unwrap_or_default();
+        agents.sort_by(|a, b| a["id"].as_str().cmp(&b["id"].as_str()));
+        Ok(agents)
+    }
+
+    /// `anf agents list` rows, id, name, type and priority, sorted by id
+    pub async fn fetch_agent_rows(&self, query: &AgentQuery) -> anyhow::Result<Vec<Vec<String>>> {
+        let text = |agent: &serde_json::Value, key: &str| match &agent[key] {
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        Ok(self.fetch_agents(query).await?.iter()
+            .map(|agent| vec![text(agent, "id"), text(agent, "name"), text(agent, "agent_type"), text(agent, "priority")])
+            .collect())
+    }
+
+    /// Every task the daemon knows, or only `agent`'s
+    pub async fn fetch_tasks(&self, agent: Option<&str>) -> anyhow::Result<Vec<serde_json::Value>> {
+        let command = serde_json::json!({"action": "list_tasks", "params": {}});
+        let response: serde_json::Value = serde_json::from_str(&self.send_command(&command.to_string()).await?)
+            .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+        if let Some(error) = AnfError::from_response(&response) {
+            return Err(error.into());
+        }
+        Ok(response.get("tasks").and_then(|v| v.as_array()).into_iter().flatten()
+            .filter(|task| agent.is_none_or(|agent| task["agent_id"] == agent))
+            .cloned()
+            .collect())
+    }
+
+    /// IDs of the agents loaded by the daemon
+    pub async fn fetch_agent_ids(&self) -> anyhow::Result<Vec<String>> {
+        let command = serde_json::json!({"action": "list_agents", "params": {}});
+        let response: serde_json::Value = serde_json::from_str(&self.send_command(&command.to_string()).await?)?;
+
+        let agents = response.get("agents")
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| anyhow::anyhow!("Daemon response has no agent list"))?;
+        Ok(agents.iter()
+            .filter_map(|agent| agent.get("id").and_then(|v| v.as_str()).map(str::to_string))
+            .collect())
+    }
+}
+
+/// A bounded set of daemon connections. Checking one out waits while all
+/// `max_connections` are in use; idle ones are health-checked before reuse and
+/// replaced when the daemon has closed them.
+pub struct ConnectionPool {
+    socket_path: String,
+    tls: Option<Arc<rustls::ClientConfig>>,
+    idle: std::sync::Mutex<Vec<Box<dyn DaemonStream>>>,
+    permits: Arc<tokio::sync::Semaphore>,
+    opened: AtomicUsize,
+}
+
+impl ConnectionPool {
+    pub fn new(socket_path: String, max_connections: usize) -> Self {
+        Self {
+            socket_path,
+            tls: None,
+            idle: std::sync::Mutex::new(Vec::new()),
+            permits: Arc::new(tokio::sync::Semaphore::new(max_connections.max(1))),
+            opened: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn with_tls(mut self, tls: Option<Arc<rustls::ClientConfig>>) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Connections opened so far, replacements included
+    pub fn opened(&self) -> usize {
+        self.opened.load(Ordering::Relaxed)
+    }
+
+    pub async fn checkout(self: &Arc<Self>) -> anyhow::Result<PooledConnection> {
+        let permit = self.permits.clone().acquire_owned().await?;
+        let reused = {
+            let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+            std::iter::from_fn(|| idle.pop()).find(|stream| stream.is_healthy())
+        };
+        let stream = match reused {
+            Some(stream) => stream,
+            None => {
+                let stream = connect_daemon(&self.socket_path, self.tls.as_ref()).await?;
+                self.opened.fetch_add(1, Ordering::Relaxed);
+                stream
+            },
+        };
+        Ok(PooledConnection { stream: Some(stream), pool: self.clone(), _permit: permit })
+    }
+}
+
+/// A checked-out connection; goes back to the pool when dropped unless discarded
+pub struct PooledConnection {
+    stream: Option<Box<dyn DaemonStream>>,
+    pool: Arc<ConnectionPool>,
+    _permit: tokio::sync::OwnedSemaphorePermit,
+}
+
+impl PooledConnection {
+    pub fn stream(&mut self) -> &mut Box<dyn DaemonStream> {
+        self.stream.as_mut().expect("stream is present until drop")
+    }
+
+    /// Close instead of returning to the pool, e.g. after a protocol error
+    pub fn discard(mut self) {
+        self.stream = None;
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let Some(stream) = self.stream.take() {
+            self.pool.idle.lock().unwrap_or_else(|e| e.into_inner()).push(stream);
+        }
+    }
+}
+
+/// Open a chat session with `agent` on `connection` and forward each line of
+/// `input` as one turn, printing each reply before sending the next line.
+/// Returns the number of turns once `input` hits EOF.
+pub async fn pipe_chat<I, C>(agent: &str, input: I, connection: C, out: &mut impl Write) -> anyhow::Result<usize>
+where
+    I: tokio::io::AsyncRead + Unpin,
+    C: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let (reader, mut writer) = tokio::io::split(connection);
+    let mut replies = BufReader::new(reader).lines();
+    let open = serde_json::json!({"action": "chat", "params": {"agent_id": agent}});
+    writer.write_all((open.to_string() + "\n").as_bytes()).await?;
+    let ack = replies.next_line().await?.ok_or_else(|| anyhow::anyhow!("Daemon closed the chat before accepting it"))?;
+    if let Some(error) = serde_json::from_str::<serde_json::Value>(&ack).ok().and_then(|ack| AnfError::from_response(&ack)) {
+        return Err(error.into());
+    }
+
+    let mut lines = BufReader::new(input).lines();
+    let mut turns = 0;
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        writer.write_all((line + "\n").as_bytes()).await?;
+        let reply = replies.next_line().await?.ok_or_else(|| anyhow::anyhow!("Daemon closed the chat mid-session"))?;
+        writeln!(out, "{}", IterationOutput::from_response(&reply).text)?;
+        out.flush()?;
+        turns += 1;
+    }
+    writer.shutdown().await?;
+    Ok(turns)
+}
+
+/// `anf raw --stdin-json`: open a batch on `connection` and relay each line of
+/// `input` in order. Every command gets an `id`, its own or else its line number,
+/// which its reply carries. A line that isn't a JSON object gets an error reply
+/// and never reaches the daemon. Returns the number of replies written.
+pub async fn pipe_raw<I, C>(input: I, connection: C, out: &mut impl Write) -> anyhow::Result<usize>
+where
+    I: tokio::io::AsyncRead + Unpin,
+    C: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let (reader, mut writer) = tokio::io::split(connection);
+    let mut replies = BufReader::new(reader).lines();
+    writer.write_all(b"batch\n").await?;
+    let ack = replies.next_line().await?.ok_or_else(|| anyhow::anyhow!("Daemon closed the batch before accepting it"))?;
+    if let Some(error) = serde_json::from_str::<serde_json::Value>(&ack).ok().and_then(|ack| AnfError::from_response(&ack)) {
+        return Err(error.into());
+    }
+
+    let mut lines = BufReader::new(input).lines();
+    let (mut line_number, mut written) = (0, 0);
+    while let Some(line) = lines.next_line().await? {
+        line_number += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str::<serde_json::Value>(&line) {
+            Ok(serde_json::Value::Object(mut command)) => {
+                let id = command.entry("id").or_insert_with(|| line_number.into()).clone();
+                writer.write_all((serde_json::to_string(&command)? + "\n").as_bytes()).await?;
+                let reply = replies.next_line().await?.ok_or_else(|| anyhow::anyhow!("Daemon closed the batch mid-stream"))?;
+                let mut reply: serde_json::Value = serde_json::from_str(&reply)
+                    .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+                reply["id"] = id;
+                reply
+            },
+            Ok(_) => serde_json::json!({"id": line_number, "error": format!("Line {} is not a JSON object", line_number)}),
+            Err(e) => serde_json::json!({"id": line_number, "error": format!("Invalid JSON on line {}: {}", line_number, e)}),
+        };
+        writeln!(out, "{}", reply)?;
+        out.flush()?;
+        written += 1;
+    }
+    writer.shutdown().await?;
+    Ok(written)
+}
+
+/// The list-type commands that support `--porcelain`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PorcelainList {
+    Agents,
+    Tasks,
+    Swarms,
+}
+
+impl PorcelainList {
+    /// Key of the array in the daemon response
+    fn key(self) -> &'static str {
+        match self {
+            Self::Agents => "agents",
+            Self::Tasks => "tasks",
+            Self::Swarms => "swarms",
+        }
+    }
+}
+
+/// `--porcelain` lines for a daemon list response. Columns, tab-separated:
+///
+/// - agents: `id`, `type`, `priority`
+/// - tasks: `id`, `agent`, `status`
+/// - swarms: `id`, `topology`, `member count`
+///
+/// The set and order of columns is stable across releases; new columns are only
+/// ever appended. Tabs and newlines inside values become spaces, missing values
+/// are empty, and nothing is coloured.
+pub fn porcelain_rows(list: PorcelainList, response: &serde_json::Value) -> Vec<String> {
+    fn field(item: &serde_json::Value, key: &str) -> String {
+        match item.get(key) {
+            Some(serde_json::Value::String(s)) => s.replace(['\t', '\n', '\r'], " "),
+            Some(serde_json::Value::Null) | None => String::new(),
+            Some(other) => other.to_string(),
+        }
+    }
+
+    response.get(list.key()).and_then(|v| v.as_array()).into_iter().flatten()
+        .map(|item| {
+            let columns = match list {
+                PorcelainList::Agents => vec![field(item, "id"), field(item, "agent_type"), field(item, "priority")],
+                PorcelainList::Tasks => vec![field(item, "id"), field(item, "agent_id"), field(item, "status")],
+                PorcelainList::Swarms => {
+                    vec![field(item, "id"), field(item, "topology"), swarm_member_count(item).to_string()]
+                },
+            };
+            columns.join("\t")
+        })
+        .collect()
+}
+
+/// A 0-to-1 ratio as a whole percentage, "n/a" when unknown
+fn percent_or_na(ratio: Option<f64>) -> String {
+    ratio.map_or_else(|| "n/a".to_string(), |ratio| format!("{:.0}%", ratio * 100.0))
+}
+
+/// A `swarm_list` entry's member count; the bridge sends it as a number, a
+/// swarm recorded by the daemon lists the members themselves
+fn swarm_member_count(swarm: &serde_json::Value) -> u64 {
+    let agents = swarm.get("agents");
+    agents.and_then(|v| v.as_u64())
+        .or_else(|| agents.and_then(|v| v.as_array()).map(|members| members.len() as u64))
+        .unwrap_or(0)
+}
+
+/// Where a finished task sits in history; unfinished tasks have no position yet
+fn task_position(task: &serde_json::Value) -> Option<Cursor> {
+    Some(Cursor {
+        at: serde_json::from_value(task.get("completed_at")?.clone()).ok()?,
+        id: serde_json::from_value(task.get("id")?.clone()).ok()?,
+    })
+}
+
+async fn print_porcelain(client: &DaemonClient, command: &serde_json::Value, list: PorcelainList) -> anyhow::Result<()> {
+    let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+        .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+    if let Some(error) = AnfError::from_response(&response) {
+        return Err(error.into());
+    }
+    for row in porcelain_rows(list, &response) {
+        println!("{}", row);
+    }
+    Ok(())
+}
+
+/// A file a task stored in the daemon's artifact store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProducedArtifact {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// The artifacts listed in an `exec` reply's output, or in each task of a `run_members` reply
+pub fn produced_artifacts(response: &serde_json::Value) -> Vec<ProducedArtifact> {
+    let outputs = std::iter::once(&response["output"])
+        .chain(response["tasks"].as_array().into_iter().flatten().map(|task| &task["output"]));
+    outputs
+        .flat_map(|output| output["artifacts"].as_array().into_iter().flatten())
+        .filter_map(|artifact| Some(ProducedArtifact {
+            name: artifact["name"].as_str()?.to_string(),
+            path: PathBuf::from(artifact["path"].as_str()?),
+        }))
+        .collect()
+}
+
+/// Copy `artifacts` into `dir` under their own names. A name already taken,
+/// in `dir` or earlier in this batch, gets a `-1`, `-2`, ... suffix before its
+/// extension. Only the last component of a name is used, so an artifact can't
+/// land outside `dir`. Returns where each one ended up.
+pub fn collect_artifacts(artifacts: &[ProducedArtifact], dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    anf::paths::ensure_dir(dir)?;
+    let mut collected = Vec::with_capacity(artifacts.len());
+    for artifact in artifacts {
+        let Some(file_name) = Path::new(&artifact.name).file_name() else {
+            anyhow::bail!("Refusing to collect artifact '{}': not a file name", artifact.name);
+        };
+        let name = Path::new(file_name);
+        let (stem, extension) = (
+            name.file_stem().map_or_else(|| file_name.to_string_lossy().into_owned(), |s| s.to_string_lossy().into_owned()),
+            name.extension().map(|e| e.to_string_lossy().into_owned()),
+        );
+        let mut target = dir.join(name);
+        for n in 1.. {
+            if !target.exists() {
+                break;
+            }
+            let suffixed = match &extension {
+                Some(extension) => format!("{}-{}.{}", stem, n, extension),
+                None => format!("{}-{}", stem, n),
+            };
+            target = dir.join(suffixed);
+        }
+        std::fs::copy(&artifact.path, &target)
+            .map_err(|e| anyhow::anyhow!("Failed to copy artifact {} from {}: {}", artifact.name, artifact.path.display(), e))?;
+        collected.push(target);
+    }
+    Ok(collected)
+}
+
+fn print_collected(artifacts: &[ProducedArtifact], dir: &Path) -> anyhow::Result<()> {
+    let collected = collect_artifacts(artifacts, dir)?;
+    println!("📦 Collected {} artifact(s) into {}", collected.len(), dir.display());
+    for path in collected {
+        println!("  {}", path.display());
+    }
+    Ok(())
+}
+
+/// What came back from running a set of members
+#[derive(Debug, Default)]
+pub struct MemberRun {
+    pub all_completed: bool,
+    pub artifacts: Vec<ProducedArtifact>,
+    pub results: Vec<MemberResult>,
+}
+
+/// The outputs of the members in a `run_members` reply that finished without error
+pub fn member_results(response: &serde_json::Value) -> Vec<MemberResult> {
+    response["tasks"].as_array().into_iter().flatten()
+        .filter(|task| task["error"].is_null())
+        .filter_map(|task| Some(MemberResult {
+            agent: task["agent_id"].as_str()?.to_string(),
+            output: task["output"]["text"].as_str()?.to_string(),
+            confidence: task["output"]["confidence"].as_f64(),
+        }))
+        .collect()
+}
+
+/// The result line, then with `explain` each candidate's score and the rationale
+pub fn aggregation_lines(aggregation: &Aggregation, explain: bool) -> Vec<String> {
+    let result = aggregation.result();
+    let mut lines = vec![format!("🏆 Result ({}) from {}:", aggregation.strategy, result.agent), result.output.clone()];
+    if explain {
+        lines.push("Candidates:".to_string());
+        for (i, candidate) in aggregation.candidates.iter().enumerate() {
+            let marker = if i == aggregation.winner { "→" } else { " " };
+            let first_line = candidate.output.lines().next().unwrap_or_default();
+            lines.push(format!("  {} {:<25} score {:<6} {}", marker, candidate.agent, candidate.score, elide(first_line, 60)));
+        }
+        lines.push(format!("Why: {}", aggregation.rationale));
+    }
+    lines
+}
+
+/// Run `prompt` on each member through the daemon. Returns whether every member
+/// completed, with their results and the artifacts they produced.
+async fn run_members(
+    client: &DaemonClient,
+    members: &[String],
+    prompt: &str,
+    timeout_secs: Option<u64>,
+    timeout_per_agent_secs: Option<u64>,
+) -> anyhow::Result<MemberRun> {
+    let command = serde_json::json!({
+        "action": "run_members",
+        "params": {
+            "agents": members,
+            "prompt": prompt,
+            "timeout_ms": timeout_secs.map(|s| s * 1000),
+            "timeout_per_agent_ms": timeout_per_agent_secs.map(|s| s * 1000)
+        }
+    });
+    let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+        .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+    if let Some(error) = AnfError::from_response(&response) {
+        return Err(error.into());
+    }
+
+    for task in response.get("tasks").and_then(|v| v.as_array()).into_iter().flatten() {
+        let agent = task.get("agent_id").and_then(|v| v.as_str()).unwrap_or("?");
+        match task.get("error").and_then(|v| v.as_str()) {
+            Some(error) => println!("  ❌ {}: {}", agent, error),
+            None => println!("  ✅ {}", agent),
+        }
+    }
+    Ok(MemberRun {
+        all_completed: response.get("success").and_then(|v| v.as_bool()).unwrap_or(false),
+        artifacts: produced_artifacts(&response),
+        results: member_results(&response),
+    })
+}
+
+async fn fetch_swarm_status(client: &DaemonClient, swarm_id: &str) -> anyhow::Result<SwarmStatus> {
+    let command = serde_json::json!({"action": "swarm_status", "params": {"swarm_id": swarm_id}});
+    let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+        .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+
+    if let Some(error) = AnfError::from_response(&response) {
+        return Err(error.into());
+    }
+    warn_if_stale(&response);
+    let status = response.get("status").cloned()
+        .ok_or_else(|| anyhow::anyhow!("Daemon returned no status for swarm {}", swarm_id))?;
+    Ok(serde_json::from_value(status)?)
+}
+
+/// A cached bridge reply says so on stderr, leaving stdout to the answer itself
+fn warn_if_stale(response: &serde_json::Value) {
+    if response["stale"] == true {
+        eprintln!(
+            "Warning: {}; showing what it reported at {}",
+            response["bridge_error"].as_str().unwrap_or("the Python bridge is unreachable"),
+            response["cached_at"].as_str().unwrap_or("an unknown time")
+        );
+    }
+}
+
+fn print_config_entries(title: &str, entries: &[ConfigEntry], json: bool) -> anyhow::Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(entries)?);
+        return Ok(());
+    }
+    println!("⚙️  {}:", title);
+    for entry in entries {
+        println!("  {:<24} = {:<30} ({})", entry.key, entry.value, entry.source);
+    }
+    Ok(())
+}
+
+/// Which agents `anf agents list` asks the daemon for
+#[derive(Debug, Clone, Default)]
+pub struct AgentQuery {
+    pub category: Option<String>,
+    pub available: bool,
+    pub active: bool,
+}
+
+impl AgentQuery {
+    fn command(&self) -> serde_json::Value {
+        serde_json::json!({"action": "list_agents", "params": {
+            "category": self.category, "available": self.available, "active": self.active
+        }})
+    }
+}
+
+/// Everything about an ask besides the prompt itself
+#[derive(Debug, Clone, Default)]
+pub struct AskOptions {
+    pub agent: Option<String>,
+    pub fallback: Vec<String>,
+    pub context: serde_json::Value,
+    pub model: Option<String>,
+    /// Per-attempt deadline, sent as `timeout_ms`
+    pub timeout: Option<Duration>,
+    pub on_timeout: Option<TimeoutPolicy>,
+    pub overrides: AgentOverrides,
+    /// How long an ask queued for its overrides waits for the answer when it
+    /// has no `timeout` of its own
+    pub queue_timeout: Duration,
+}
+
+/// The flags set on an ask that only reach an agent through `exec`. An ask
+/// with no agent is a bare `ask:` line, which would silently drop them.
+pub fn agent_only_flags(command: &Commands) -> Vec<&'static str> {
+    let Commands::Ask { context, context_lang, merge_context, context_redact, system, fallback, model, timeout, on_timeout, overrides, .. } = command else {
+        return Vec::new();
+    };
+    [
+        ("--context", context.is_some()),
+        ("--context-lang", context_lang.is_some()),
+        ("--merge-context", *merge_context),
+        ("--context-redact", *context_redact),
+        ("--system", !system.is_empty()),
+        ("--fallback", !fallback.is_empty()),
+        ("--model", model.is_some()),
+        ("--timeout", timeout.is_some()),
+        ("--on-timeout", on_timeout.is_some()),
+        ("--override", !overrides.is_empty()),
+    ]
+    .into_iter()
+    .filter_map(|(flag, set)| set.then_some(flag))
+    .collect()
+}
+
+/// Put the `--system` instructions in the context's `system` field, joined by a
+/// blank line; the context is left alone when there are none
+pub fn with_system(context: &mut serde_json::Value, system: &[String]) {
+    if system.is_empty() {
+        return;
+    }
+    if !context.is_object() {
+        *context = serde_json::json!({});
+    }
+    context["system"] = system.join("\n\n").into();
+}
+
+/// Mask secrets in the context's content; returns how many were masked
+pub fn redact_context(context: &mut serde_json::Value, redactor: &Redactor) -> usize {
+    let Some(content) = context.get_mut("content") else { return 0 };
+    let Some((redacted, count)) = content.as_str().map(|text| redactor.redact(text)) else { return 0 };
+    *content = redacted.into();
+    count
+}
+
+fn exec_command(agent_id: &str, prompt: &str, options: &AskOptions) -> serde_json::Value {
+    serde_json::json!({
+        "action": "exec",
+        "params": {
+            "agent_id": agent_id,
+            "prompt": prompt,
+            "fallback": options.fallback,
+            "context": options.context,
+            "model": options.model,
+            "timeout_ms": options.timeout.map(|timeout| timeout.as_millis().min(u64::MAX as u128) as u64),
+            "on_timeout": options.on_timeout,
+            "overrides": (!options.overrides.is_empty()).then_some(&options.overrides)
+        }
+    })
+}
+
+/// `exec` runs a task straight away, past the scheduler, so an ask with
+/// overrides is queued with `submit_task` and waited on instead, for its
+/// `timeout` or else `queue_timeout`; a task still unfinished by then is
+/// cancelled. The reply is shaped like an `exec` one.
+async fn queued_ask(client: &DaemonClient, agent_id: &str, prompt: &str, options: &AskOptions) -> anyhow::Result<String> {
+    let mut command = exec_command(agent_id, prompt, options);
+    command["action"] = "submit_task".into();
+    let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+        .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+    if let Some(error) = AnfError::from_response(&response) {
+        return Err(error.into());
+    }
+    let task_id = response["task_id"].as_str()
+        .ok_or_else(|| anyhow::anyhow!("Daemon reply has no task id"))?
+        .to_string();
+    let outcome = wait_for_task(Duration::from_millis(100), options.timeout.unwrap_or(options.queue_timeout), || {
+        let command = serde_json::json!({"action": "get_task", "params": {"task_id": task_id}});
+        async move {
+            serde_json::from_str(&client.send_command(&command.to_string()).await?)
+                .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))
+        }
+    }, |_| {}).await?;
+    match outcome {
+        WaitOutcome::Finished(task) => Ok(serde_json::json!({
+            "success": task["status"] == "Completed",
+            "task_id": task_id,
+            "status": task["status"],
+            "agent": task["fulfilled_by"],
+            "model": task["model"],
+            "output": task["output"],
+            "error": task["error"],
+        }).to_string()),
+        WaitOutcome::TimedOut(status) => {
+            // Nobody is left waiting for the answer; the timeout is still the error to report
+            let cancel = serde_json::json!({"action": "cancel_task", "params": {"task_id": task_id}});
+            let cancelled = match client.send_command(&cancel.to_string()).await {
+                Ok(reply) => match serde_json::from_str::<serde_json::Value>(&reply) {
+                    Ok(reply) => AnfError::from_response(&reply).map_or_else(|| "cancelled".to_string(), |e| format!("not cancelled: {}", e)),
+                    Err(e) => format!("not cancelled: {}", e),
+                },
+                Err(e) => format!("not cancelled: {}", e),
+            };
+            Err(AnfError::Timeout(format!("Timed out waiting for task {} (still {}, {})", task_id, status, cancelled)).into())
+        },
+    }
+}
+
+/// What `run_cli` prints results as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Tables, emoji and animations
+    Human,
+    /// One `json_report` object
+    Json,
+}
+
+impl OutputFormat {
+    pub fn of(cli: &Cli) -> Self {
+        if cli.json { OutputFormat::Json } else { OutputFormat::Human }
+    }
+}
+
+/// What a command prints under `--json`: its name and "ok" or "error" as
+/// `command` and `status`, plus `fields`
+pub fn json_report(command: &str, status: &str, fields: serde_json::Value) -> serde_json::Value {
+    let mut report = serde_json::Map::new();
+    report.insert("command".to_string(), command.into());
+    report.insert("status".to_string(), status.into());
+    if let serde_json::Value::Object(fields) = fields {
+        report.extend(fields);
+    }
+    serde_json::Value::Object(report)
+}
+
+/// `anf ask --json` for one daemon reply, which is either an `exec` result or
+/// a plain `ask:` answer
+pub fn ask_report(agent: Option<&str>, response: &str) -> serde_json::Value {
+    let reply: serde_json::Value = serde_json::from_str(response).unwrap_or_default();
+    let failed = reply.get("error").is_some_and(|error| !error.is_null()) || reply.get("success") == Some(&serde_json::Value::Bool(false));
+    json_report("ask", if failed { "error" } else { "ok" }, serde_json::json!({
+        "agent": reply.get("agent").filter(|agent| !agent.is_null()).cloned().unwrap_or_else(|| agent.into()),
+        "task_id": reply.get("task_id"),
+        "model": reply.get("model"),
+        "output": IterationOutput::from_response(response).text,
+        "tokens": reply.pointer("/output/tokens"),
+        "error": reply.get("error"),
+    }))
+}
+
+/// The failure in an `exec` or `ask:` reply: its `error`, or `success: false`
+/// without one. Replies that aren't JSON are plain answers.
+pub fn reply_error(response: &str) -> Option<AnfError> {
+    let reply: serde_json::Value = serde_json::from_str(response).ok()?;
+    if reply.get("error").is_some_and(|error| !error.is_null()) {
+        return AnfError::from_response(&reply);
+    }
+    (reply.get("success") == Some(&serde_json::Value::Bool(false))).then(|| match reply.get("status").and_then(|v| v.as_str()) {
+        Some(status) => AnfError::Daemon(format!("Task ended {}", status)),
+        None => AnfError::Daemon("The daemon reported a failure".to_string()),
+    })
+}
+
+/// The subcommand path `matches` ran, e.g. "agents list"
+pub fn command_name(matches: &clap::ArgMatches) -> String {
+    let mut names = Vec::new();
+    let mut current = matches;
+    while let Some((name, sub)) = current.subcommand() {
+        names.push(name);
+        current = sub;
+    }
+    names.join(" ")
+}
+
+async fn ask_once(client: &DaemonClient, prompt: &str, options: &AskOptions) -> anyhow::Result<String> {
+    match &options.agent {
+        Some(agent_id) if !options.overrides.is_empty() => queued_ask(client, agent_id, prompt, options).await,
+        Some(agent_id) => client.send_command(&exec_command(agent_id, prompt, options).to_string()).await,
+        None => client.send_command(&format!("ask:{}", prompt)).await,
+    }
+}
+
+/// How a listing or stream is printed, for commands whose output feeds pipelines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LineFormat {
+    /// For reading: a table, or a line of text per event
+    #[default]
+    Text,
+    /// One JSON object per line, flushed as it is written
+    Ndjson,
+}
+
+/// Each of `items` as one compact JSON line, flushed after every line so a
+/// reader at the other end of a pipe sees it straight away
+pub fn write_ndjson<'a>(items: impl IntoIterator<Item = &'a serde_json::Value>, out: &mut impl Write) -> anyhow::Result<usize> {
+    let mut written = 0;
+    for item in items {
+        writeln!(out, "{}", item)?;
+        out.flush()?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// A `subscribe` event as a line of text; events this CLI doesn't know print as their JSON
+pub fn event_line(event: &serde_json::Value) -> String {
+    let text = |value: &serde_json::Value| match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    match event["event"].as_str() {
+        Some("task") => {
+            let update = &event["update"];
+            let detail = update.get("detail").filter(|detail| !detail.is_null())
+                .map_or_else(String::new, |detail| format!(": {}", text(detail)));
+            format!("task {} {}{}", text(&event["task_id"]), text(&update["event"]), detail)
+        },
+        Some("agent_busy") => format!("agent {} busy", text(&event["agent_id"])),
+        Some("agent_idle") => format!("agent {} idle", text(&event["agent_id"])),
+        Some("lagged") => format!("fell behind, missed {} events", text(&event["skipped"])),
+        _ => event.to_string(),
+    }
+}
+
+/// Subscribe on `connection` and print each event as it arrives until the daemon
+/// closes the stream. Returns the number of events printed.
+pub async fn follow_events<C>(connection: C, format: LineFormat, out: &mut impl Write) -> anyhow::Result<usize>
+where
+    C: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let (reader, mut writer) = tokio::io::split(connection);
+    let mut events = BufReader::new(reader).lines();
+    writer.write_all(b"subscribe\n").await?;
+    let ack = events.next_line().await?.ok_or_else(|| anyhow::anyhow!("Daemon closed the connection before subscribing"))?;
+    if let Some(error) = serde_json::from_str::<serde_json::Value>(&ack).ok().and_then(|ack| AnfError::from_response(&ack)) {
+        return Err(error.into());
+    }
+
+    let mut printed = 0;
+    while let Some(line) = events.next_line().await? {
+        let event: serde_json::Value = serde_json::from_str(&line)
+            .map_err(|e| anyhow::anyhow!("Unexpected event from daemon: {}", e))?;
+        match format {
+            LineFormat::Ndjson => {
+                write_ndjson([&event], out)?;
+            },
+            LineFormat::Text => {
+                writeln!(out, "{} {}", chrono::Local::now().format("%H:%M:%S"), event_line(&event))?;
+                out.flush()?;
+            },
+        }
+        printed += 1;
+    }
+    Ok(printed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum FanOutLayout {
+    /// One answer after another under a label
+    Stacked,
+    /// One column per agent
+    SideBySide,
+}
+
+/// Send the same ask to every agent concurrently, one task each. Answers are
+/// keyed by agent; a failed ask is kept as its error rather than dropped.
+pub async fn fan_out<F, Fut>(agents: &[String], ask: F) -> BTreeMap<String, anyhow::Result<String>>
+where
+    F: Fn(String) -> Fut,
+    Fut: std::future::Future<Output = anyhow::Result<String>> + Send + 'static,
+{
+    let handles: Vec<_> = agents.iter()
+        .map(|agent| (agent.clone(), tokio::spawn(ask(agent.clone()))))
+        .collect();
+    let mut answers = BTreeMap::new();
+    for (agent, handle) in handles {
+        let answer = handle.await.unwrap_or_else(|e| Err(anyhow::anyhow!("ask panicked: {}", e)));
+        answers.insert(agent, answer);
+    }
+    answers
+}
+
+/// Narrowest layout the boxes and rules are drawn at; a smaller terminal wraps them
+const MIN_WIDTH: usize = 20;
+
+fn effective_width(width: usize) -> usize {
+    width.max(MIN_WIDTH)
+}
+
+/// `text` cut to `max_chars` characters, ending in `…` when anything was cut
+pub fn elide(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut elided: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    elided.push('…');
+    elided
+}
+
+/// Narrowest a column is squeezed to when a table is too wide
+const MIN_COLUMN_WIDTH: usize = 3;
+
+/// `headers` above `rows`, each column as wide as its widest cell and divided
+/// by ` │ `. When that is wider than `max_width`, the widest columns give way
+/// first and their cut cells end in `…`. Lines have no trailing spaces. Cells
+/// are measured after `glyphs` has rendered them, so ASCII tables line up too.
+pub fn render_table(headers: &[&str], rows: &[Vec<String>], max_width: usize, glyphs: Glyphs) -> String {
+    let separator = glyphs.render(" │ ");
+    let ellipsis = glyphs.render("…");
+    let cells: Vec<Vec<String>> = std::iter::once(headers.iter().map(|header| glyphs.render(header)).collect())
+        .chain(rows.iter().map(|row| row.iter().map(|cell| glyphs.render(cell)).collect()))
+        .collect();
+    let mut widths = vec![0; headers.len()];
+    for row in &cells {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let separators = separator.chars().count() * widths.len().saturating_sub(1);
+    while widths.iter().sum::<usize>() + separators > max_width {
+        let Some(widest) = widths.iter_mut().filter(|width| **width > MIN_COLUMN_WIDTH).max_by_key(|width| **width) else {
+            break;
+        };
+        *widest -= 1;
+    }
+
+    let fit = |cell: &str, width: usize| match cell.chars().count() > width {
+        true => cell.chars().take(width.saturating_sub(ellipsis.chars().count())).collect::<String>() + &ellipsis,
+        false => cell.to_string(),
+    };
+    cells.iter()
+        .map(|row| {
+            let padded: Vec<String> = widths.iter().enumerate()
+                .map(|(i, width)| format!("{:<width$}", fit(row.get(i).map_or("", String::as_str), *width), width = width))
+                .collect();
+            format!("{}\n", padded.join(&separator).trim_end())
+        })
+        .collect()
+}
+
+/// Labelled answers in `agents` order, stacked or in `width`-wide columns.
+/// Rules and dividers go through `glyphs`; the answers are printed as given.
+pub fn render_fan_out(agents: &[String], answers: &BTreeMap<String, anyhow::Result<String>>, layout: FanOutLayout, width: usize, glyphs: Glyphs) -> String {
+    let text = |agent: &String| match answers.get(agent) {
+        Some(Ok(text)) => text.clone(),
+        Some(Err(e)) => format!("error: {}", e),
+        None => "no answer".to_string(),
+    };
+    match layout {
+        FanOutLayout::Stacked => agents.iter()
+            .map(|agent| format!("{rule} {} {rule}\n{}\n", agent, text(agent).trim_end(), rule = glyphs.render("──")))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        FanOutLayout::SideBySide => {
+            let column = (width.saturating_sub(3 * agents.len().saturating_sub(1)) / agents.len().max(1)).max(10);
+            let columns: Vec<Vec<String>> = agents.iter()
+                .map(|agent| {
+                    let mut lines = vec![agent.clone(), glyphs.render("─").repeat(column)];
+                    for line in text(agent).lines() {
+                        let chars: Vec<char> = line.chars().collect();
+                        lines.extend(chars.chunks(column).map(|chunk| chunk.iter().collect::<String>()));
+                        if chars.is_empty() {
+                            lines.push(String::new());
+                        }
+                    }
+                    lines
+                })
+                .collect();
+            let rows = columns.iter().map(Vec::len).max().unwrap_or(0);
+            (0..rows)
+                .map(|row| {
+                    let cells: Vec<String> = columns.iter()
+                        .map(|lines| {
+                            let cell = lines.get(row).map(String::as_str).unwrap_or("");
+                            format!("{}{}", cell, " ".repeat(column.saturating_sub(cell.chars().count())))
+                        })
+                        .collect();
+                    cells.join(&glyphs.render(" │ ")).trim_end().to_string() + "\n"
+                })
+                .collect()
+        },
+    }
+}
+
+/// Set when any agent failed to answer, naming each one
+pub fn fan_out_failure(agents: &[String], answers: &BTreeMap<String, anyhow::Result<String>>) -> Option<AnfError> {
+    let failed: Vec<&str> = agents.iter()
+        .filter(|agent| !answers.get(*agent).is_some_and(|answer| answer.is_ok()))
+        .map(String::as_str)
+        .collect();
+    (!failed.is_empty()).then(|| AnfError::Daemon(format!("{} of {} agents failed: {}", failed.len(), agents.len(), failed.join(", "))))
+}
+
+pub fn fan_out_summary(agents: &[String], answers: &BTreeMap<String, anyhow::Result<String>>) -> String {
+    let (answered, failed): (Vec<&String>, Vec<&String>) = agents.iter()
+        .partition(|agent| answers.get(*agent).is_some_and(|answer| answer.is_ok()));
+    let list = |agents: &[&String]| if agents.is_empty() {
+        "none".to_string()
+    } else {
+        agents.iter().map(|a| a.as_str()).collect::<Vec<_>>().join(", ")
+    };
+    format!("Summary: {}/{} answered ({}); failed: {}", answered.len(), agents.len(), list(&answered), list(&failed))
+}
+
+/// The positional prompt, or the full contents of `--prompt-file` (`-` reads `stdin`)
+pub fn read_prompt(prompt: Option<&str>, file: Option<&Path>, mut stdin: impl std::io::Read) -> anyhow::Result<String> {
+    match (prompt, file) {
+        (Some(prompt), _) => Ok(prompt.to_string()),
+        (None, Some(path)) if path == Path::new("-") => {
+            let mut prompt = String::new();
+            stdin.read_to_string(&mut prompt)
+                .map_err(|e| anyhow::anyhow!("Failed to read prompt from stdin: {}", e))?;
+            Ok(prompt)
+        },
+        (None, Some(path)) => std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read prompt file {}: {}", path.display(), e)),
+        (None, None) => Err(AnfError::Usage("No prompt given".to_string()).into()),
+    }
+}
+
+/// `--output-template` text, checked for unknown placeholders up front
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputTemplate {
+    segments: Vec<TemplateSegment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TemplateSegment {
+    Literal(String),
+    Field(String),
+}
+
+impl OutputTemplate {
+    pub const FIELDS: &'static [&'static str] = &["agent", "result", "duration", "cost", "task_id"];
+
+    /// `{{` and `}}` produce literal braces
+    pub fn parse(template: &str) -> anyhow::Result<Self> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                },
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                },
+                '{' => {
+                    let name: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                    if !Self::FIELDS.contains(&name.as_str()) {
+                        return Err(anyhow::anyhow!(
+                            "Unknown placeholder {{{}}} in output template (expected one of: {})",
+                            name, Self::FIELDS.join(", ")
+                        ));
+                    }
+                    if !literal.is_empty() {
+                        segments.push(TemplateSegment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(TemplateSegment::Field(name));
+                },
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(TemplateSegment::Literal(literal));
+        }
+        Ok(Self { segments })
+    }
+
+    /// Fill placeholders from a daemon response; values the response lacks render empty
+    pub fn render(&self, response: &serde_json::Value) -> String {
+        self.segments.iter()
+            .map(|segment| match segment {
+                TemplateSegment::Literal(text) => text.clone(),
+                TemplateSegment::Field(name) => Self::field(response, name),
+            })
+            .collect()
+    }
+
+    fn field(response: &serde_json::Value, name: &str) -> String {
+        let as_text = |value: Option<&serde_json::Value>| match value {
+            Some(serde_json::Value::String(s)) => s.clone(),
+            Some(serde_json::Value::Null) | None => String::new(),
+            Some(other) => other.to_string(),
+        };
+        match name {
+            "result" => IterationOutput::from_response(&response.to_string()).text,
+            "duration" => response.get("duration_ms")
+                .and_then(|v| v.as_i64())
+                .map(|ms| format!("{}ms", ms))
+                .unwrap_or_default(),
+            // The daemon reports tokens, not money; price them at the planning rate
+            "cost" => response["output"]["tokens"].as_object()
+                .map(|tokens| ["prompt_tokens", "completion_tokens"].iter().filter_map(|k| tokens.get(*k)?.as_u64()).sum())
+                .map(|tokens| format!("{:.4}", CostModel::default().cost_usd(tokens)))
+                .unwrap_or_default(),
+            other => as_text(response.get(other)),
+        }
+    }
+}
+
+/// Map a file extension to the language name used for code fences
+pub fn detect_language(path: &Path) -> Option<&'static str> {
+    let lang = match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+        "rs" => "rust",
+        "ts" | "tsx" => "typescript",
+        "js" | "jsx" | "mjs" => "javascript",
+        "py" => "python",
+        "go" => "go",
+        "java" => "java",
+        "rb" => "ruby",
+        "c" | "h" => "c",
+        "cpp" | "cc" | "hpp" => "cpp",
+        "cs" => "csharp",
+        "sh" | "bash" => "bash",
+        "toml" => "toml",
+        "yaml" | "yml" => "yaml",
+        "json" => "json",
+        "md" => "markdown",
+        "sql" => "sql",
+        _ => return None,
+    };
+    Some(lang)
+}
+
+/// An explicit `--context-lang` wins over detection from the context path
+pub fn resolve_context_lang(explicit: Option<&str>, context: Option<&Path>) -> Option<String> {
+    explicit
+        .map(str::to_string)
+        .or_else(|| context.and_then(detect_language).map(str::to_string))
+}
+
+fn load_context(path: Option<&Path>, explicit_lang: Option<&str>) -> anyhow::Result<serde_json::Value> {
+    let mut context = serde_json::Map::new();
+    if let Some(path) = path {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read context {}: {}", path.display(), e))?;
+        context.insert("path".to_string(), path.display().to_string().into());
+        context.insert("content".to_string(), content.into());
+    }
+    if let Some(lang) = resolve_context_lang(explicit_lang, path) {
+        context.insert("lang".to_string(), lang.into());
+    }
+    Ok(context.into())
+}
+
+/// Files matching `pattern`, sorted. `*` and `?` stay within one path
+/// component and `**` spans any number of them. A `**` walk stops at `limits`,
+/// and each thing it leaves out is passed to `warn`.
+pub fn expand_glob(pattern: &str, limits: WalkLimits, warn: &mut impl FnMut(&str)) -> anyhow::Result<Vec<PathBuf>> {
+    let matcher = walk::glob_regex(pattern)
+        .map_err(|e| anyhow::anyhow!("Invalid context glob '{}': {}", pattern, e))?;
+    
+    // Walk from the components before the first wildcard, only as deep as the pattern reaches
+    let components: Vec<&str> = pattern.split('/').collect();
+    let literal = components.iter().take_while(|c| !c.contains(['*', '?'])).count().min(components.len() - 1);
+    let base = components[..literal].join("/");
+    let depth = if pattern.contains("**") { usize::MAX } else { components.len() - literal };
+    let root = match base.as_str() {
+        "" if pattern.starts_with('/') => PathBuf::from("/"),
+        "" => PathBuf::from("."),
+        base => PathBuf::from(base),
+    };
+    
+    let relative = |path: &Path| if base.is_empty() { path.strip_prefix(".").map(Path::to_path_buf).unwrap_or(path.to_path_buf()) } else { path.to_path_buf() };
+    let found = walk::walk(
+        &root,
+        WalkLimits { max_depth: depth.min(limits.max_depth), ..limits },
+        |path| matcher.is_match(&relative(path).to_string_lossy()),
+    );
+    for skipped in &found.skipped {
+        // Deeper than the pattern reaches isn't a limit the user set
+        if depth > limits.max_depth || matches!(skipped, Skipped::FileLimit(_)) {
+            warn(&format!("{}: {}", pattern, skipped));
+        }
+    }
+    Ok(found.files.iter().map(|path| relative(path)).collect())
+}
+
+/// Context from several sources in a fixed order: the explicit file (or the
+/// files under it, when it is a directory), then glob matches in path order,
+/// then stdin. Each source gets a `--- <source> ---` header; a source whose
+/// contents were already included is dropped. No file is read past `max_bytes`;
+/// ones with a NUL byte are taken for binary and skipped, and other invalid
+/// UTF-8 is replaced. What is left out goes to `warn`.
+pub fn merge_context(
+    file: Option<&Path>,
+    globs: &[String],
+    stdin: Option<String>,
+    explicit_lang: Option<&str>,
+    max_bytes: usize,
+    limits: WalkLimits,
+    mut warn: impl FnMut(&str),
+) -> anyhow::Result<serde_json::Value> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    match file {
+        Some(dir) if dir.is_dir() => {
+            let found = walk::walk(dir, limits, |_| true);
+            for skipped in &found.skipped {
+                warn(&skipped.to_string());
+            }
+            files.extend(found.files);
+        },
+        file => files.extend(file.map(Path::to_path_buf)),
+    }
+    for pattern in globs {
+        files.extend(expand_glob(pattern, limits, &mut warn)?);
+    }
+    let mut included: Vec<(String, String)> = Vec::new();
+    for path in &files {
+        // No one file can fit in more than the whole limit, repeats included
+        if let Some(content) = read_context_file(path, max_bytes, &mut warn)? {
+            include_source(&mut included, path.display().to_string(), content, max_bytes)?;
+        }
+    }
+    if let Some(content) = stdin {
+        include_source(&mut included, "<stdin>".to_string(), content, max_bytes)?;
+    }
+    let mut merged = String::new();
+    for (label, content) in &included {
+        merged.push_str(&format!("--- {} ---\n{}", label, content));
+        if !content.ends_with('\n') {
+            merged.push('\n');
+        }
+    }
+    if merged.len() > max_bytes {
+        return Err(anyhow::anyhow!(
+            "Merged context is {} bytes, over the agents.max_context_bytes limit of {}",
+            merged.len(), max_bytes
+        ));
+    }
+    
+    let mut context = serde_json::Map::new();
+    context.insert("sources".to_string(), included.iter().map(|(label, _)| label.clone()).collect::<Vec<_>>().into());
+    context.insert("content".to_string(), merged.into());
+    if let Some(lang) = resolve_context_lang(explicit_lang, files.first().map(PathBuf::as_path)) {
+        context.insert("lang".to_string(), lang.into());
+    }
+    Ok(context.into())
+}
+
+/// At most `limit + 1` bytes of `path`, enough to tell it went over; None,
+/// with a warning, when a NUL byte marks it as binary
+fn read_context_file(path: &Path, limit: usize, warn: &mut impl FnMut(&str)) -> anyhow::Result<Option<String>> {
+    let mut bytes = Vec::new();
+    std::fs::File::open(path)
+        .and_then(|file| std::io::Read::read_to_end(&mut std::io::Read::take(file, limit as u64 + 1), &mut bytes))
+        .map_err(|e| anyhow::anyhow!("Failed to read context {}: {}", path.display(), e))?;
+    if bytes.contains(&0) {
+        warn(&format!("{} looks binary, skipped", path.display()));
+        return Ok(None);
+    }
+    Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+/// Add `content` unless it is already there, refusing to go past `max_bytes` in all
+fn include_source(included: &mut Vec<(String, String)>, label: String, content: String, max_bytes: usize) -> anyhow::Result<()> {
+    if included.iter().any(|(_, seen)| *seen == content) {
+        return Ok(());
+    }
+    if included.iter().map(|(_, seen)| seen.len()).sum::<usize>() + content.len() > max_bytes {
+        return Err(anyhow::anyhow!("Merged context is over the agents.max_context_bytes limit of {}", max_bytes));
+    }
+    included.push((label, content));
+    Ok(())
+}
+
+/// Tag untagged fenced code blocks in a response with the context language
+pub fn render_markdown(text: &str, lang: Option<&str>) -> String {
+    let Some(lang) = lang else {
+        return text.to_string();
+    };
+
+    let mut in_block = false;
+    text.lines()
+        .map(|line| {
+            if line.trim_start().starts_with("```") {
+                let opening = !in_block;
+                in_block = !in_block;
+                if opening && line.trim() == "```" {
+                    return line.replacen("```", &format!("```{}", lang), 1);
+                }
+            }
+            line.to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Stop condition for `ask --repeat-until`
+#[derive(Debug)]
+pub enum RepeatCondition {
+    Contains(String),
+    Regex(regex::Regex),
+    ExitZero,
+}
+
+impl std::str::FromStr for RepeatCondition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "exit-zero" {
+            return Ok(Self::ExitZero);
+        }
+        match s.split_once(':') {
+            Some(("contains", needle)) => Ok(Self::Contains(needle.to_string())),
+            Some(("regex", pattern)) => Ok(Self::Regex(regex::Regex::new(pattern)?)),
+            _ => Err(anyhow::anyhow!("Invalid condition {:?}: expected contains:<s>, regex:<r> or exit-zero", s)),
+        }
+    }
+}
+
+impl RepeatCondition {
+    pub fn matches(&self, output: &IterationOutput) -> bool {
+        match self {
+            Self::Contains(needle) => output.text.contains(needle.as_str()),
+            Self::Regex(re) => re.is_match(&output.text),
+            Self::ExitZero => output.exit_code == Some(0),
+        }
+    }
+}
+
+/// What one iteration of a repeated ask produced
+#[derive(Debug, Clone)]
+pub struct IterationOutput {
+    pub text: String,
+    pub exit_code: Option<i32>,
+}
+
+impl IterationOutput {
+    /// Pull the answer text (and exit code, for agents that shell out) from a daemon reply
+    pub fn from_response(response: &str) -> Self {
+        match serde_json::from_str::<serde_json::Value>(response) {
+            Ok(value) => {
+                let text = value.get("response")
+                    .or_else(|| value.pointer("/output/text"))
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string)
+                    .unwrap_or_else(|| response.to_string());
+                let exit_code = value.get("exit_code").and_then(|v| v.as_i64()).map(|code| code as i32);
+                Self { text, exit_code }
+            },
+            Err(_) => Self { text: response.to_string(), exit_code: None },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RepeatOutcome {
+    pub output: String,
+    pub iterations: usize,
+    pub matched: bool,
+}
+
+/// Run `step` until its output satisfies `condition` or `max_iterations` is reached.
+/// `step` gets the 1-based iteration number and the previous output text.
+pub async fn repeat_until_match<F, Fut>(
+    condition: &RepeatCondition,
+    max_iterations: usize,
+    mut step: F,
+) -> anyhow::Result<RepeatOutcome>
+where
+    F: FnMut(usize, Option<&str>) -> Fut,
+    Fut: std::future::Future<Output = anyhow::Result<IterationOutput>>,
+{
+    let mut previous: Option<String> = None;
+    for iteration in 1..=max_iterations.max(1) {
+        let output = step(iteration, previous.as_deref()).await?;
+        if condition.matches(&output) {
+            return Ok(RepeatOutcome { output: output.text, iterations: iteration, matched: true });
+        }
+        previous = Some(output.text);
+    }
+
+    Ok(RepeatOutcome {
+        output: previous.unwrap_or_default(),
+        iterations: max_iterations.max(1),
+        matched: false,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WaitOutcome {
+    /// The task as last fetched, in a terminal status
+    Finished(serde_json::Value),
+    /// Still running at the deadline; the last status seen
+    TimedOut(String),
+}
+
+/// Poll `fetch` (a `get_task` response) every `interval` until the task is
+/// completed, failed or cancelled, or `timeout` passes. `on_poll` sees each status.
+pub async fn wait_for_task<F, Fut>(
+    interval: Duration,
+    timeout: Duration,
+    mut fetch: F,
+    mut on_poll: impl FnMut(&str),
+) -> anyhow::Result<WaitOutcome>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = anyhow::Result<serde_json::Value>>,
+{
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        let response = fetch().await?;
+        if let Some(error) = AnfError::from_response(&response) {
+            return Err(error.into());
+        }
+        let task = &response["task"];
+        let status = task["status"].as_str().unwrap_or("Unknown").to_string();
+        on_poll(&status);
+        if matches!(status.as_str(), "Completed" | "Failed" | "Cancelled") {
+            return Ok(WaitOutcome::Finished(task.clone()));
+        }
+        if tokio::time::Instant::now() + interval > deadline {
+            return Ok(WaitOutcome::TimedOut(status));
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// The output of a completed task, or the error `anf agents wait` exits with
+pub fn wait_result(task_id: &str, outcome: &WaitOutcome) -> anyhow::Result<String> {
+    match outcome {
+        WaitOutcome::Finished(task) if task["status"] == "Completed" => {
+            Ok(task["output"]["text"].as_str().unwrap_or_default().to_string())
+        },
+        WaitOutcome::Finished(task) => Err(anyhow::anyhow!(
+            "Task {} {}: {}",
+            task_id,
+            task["status"].as_str().unwrap_or("failed").to_ascii_lowercase(),
+            task["error"].as_str().unwrap_or("no error recorded")
+        )),
+        WaitOutcome::TimedOut(status) => Err(AnfError::Timeout(format!("Timed out waiting for task {} (still {})", task_id, status)).into()),
+    }
+}
+
+/// What `agents batch` queued and what it couldn't, by 1-based line number
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct BatchResult {
+    pub submitted: Vec<(usize, uuid::Uuid)>,
+    pub rejected: Vec<(usize, String)>,
+}
+
+impl BatchResult {
+    /// An error naming how many lines were rejected, unless none were or `keep_going`
+    pub fn check(&self, keep_going: bool) -> anyhow::Result<()> {
+        if self.rejected.is_empty() || keep_going {
+            return Ok(());
+        }
+        Err(AnfError::Daemon(format!(
+            "{} of {} tasks rejected", self.rejected.len(), self.rejected.len() + self.submitted.len()
+        )).into())
+    }
+
+    pub fn summary_lines(&self) -> Vec<String> {
+        let mut lines = vec![format!("📦 Submitted {} of {} tasks", self.submitted.len(), self.submitted.len() + self.rejected.len())];
+        lines.extend(self.submitted.iter().map(|(line, task_id)| format!("  ✅ line {}: {}", line, task_id)));
+        if !self.rejected.is_empty() {
+            lines.push(format!("Rejected {}:", self.rejected.len()));
+            lines.extend(self.rejected.iter().map(|(line, reason)| format!("  ❌ line {}: {}", line, reason)));
+        }
+        lines
+    }
+}
+
+/// Submit each task line of `batch` through `submit` (a `submit_task` round trip).
+/// Blank lines and `#` comments are skipped; a line that isn't a JSON object,
+/// or that the daemon refuses, is rejected with the reason and the rest carry on.
+pub async fn submit_batch<F, Fut>(batch: &str, mut submit: F) -> BatchResult
+where
+    F: FnMut(serde_json::Value) -> Fut,
+    Fut: std::future::Future<Output = anyhow::Result<serde_json::Value>>,
+{
+    let mut result = BatchResult::default();
+    for (index, line) in batch.lines().enumerate() {
+        let (number, line) = (index + 1, line.trim());
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let params = match serde_json::from_str::<serde_json::Value>(line) {
+            Ok(params) if params.is_object() => params,
+            Ok(_) => {
+                result.rejected.push((number, "expected a JSON object".to_string()));
+                continue;
+            },
+            Err(e) => {
+                result.rejected.push((number, format!("invalid JSON: {}", e)));
+                continue;
+            },
+        };
+        let response = submit(serde_json::json!({"action": "submit_task", "params": params})).await;
+        let outcome = response.and_then(|response| match AnfError::from_response(&response) {
+            Some(error) => Err(error.into()),
+            None => response["task_id"].as_str()
+                .and_then(|id| uuid::Uuid::parse_str(id).ok())
+                .ok_or_else(|| anyhow::anyhow!("Daemon reply has no task id")),
+        });
+        match outcome {
+            Ok(task_id) => result.submitted.push((number, task_id)),
+            Err(e) => result.rejected.push((number, e.to_string())),
+        }
+    }
+    result
+}
+
+/// `agents info` output for an `agent_info` agent. The description line is left
+/// out when there is none; author and version fall back to placeholders.
+pub fn agent_info_lines(agent: &serde_json::Value) -> Vec<String> {
+    let text = |key: &str| agent.get(key).and_then(|v| v.as_str()).filter(|v| !v.trim().is_empty());
+    let list = |key: &str| agent.get(key).and_then(|v| v.as_array())
+        .map(|items| items.iter().filter_map(|item| item.as_str()).collect::<Vec<_>>().join(", "))
+        .unwrap_or_default();
+    
+    let mut lines = vec![format!("{} ({})", text("name").unwrap_or("?"), text("id").unwrap_or("?"))];
+    lines.extend(text("description").map(str::to_string));
+    lines.push(format!("Type: {}", text("agent_type").unwrap_or("unknown")));
+    lines.push(format!("Capabilities: {}", list("capabilities")));
+    lines.push(format!("Models: {}", list("models")));
+    lines.push(format!("Version: {}", text("version").unwrap_or("unversioned")));
+    lines.push(format!("Author: {}", text("author").unwrap_or("unknown")));
+    lines
+}
+
+/// `anf task status`: where a task is and, once it has ended badly, why
+pub fn task_status_lines(task: &serde_json::Value) -> Vec<String> {
+    let text = |key: &str| task.get(key).and_then(|v| v.as_str()).filter(|v| !v.is_empty());
+    let mut lines = vec![
+        format!("Task {}: {}", text("id").unwrap_or("?"), text("status").unwrap_or("Unknown")),
+        format!("Agent: {}", text("fulfilled_by").or(text("agent_id")).unwrap_or("?")),
+        format!("Created: {}", text("created_at").unwrap_or("?")),
+    ];
+    lines.extend(text("started_at").map(|at| format!("Started: {}", at)));
+    lines.extend(text("completed_at").map(|at| format!("Finished: {}", at)));
+    match (text("status_detail"), text("error")) {
+        (Some(detail), Some(error)) => lines.push(format!("Reason: {} ({})", detail, error)),
+        (detail, error) => lines.extend(detail.or(error).map(|reason| format!("Reason: {}", reason))),
+    }
+    let tokens = &task["output"]["tokens"];
+    if let (Some(prompt), Some(completion)) = (tokens["prompt_tokens"].as_u64(), tokens["completion_tokens"].as_u64()) {
+        let source = tokens["counted_by"].as_str().map_or_else(|| "exact".to_string(), |tokenizer| format!("estimated by {}", tokenizer));
+        lines.push(format!("Tokens: {} prompt, {} completion ({})", prompt, completion, source));
+    }
+    lines
+}
+
+/// `anf cache stats` for reading; the ratio is left out until something has been looked up
+pub fn cache_stats_lines(response: &serde_json::Value) -> Vec<String> {
+    let stats = &response["stats"];
+    let count = |key: &str| stats[key].as_u64().unwrap_or(0);
+    let mut lines = Vec::new();
+    if response["enabled"] == false {
+        lines.push("Response cache is off; set agents.response_cache = true to turn it on".to_string());
+    }
+    let evicted = match count("evictions") {
+        0 => String::new(),
+        evictions => format!(", {} evicted to stay within the limits", evictions),
+    };
+    lines.push(format!("Entries: {} ({}){}", count("entries"), format_size(count("bytes")), evicted));
+    let ratio = stats["hit_ratio"].as_f64().map_or_else(String::new, |ratio| format!(", {:.1}% hit ratio", ratio * 100.0));
+    lines.push(format!("Lookups: {} hits, {} misses{}", count("hits"), count("misses"), ratio));
+    lines
+}
+
+/// How long ago, in the largest whole unit: 42s, 5m, 3h, 2d
+pub fn format_age(seconds: i64) -> String {
+    let seconds = seconds.max(0);
+    match seconds {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m", s / 60),
+        s if s < 86_400 => format!("{}h", s / 3600),
+        s => format!("{}d", s / 86_400),
+    }
+}
+
+async fn send_cache_command(client: &DaemonClient, action: &str, params: serde_json::Value) -> anyhow::Result<serde_json::Value> {
+    let command = serde_json::json!({"action": action, "params": params});
+    let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+        .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+    if let Some(error) = AnfError::from_response(&response) {
+        return Err(error.into());
+    }
+    Ok(response)
+}
+
+/// The dashboard's latency table: overall first, then each agent
+pub fn latency_lines(latency: &serde_json::Value) -> Vec<String> {
+    let row = |label: &str, summary: &serde_json::Value| format!(
+        "  {:<28} p50 {:>6}ms  p90 {:>6}ms  p99 {:>6}ms  ({} tasks)",
+        label,
+        summary["p50_ms"].as_u64().unwrap_or(0),
+        summary["p90_ms"].as_u64().unwrap_or(0),
+        summary["p99_ms"].as_u64().unwrap_or(0),
+        summary["count"].as_u64().unwrap_or(0),
+    );
+    let mut lines = vec!["Task latency:".to_string(), row("all agents", &latency["overall"])];
+    for (agent, summary) in latency["agents"].as_object().into_iter().flatten() {
+        lines.push(row(agent, summary));
+    }
+    lines
+}
+
+/// `template` as a bar style, or the default bar after telling `warn` why the
+/// template was rejected, so a bad template degrades the display rather than failing
+pub fn bar_style_or_default(template: &str, warn: impl FnOnce(&str)) -> ProgressStyle {
+    ProgressStyle::default_bar().template(template).unwrap_or_else(|e| {
+        warn(&e.to_string());
+        ProgressStyle::default_bar()
+    })
+}
+
+/// A CLI enum's values with their descriptions; a JSON array of `{name, description}` with `--json`
+fn print_values<T: clap::ValueEnum>(json: bool) -> anyhow::Result<()> {
+    let values = value_descriptions::<T>();
+    if json {
+        let values: Vec<serde_json::Value> = values.iter()
+            .map(|(name, description)| serde_json::json!({"name": name, "description": description}))
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&values)?);
+    } else {
+        let width = values.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        for (name, description) in &values {
+            println!("{:<width$}  {}", name, description, width = width);
+        }
+    }
+    Ok(())
+}
+
+/// Submit each step in dependency order, asking first when `confirmation` is set.
+/// Progress is printed from the runner's step events, as NDJSON with `ndjson`.
+/// Returns the artifacts the steps produced.
+async fn run_workflow(
+    client: &DaemonClient,
+    workflow: &Workflow,
+    confirmation: Option<StepConfirmation>,
+    ndjson: bool,
+) -> anyhow::Result<Vec<ProducedArtifact>> {
+    if !ndjson {
+        println!("🚀 Running workflow '{}' ({} steps)", workflow.name, workflow.steps.len());
+    }
+    let (events, received) = tokio::sync::broadcast::channel(64);
+    let display = tokio::spawn(print_step_events(received, ndjson));
+    let produced = Arc::new(std::sync::Mutex::new(Vec::new()));
+    
+    let result = workflow.run(
+        confirmation,
+        || {
+            let mut answer = String::new();
+            std::io::stdin().read_line(&mut answer)?;
+            Ok(answer)
+        },
+        |step| {
+            let (client, prompt, produced) = (client.clone(), step.prompt.clone(), produced.clone());
+            let options = AskOptions {
+                agent: Some(step.agent.clone()),
+                fallback: Vec::new(),
+                context: serde_json::json!({}),
+                ..AskOptions::default()
+            };
+            async move {
+                let response = ask_once(&client, &prompt, &options).await?;
+                if let Ok(value) = serde_json::from_str(&response) {
+                    produced.lock().unwrap_or_else(|e| e.into_inner()).extend(produced_artifacts(&value));
+                }
+                Ok(IterationOutput::from_response(&response).text)
+            }
+        },
+        &events,
+    ).await;
+    
+    drop(events);
+    display.await?;
+    result?;
+    let produced = std::mem::take(&mut *produced.lock().unwrap_or_else(|e| e.into_inner()));
+    Ok(produced)
+}
+
+/// How a step event reads in the progress display; the approval prompt has no newline
+pub fn render_step_event(event: &StepEvent) -> String {
+    let detail = event.detail.as_deref().unwrap_or_default();
+    match event.state {
+        StepState::AwaitingApproval => format!(
+            "\n[{}/{}] {} → {}\n    {}\nRun this step? [y/N/a] ",
+            event.index, event.total, event.step, event.agent, detail
+        ),
+        StepState::Started => format!("⏳ [{}/{}] {} → {}\n", event.index, event.total, event.step, event.agent),
+        StepState::Completed => format!("✅ {}: {}\n", event.step, detail),
+        StepState::Failed => format!("❌ {}: {}\n", event.step, detail),
+        StepState::Skipped => format!("⏭️  Skipped {}\n", event.step),
+    }
+}
+
+async fn print_step_events(mut events: tokio::sync::broadcast::Receiver<StepEvent>, ndjson: bool) {
+    use tokio::sync::broadcast::error::RecvError;
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(skipped)) => {
+                eprintln!("(missed {} workflow events)", skipped);
+                continue;
+            },
+            Err(RecvError::Closed) => return,
+        };
+        let mut out = std::io::stdout().lock();
+        let _ = if ndjson {
+            writeln!(out, "{}", serde_json::to_string(&event).unwrap_or_default())
+        } else {
+            write!(out, "{}", render_step_event(&event))
+        };
+        let _ = out.flush();
+    }
+}
+
+/// Parse a comma-separated `--agents` value shared by collaborate and swarm create.
+///
+/// Entries are trimmed, empties and duplicates dropped, and when the registry is
+/// known every name must exist in it; all unknown names are reported together.
+pub fn parse_agent_list(raw: &str, registry: Option<&[String]>) -> anyhow::Result<Vec<String>> {
+    let mut agents: Vec<String> = Vec::new();
+    for name in raw.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        if !agents.iter().any(|existing| existing == name) {
+            agents.push(name.to_string());
+        }
+    }
+
+    if let Some(registry) = registry {
+        let unknown: Vec<&str> = agents.iter()
+            .filter(|name| !registry.contains(name))
+            .map(String::as_str)
+            .collect();
+        if !unknown.is_empty() {
+            return Err(anyhow::anyhow!("Unknown agents: {}", unknown.join(", ")));
+        }
+    }
+
+    if agents.is_empty() {
+        return Err(anyhow::anyhow!("No agents given"));
+    }
+
+    Ok(agents)
+}
+
+pub async fn run_cli(cli: Cli) -> anyhow::Result<()> {
+    let flags: Vec<(String, String)> = cli.socket.iter()
+        .map(|path| ("daemon.socket_path".to_string(), path.display().to_string()))
+        .collect();
+    let resolved = Config::load(&flags)?;
+    let format = OutputFormat::of(&cli);
+    let ui = TerminalUI::new()
+        .with_encoding(resolved.config.interface.encoding)
+        .with_compact(cli.compact);
+    if !resolved.config.interface.colors {
+        crossterm::style::force_color_output(false);
+    }
+    let client = if cli.no_daemon {
+        DaemonClient::embedded(EmbeddedDaemon::start(AgentPool::configured(&resolved)).await?)
+    } else {
+        let client = DaemonClient::new(resolved.config.daemon.socket_path.clone());
+        match &resolved.config.daemon.tls_ca {
+            Some(ca) => client.with_tls_ca(Path::new(ca))?,
+            None => client,
+        }
+    };
+
+    if let Some(name) = &cli.restore_session {
+        restore_session(&client, name).await?;
+    }
+    let active = ActiveState::load();
+
+    let Some(command) = &cli.command else {
+        match &cli.save_session {
+            Some(name) => save_session(&client, name, &active).await?,
+            None if cli.restore_session.is_none() => {
+                <Cli as clap::CommandFactory>::command().print_help()?;
+            },
+            None => {},
+        }
+        return Ok(());
+    };
+
+    match command {
+        Commands::Ask { prompt, prompt_file, agent, agents, layout, summary, max_concurrency, context, context_lang, context_glob, context_stdin, merge_context, max_depth, max_files, context_redact, system, background: _, fallback, model, timeout, on_timeout, repeat_until, max_iterations, feed_back, output_template, overrides } => {
+            let prompt = &read_prompt(prompt.as_deref(), prompt_file.as_deref(), std::io::stdin().lock())?;
+            let template = output_template.as_deref().map(OutputTemplate::parse).transpose()?;
+            let mut options = AskOptions {
+                agent: agent.clone()
+                    .or_else(|| active.agent.clone())
+                    .or_else(|| resolved.config.agents.default_agent.clone()),
+                fallback: fallback.clone(),
+                context: if *merge_context || context.as_deref().or(active.context.as_deref()).is_some_and(Path::is_dir) {
+                    if *context_stdin && prompt_file.as_deref() == Some(Path::new("-")) {
+                        return Err(AnfError::Usage("stdin can't be both the prompt and the context".to_string()).into());
+                    }
+                    let max_context_bytes = resolved.config.agents.max_context_bytes;
+                    let stdin = context_stdin.then(|| {
+                        let mut bytes = Vec::new();
+                        std::io::Read::read_to_end(&mut std::io::Read::take(std::io::stdin().lock(), max_context_bytes as u64 + 1), &mut bytes)
+                            .map(|_| String::from_utf8_lossy(&bytes).into_owned())
+                            .map_err(|e| anyhow::anyhow!("Failed to read context from stdin: {}", e))
+                    }).transpose()?;
+                    crate::merge_context(
+                        context.as_deref().or(active.context.as_deref()),
+                        context_glob,
+                        stdin,
+                        context_lang.as_deref(),
+                        max_context_bytes,
+                        WalkLimits { max_depth: *max_depth, max_files: *max_files },
+                        |warning| eprintln!("Warning: {}", warning),
+                    )?
+                } else {
+                    load_context(context.as_deref().or(active.context.as_deref()), context_lang.as_deref())?
+                },
+                model: model.clone(),
+                timeout: *timeout,
+                on_timeout: *on_timeout,
+                overrides: AgentOverrides::parse(overrides).map_err(|e| AnfError::Usage(e.to_string()))?,
+                queue_timeout: Duration::from_secs(resolved.config.agents.default_timeout),
+            };
+            let agent_only = agent_only_flags(command);
+            if options.agent.is_none() && agents.is_empty() && !agent_only.is_empty() {
+                return Err(AnfError::Usage(format!(
+                    "{} need an agent (--agent or agents.default_agent)", agent_only.join(", ")
+                )).into());
+            }
+            with_system(&mut options.context, system);
+            if *context_redact {
+                let redactor = Redactor::new(&resolved.config.redact.patterns)?;
+                let redactions = redact_context(&mut options.context, &redactor);
+                eprintln!("{}", ui.glyphs.render(&format!("🔒 Redacted {} secret(s) from the context", redactions)));
+            }
+            let lang = options.context.get("lang").and_then(|v| v.as_str()).map(str::to_string);
+            
+            if !agents.is_empty() {
+                let mut unique: Vec<String> = Vec::with_capacity(agents.len());
+                for agent in agents {
+                    if !unique.contains(agent) {
+                        unique.push(agent.clone());
+                    }
+                }
+                let agents = &unique;
+                let client = client.clone().with_pool(*max_concurrency);
+                let answers = fan_out(agents, |agent| {
+                    let (client, prompt) = (client.clone(), prompt.clone());
+                    let options = AskOptions { agent: Some(agent), ..options.clone() };
+                    async move {
+                        let response = ask_once(&client, &prompt, &options).await?;
+                        if let Some(error) = reply_error(&response) {
+                            return Err(error.into());
+                        }
+                        Ok(IterationOutput::from_response(&response).text)
+                    }
+                }).await;
+                let failed = fan_out_failure(agents, &answers);
+                if format == OutputFormat::Json {
+                    let status = if failed.is_none() { "ok" } else { "error" };
+                    let answers: serde_json::Map<String, serde_json::Value> = answers.iter()
+                        .map(|(agent, answer)| (agent.clone(), match answer {
+                            Ok(output) => serde_json::json!({"output": output}),
+                            Err(e) => serde_json::json!({"error": e.to_string()}),
+                        }))
+                        .collect();
+                    println!("{}", json_report("ask", status, serde_json::json!({"answers": answers})));
+                    return failed.map_or(Ok(()), |error| Err(Reported(error).into()));
+                }
+                let width = size().map_or(120, |(width, _)| width as usize);
+                print!("{}", render_fan_out(agents, &answers, *layout, width, ui.glyphs));
+                if *summary {
+                    println!("{}", fan_out_summary(agents, &answers));
+                }
+                return failed.map_or(Ok(()), |error| Err(error.into()));
+            }
+            
+            if let (Some(agent_id), false) = (&options.agent, cli.quiet || template.is_some() || format == OutputFormat::Json) {
+                ui.display_agent_status(agent_id, "Processing").await?;
+            }
+            
+            match repeat_until {
+                Some(condition) => {
+                    let condition: RepeatCondition = condition.parse()?;
+                    let outcome = repeat_until_match(&condition, *max_iterations, |_, previous| {
+                        let prompt = match (previous, *feed_back) {
+                            (Some(previous), true) => format!("{}\n\nPrevious output:\n{}", prompt, previous),
+                            _ => prompt.clone(),
+                        };
+                        let (client, options) = (&client, &options);
+                        async move {
+                            let response = ask_once(client, &prompt, options).await?;
+                            if let Some(error) = reply_error(&response) {
+                                return Err(error.into());
+                            }
+                            Ok(IterationOutput::from_response(&response))
+                        }
+                    }).await?;
+                    
+                    if format == OutputFormat::Json {
+                        println!("{}", json_report("ask", "ok", serde_json::json!({
+                            "agent": options.agent,
+                            "output": outcome.output,
+                            "iterations": outcome.iterations,
+                            "matched": outcome.matched,
+                        })));
+                        return Ok(());
+                    }
+                    println!("🤖 {}", render_markdown(&outcome.output, lang.as_deref()));
+                    println!("🔁 {} iteration(s), condition {}", outcome.iterations,
+                             if outcome.matched { "met" } else { "not met" });
+                },
+                None => {
+                    let response = ask_once(&client, prompt, &options).await?;
+                    if let Some(error) = reply_error(&response) {
+                        if template.is_none() && format == OutputFormat::Json {
+                            println!("{}", ask_report(options.agent.as_deref(), &response));
+                            return Err(Reported(error).into());
+                        }
+                        return Err(error.into());
+                    }
+                    let text = IterationOutput::from_response(&response).text;
+                    match (&template, format, cli.quiet) {
+                        (Some(template), _, _) => {
+                            let response = serde_json::from_str(&response)
+                                .unwrap_or_else(|_| serde_json::json!({"response": response}));
+                            println!("{}", template.render(&response));
+                        },
+                        (None, OutputFormat::Json, _) => println!("{}", ask_report(options.agent.as_deref(), &response)),
+                        (None, OutputFormat::Human, true) => println!("{}", text),
+                        (None, OutputFormat::Human, false) => println!("🤖 {}", render_markdown(&text, lang.as_deref())),
+                    }
+                },
+            }
+        },
+
+        Commands::Spawn { agent, background: _, pipe_to: _, mem_limit } => {
+            // Ask the daemon first so a refusal isn't preceded by a success animation
+            let spawned = client.spawn_agent(agent, *mem_limit).await?;
+            match format {
+                OutputFormat::Json => println!("{}", json_report("spawn", "ok", serde_json::json!({"agent": agent, "message": spawned.message}))),
+                OutputFormat::Human => ui.spawn_agent(agent).await?,
+            }
+        },
+
+        Commands::Interactive { agent } => {
+            ui.interactive_mode(&client, agent.as_deref()).await?;
+        },
 
         Commands::Agents { action } => {
             match action {
-                AgentCommands::List { category: _, available: _, active: _ } => {
-                    ui.list_agents().await?;
+                AgentCommands::List { category, available, active } => {
+                    let query = AgentQuery { category: category.clone(), available: *available, active: *active };
+                    if cli.porcelain {
+                        print_porcelain(&client, &query.command(), PorcelainList::Agents).await?;
+                    } else if format == OutputFormat::Json {
+                        let agents = client.fetch_agents(&query).await?;
+                        println!("{}", json_report("agents list", "ok", serde_json::json!({"agents": agents})));
+                    } else {
+                        ui.list_agents(&client, &query).await?;
+                    }
+                },
+                AgentCommands::Info { agent, capabilities: _, status: true } => {
+                    let command = serde_json::json!({"action": "agent_status", "params": {"agent_id": agent}});
+                    let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+                        .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+                    if let Some(error) = AnfError::from_response(&response) {
+                        return Err(error.into());
+                    }
+                    println!("{}", response["status"].as_str().unwrap_or("unknown"));
+                    match response.get("last_probe").filter(|probe| !probe.is_null()) {
+                        Some(probe) if probe["ok"] == true => println!("Last probe: ok in {:.1}ms at {}", probe["latency_ms"].as_f64().unwrap_or(0.0), probe["at"].as_str().unwrap_or("?")),
+                        Some(probe) => println!("Last probe: failed ({}) at {}", probe["error"].as_str().unwrap_or("unknown error"), probe["at"].as_str().unwrap_or("?")),
+                        None => println!("Last probe: never probed"),
+                    }
+                },
+                AgentCommands::Info { agent, capabilities: _, status: false } => {
+                    let command = serde_json::json!({"action": "agent_info", "params": {"agent_id": agent}});
+                    let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+                        .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+                    if let Some(error) = AnfError::from_response(&response) {
+                        return Err(error.into());
+                    }
+                    if ui.compact {
+                        let load = &response["status"];
+                        let state = if load["running_tasks"].as_u64().unwrap_or(0) > 0 { "busy" } else { "idle" };
+                        ui.print_compact(&ui.compact_agent_line(agent, state, Some(load)))?;
+                    } else {
+                        for line in agent_info_lines(&response["agent"]) {
+                            println!("{}", ui.glyphs.render(&line));
+                        }
+                        let status = &response["status"];
+                        if let (Some(running), Some(queued), Some(cap)) = (status["running_tasks"].as_u64(), status["queued_tasks"].as_u64(), status["max_concurrent_tasks"].as_u64()) {
+                            let throttled = if status["throttled"].as_bool() == Some(true) { ", throttled" } else { "" };
+                            println!("Tasks: {} of {} running, {} queued{}", running, cap, queued, throttled);
+                        }
+                        if let Some(available) = status.get("available_memory") {
+                            match available.as_u64() {
+                                Some(bytes) => println!("Memory: {} free for new tasks", format_size(bytes)),
+                                None => println!("Memory: no limit"),
+                            }
+                        }
+                    }
+                },
+                AgentCommands::Create { name, base, capabilities, config } => {
+                    let command = match config {
+                        Some(path) => {
+                            let agent: serde_json::Value = std::fs::read_to_string(path)
+                                .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))
+                                .and_then(|contents| serde_json::from_str(&contents)
+                                    .map_err(|e| AnfError::Usage(format!("Invalid agent config {}: {}", path.display(), e)).into()))?;
+                            serde_json::json!({"action": "save_agent", "params": {"agent": agent}})
+                        },
+                        None => serde_json::json!({
+                            "action": "create_agent",
+                            "params": {"name": name, "base": base, "capabilities": capabilities}
+                        }),
+                    };
+                    let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+                        .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+                    if let Some(error) = AnfError::from_response(&response) {
+                        return Err(error.into());
+                    }
+                    println!("Created agent {}", response["agent"]["id"].as_str().unwrap_or("?"));
                 },
-                AgentCommands::Info { agent, capabilities: _, status: _ } => {
-                    ui.display_agent_status(&agent, "Active").await?;
+                AgentCommands::Undo => {
+                    let command = serde_json::json!({"action": "undo_registry", "params": {}});
+                    let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+                        .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+                    if let Some(error) = AnfError::from_response(&response) {
+                        return Err(error.into());
+                    }
+                    println!("Undid {} ({} agents registered)", response["undone"].as_str().unwrap_or("?"), response["agents"]);
                 },
-                AgentCommands::Create { name: _, base: _, capabilities: _ } => {
-                    println!("Creating custom agent...");
+                AgentCommands::Search { capability } => {
+                    let command = serde_json::json!({"action": "search_agents", "params": {"capability": capability}});
+                    let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+                        .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+                    for agent in response.get("agents").and_then(|v| v.as_array()).into_iter().flatten() {
+                        println!("{}", agent.get("id").and_then(|v| v.as_str()).unwrap_or("?"));
+                    }
+                },
+                AgentCommands::Verify { file } => {
+                    let definition = std::fs::read_to_string(file)
+                        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", file.display(), e))?;
+                    let command = serde_json::json!({"action": "verify_agent", "params": {"definition": definition}});
+                    let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+                        .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+                    if let Some(error) = AnfError::from_response(&response) {
+                        return Err(error.into());
+                    }
+                    let problems: Vec<&str> = response["problems"].as_array().into_iter().flatten().filter_map(|p| p.as_str()).collect();
+                    if !problems.is_empty() {
+                        for problem in &problems {
+                            eprintln!("{}: {}", file.display(), problem);
+                        }
+                        return Err(anyhow::anyhow!("{} problem(s) in {}", problems.len(), file.display()));
+                    }
+                    println!("ok");
+                },
+                AgentCommands::Rank { capabilities, use_learned } => {
+                    let command = serde_json::json!({
+                        "action": "rank_agents",
+                        "params": {"capabilities": capabilities, "use_learned": use_learned}
+                    });
+                    let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+                        .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+                    for entry in response.get("ranking").and_then(|v| v.as_array()).into_iter().flatten() {
+                        println!("{:<30} {:.2}", entry["agent"]["id"].as_str().unwrap_or("?"), entry["score"].as_f64().unwrap_or(0.0));
+                    }
+                },
+                AgentCommands::History { agent, since_last, reset_cursor, profile, format } => {
+                    let tasks = client.fetch_tasks(agent.as_deref()).await?;
+                    
+                    let store = CursorStore::new(CursorStore::default_path());
+                    let cursor = format!("agents-history/{}", profile);
+                    if *reset_cursor {
+                        store.reset(&cursor)?;
+                    }
+                    let tasks = if *since_last {
+                        store.advance(&cursor, tasks, task_position)?
+                    } else {
+                        let mut finished: Vec<_> = tasks.into_iter().filter(|task| task_position(task).is_some()).collect();
+                        finished.sort_by_key(task_position);
+                        finished
+                    };
+                    
+                    let listing = serde_json::json!({"tasks": tasks});
+                    if *format == LineFormat::Ndjson {
+                        write_ndjson(&tasks, &mut std::io::stdout().lock())?;
+                    } else if cli.json {
+                        println!("{}", listing);
+                    } else if cli.porcelain {
+                        for row in porcelain_rows(PorcelainList::Tasks, &listing) {
+                            println!("{}", row);
+                        }
+                    } else {
+                        let text = |task: &serde_json::Value, key: &str| task[key].as_str().unwrap_or_default().to_string();
+                        let rows: Vec<Vec<String>> = tasks.iter()
+                            .map(|task| vec![
+                                text(task, "id"),
+                                text(task, "agent_id"),
+                                text(task, "status"),
+                                text(task, "completed_at"),
+                                text(task, "prompt").replace(['\n', '\r', '\t'], " "),
+                            ])
+                            .collect();
+                        let width = size().map_or(120, |(width, _)| width as usize);
+                        print!("{}", render_table(&["ID", "AGENT", "STATUS", "COMPLETED", "PROMPT"], &rows, width, ui.glyphs));
+                    }
+                },
+                AgentCommands::Wait { task_id, interval, timeout } => {
+                    let spinner = ProgressBar::new_spinner();
+                    if ui.glyphs.is_ascii() {
+                        spinner.set_style(ProgressStyle::default_spinner().tick_chars("|/-\\ "));
+                    }
+                    spinner.enable_steady_tick(Duration::from_millis(100));
+                    let outcome = wait_for_task(
+                        *interval,
+                        *timeout,
+                        || {
+                            let client = client.clone();
+                            let command = serde_json::json!({"action": "get_task", "params": {"task_id": task_id}});
+                            async move {
+                                serde_json::from_str(&client.send_command(&command.to_string()).await?)
+                                    .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))
+                            }
+                        },
+                        |status| spinner.set_message(format!("{} {}", task_id, status)),
+                    ).await;
+                    spinner.finish_and_clear();
+                    println!("{}", wait_result(task_id, &outcome?)?);
+                },
+                AgentCommands::Batch { file, keep_going } => {
+                    let batch = if file == Path::new("-") {
+                        std::io::read_to_string(std::io::stdin())
+                            .map_err(|e| anyhow::anyhow!("Failed to read batch from stdin: {}", e))?
+                    } else {
+                        std::fs::read_to_string(file)
+                            .map_err(|e| anyhow::anyhow!("Failed to read batch file {}: {}", file.display(), e))?
+                    };
+                    let result = submit_batch(&batch, |command| {
+                        let client = client.clone();
+                        async move {
+                            serde_json::from_str(&client.send_command(&command.to_string()).await?)
+                                .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))
+                        }
+                    }).await;
+                    if cli.json {
+                        println!("{}", serde_json::to_string_pretty(&result)?);
+                    } else {
+                        result.summary_lines().iter().for_each(|line| println!("{}", line));
+                    }
+                    result.check(*keep_going)?;
+                },
+                AgentCommands::Task { task_id, explain_schedule } => {
+                    let action = if *explain_schedule { "explain_schedule" } else { "get_task" };
+                    let command = serde_json::json!({"action": action, "params": {"task_id": task_id}});
+                    let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+                        .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+                    if let Some(error) = AnfError::from_response(&response) {
+                        return Err(error.into());
+                    }
+                    match response.get("reason").and_then(|v| v.as_str()) {
+                        Some(reason) => println!("{}: {}", task_id, reason),
+                        None => println!("{}", serde_json::to_string_pretty(&response["task"])?),
+                    }
+                },
+                AgentCommands::Trace { task_id } => {
+                    let command = serde_json::json!({"action": "trace", "params": {"task_id": task_id}});
+                    let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+                        .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+                    if let Some(error) = AnfError::from_response(&response) {
+                        return Err(error.into());
+                    }
+                    println!("🕒 {}", serde_json::to_string_pretty(&response["trace"])?);
+                },
+                AgentCommands::Artifacts { task_id } => {
+                    let command = serde_json::json!({
+                        "action": "list_artifacts",
+                        "params": {"task_id": task_id}
+                    });
+                    let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+                        .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+                    if let Some(error) = AnfError::from_response(&response) {
+                        return Err(error.into());
+                    }
+                    println!("📦 {}", serde_json::to_string_pretty(&response["artifacts"])?);
+                },
+                AgentCommands::Artifact { action: ArtifactCommands::Get { task_id, name } } => {
+                    let command = serde_json::json!({
+                        "action": "get_artifact",
+                        "params": {"task_id": task_id, "name": name}
+                    });
+                    let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+                        .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+                    if let Some(error) = AnfError::from_response(&response) {
+                        return Err(error.into());
+                    }
+                    let content: Vec<u8> = serde_json::from_value(response["content"].clone())
+                        .map_err(|e| anyhow::anyhow!("Unexpected artifact content: {}", e))?;
+                    std::io::stdout().write_all(&content)?;
                 },
             }
         },
 
-        Commands::Dashboard { agents: _, system: _, workflows: _ } => {
+        Commands::Dashboard { live: true, format, .. } => {
+            let stream = client.connect().await?;
+            follow_events(stream, *format, &mut std::io::stdout()).await?;
+        },
+
+        Commands::Dashboard { agents: _, system, workflows: _, live: false, format: _ } => {
             println!("📊 System Dashboard");
+            if *system {
+                let command = serde_json::json!({"action": "metrics", "params": {}});
+                let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+                    .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+                if let Some(error) = AnfError::from_response(&response) {
+                    return Err(error.into());
+                }
+                for line in latency_lines(&response["metrics"]["latency"]) {
+                    println!("{}", line);
+                }
+            }
             // Implement dashboard
         },
 
+        Commands::Init { force } => {
+            let root = anf::paths::anf_dir();
+            let report = anf::scaffold::init(&root, *force)?;
+            for path in &report.created {
+                println!("{}", ui.glyphs.render(&format!("✅ Created {}", path.display())));
+            }
+            for path in &report.skipped {
+                println!("  Kept existing {}", path.display());
+            }
+            if report.created.is_empty() {
+                println!("Nothing to do, {} is already set up", root.display());
+            }
+        },
+
+        Commands::Tasks => {
+            let command = serde_json::json!({"action": "list_tasks", "params": {}});
+            if cli.porcelain {
+                print_porcelain(&client, &command, PorcelainList::Tasks).await?;
+            } else {
+                println!("📋 {}", client.send_command(&command.to_string()).await?);
+            }
+        },
+
+        Commands::Task { action: TaskCommands::Status { task_id } } => {
+            let command = serde_json::json!({"action": "task_status", "params": {"task_id": task_id}});
+            let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+                .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+            if let Some(error) = AnfError::from_response(&response) {
+                return Err(error.into());
+            }
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&response["task"])?);
+            } else {
+                task_status_lines(&response["task"]).iter().for_each(|line| println!("{}", line));
+            }
+        },
+        
+        Commands::Task { action: TaskCommands::Result { task_id } } => {
+            let command = serde_json::json!({"action": "task_result", "params": {"task_id": task_id}});
+            let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+                .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+            if let Some(error) = AnfError::from_response(&response) {
+                return Err(error.into());
+            }
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&response["output"])?);
+            } else {
+                println!("{}", response["output"]["text"].as_str().unwrap_or_default());
+            }
+        },
+
         Commands::Quick => {
-            ui.interactive_mode(None).await?;
+            ui.interactive_mode(&client, None).await?;
+        },
+
+        Commands::Raw { command: Some(command), .. } => {
+            println!("{}", client.send_command(command).await?);
+        },
+        
+        Commands::Raw { command: None, .. } => {
+            let stream = client.connect().await?;
+            pipe_raw(tokio::io::stdin(), stream, &mut std::io::stdout()).await?;
+        },
+        
+        Commands::Chat { agent, pipe: true } => {
+            let stream = client.connect().await?;
+            pipe_chat(agent, tokio::io::stdin(), stream, &mut std::io::stdout()).await?;
+        },
+        
+        Commands::Chat { agent, pipe: false } => {
+            ui.interactive_mode(&client, Some(agent)).await?;
         },
 
-        Commands::Chat { agent } => {
-            ui.interactive_mode(Some(&agent)).await?;
+        Commands::Wave { action: WaveCommands::Split { agent, direction } } => {
+            let message = WaveIntegration::new(None).split_from_cli(agent, *direction).await?;
+            println!("{}", message);
         },
 
-        Commands::Run { workflow: _, parallel: _, save_as: _ } => {
-            println!("Running workflow...");
+        Commands::Run { workflow, dry_run: true, output_dir, .. } => {
+            let workflow = Workflow::load(workflow)?;
+            let agents = client.fetch_agent_ids().await?;
+            let problems = workflow.check(&agents, output_dir.as_deref());
+            if !problems.is_empty() {
+                for problem in &problems {
+                    eprintln!("{}: {}", workflow.name, problem);
+                }
+                return Err(anyhow::anyhow!("{} problem(s) in workflow '{}'", problems.len(), workflow.name));
+            }
+            println!("ok: {} steps would run", workflow.steps.len());
+        },
+        
+        Commands::Run { workflow, parallel: _, save_as: _, step_confirm, yes, output_dir, dry_run: false } => {
+            let workflow = Workflow::load(workflow)?;
+            let confirmation = match (*step_confirm, *yes) {
+                (_, true) => None,
+                (true, false) if !std::io::stdin().is_terminal() => {
+                    return Err(anyhow::anyhow!("--step-confirm needs a terminal; pass --yes to approve all steps"));
+                },
+                (true, false) => Some(StepConfirmation::default()),
+                (false, false) => None,
+            };
+            let produced = run_workflow(&client, &workflow, confirmation, cli.json).await?;
+            if let Some(dir) = output_dir {
+                print_collected(&produced, dir)?;
+            }
         },
 
+        Commands::Context { action: ContextCommands::Set { path, name: _ } } => {
+            let state = ActiveState { context: Some(path.clone()), ..active.clone() };
+            state.save()?;
+            println!("📁 Active context: {}", path.display());
+        },
+        
         Commands::Context { action: _ } => {
             println!("Context management...");
         },
         
-        Commands::Collaborate { task, agents, mode, topology } => {
-            let agent_list = agents
-                .as_deref()
-                .unwrap_or("backend-dev,security-auditor,performance-optimizer")
-                .split(',')
-                .collect::<Vec<&str>>();
+        Commands::Config { action: ConfigCommands::Show } => {
+            let title = format!("Effective configuration ({})", Config::path().display());
+            print_config_entries(&title, &resolved.entries(), cli.json)?;
+        },
+        
+        Commands::Cache { action: CacheCommands::List } => {
+            let response = send_cache_command(&client, "cache_list", serde_json::json!({})).await?;
+            let entries = response["entries"].as_array().cloned().unwrap_or_default();
+            if format == OutputFormat::Json {
+                println!("{}", json_report("cache list", "ok", serde_json::json!({"enabled": response["enabled"], "entries": entries})));
+            } else if response["enabled"] == false {
+                println!("Response cache is off; set agents.response_cache = true to turn it on");
+            } else if entries.is_empty() {
+                println!("No cached responses");
+            } else {
+                let now = chrono::Utc::now();
+                let rows: Vec<Vec<String>> = entries.iter()
+                    .map(|entry| {
+                        let stored_at = entry["stored_at"].as_str().and_then(|at| at.parse::<chrono::DateTime<chrono::Utc>>().ok());
+                        vec![
+                            entry["key"].as_str().unwrap_or_default().to_string(),
+                            entry["agent_id"].as_str().unwrap_or_default().to_string(),
+                            stored_at.map_or_else(|| "?".to_string(), |at| format_age((now - at).num_seconds())),
+                            format_size(entry["size"].as_u64().unwrap_or(0)),
+                        ]
+                    })
+                    .collect();
+                ui.print_table(&["KEY", "AGENT", "AGE", "SIZE"], &rows)?;
+            }
+        },
+        
+        Commands::Cache { action: CacheCommands::Clear { agent, older_than } } => {
+            let params = serde_json::json!({"agent_id": agent, "older_than_ms": older_than.map(|age| age.as_millis() as u64)});
+            let response = send_cache_command(&client, "cache_clear", params).await?;
+            if format == OutputFormat::Json {
+                println!("{}", json_report("cache clear", "ok", serde_json::json!({"enabled": response["enabled"], "removed": response["removed"]})));
+            } else {
+                println!("Removed {} cached responses", response["removed"].as_u64().unwrap_or(0));
+            }
+        },
+        
+        Commands::Cache { action: CacheCommands::Stats } => {
+            let response = send_cache_command(&client, "cache_stats", serde_json::json!({})).await?;
+            if format == OutputFormat::Json {
+                println!("{}", json_report("cache stats", "ok", serde_json::json!({"enabled": response["enabled"], "stats": response["stats"]})));
+            } else {
+                for line in cache_stats_lines(&response) {
+                    println!("{}", line);
+                }
+            }
+        },
+        
+        Commands::Daemon { action: DaemonCommands::Ping } => {
+            let ping = client.ping().await?;
+            println!("🏓 Daemon up {}s at {} │ {} agents loaded │ {} queued",
+                     ping.uptime_secs, client.socket_path, ping.agents_loaded, ping.queue_depth);
+        },
+        
+        Commands::Daemon { action: DaemonCommands::Bench { tasks, concurrency } } => {
+            let command = serde_json::json!({"action": "bench", "params": {"tasks": tasks, "concurrency": concurrency}});
+            let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+                .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+            if let Some(error) = AnfError::from_response(&response) {
+                return Err(error.into());
+            }
+            let report: BenchReport = serde_json::from_value(response["bench"].clone())
+                .map_err(|e| anyhow::anyhow!("Unexpected bench report: {}", e))?;
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                println!("⏱️  {} tasks at concurrency {} in {:.2}s", report.tasks, report.concurrency, report.elapsed_ms as f64 / 1000.0);
+                println!("   {:.1} tasks/sec │ p50 {}ms │ p99 {}ms │ max queue depth {}",
+                         report.throughput_per_sec, report.p50_ms, report.p99_ms, report.max_queue_depth);
+            }
+        },
+        
+        Commands::Daemon { action: DaemonCommands::Logs { follow, lines } } => {
+            let path = resolved.config.daemon.log_file.as_ref().map_or_else(anf::logfile::default_path, PathBuf::from);
+            if !path.exists() && !*follow {
+                return Err(AnfError::NotFound(format!("No daemon log at {}", path.display())).into());
+            }
+            let (mut follower, tail) = Follower::from_tail(&path, *lines)
+                .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+            print!("{}", tail);
+            if *follow {
+                let interrupted = tokio::signal::ctrl_c();
+                tokio::pin!(interrupted);
+                loop {
+                    tokio::select! {
+                        _ = &mut interrupted => break,
+                        _ = tokio::time::sleep(Duration::from_millis(250)) => {
+                            print!("{}", follower.poll()?);
+                            std::io::stdout().flush()?;
+                        },
+                    }
+                }
+            }
+        },
+        
+        Commands::Daemon { action: DaemonCommands::Config } => {
+            let command = serde_json::json!({"action": "get_config", "params": {}});
+            let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+                .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+            if let Some(error) = AnfError::from_response(&response) {
+                return Err(error.into());
+            }
+            let entries: Vec<ConfigEntry> = serde_json::from_value(response["config"].clone())
+                .map_err(|e| anyhow::anyhow!("Unexpected daemon config: {}", e))?;
+            print_config_entries(&format!("Daemon configuration ({})", client.socket_path), &entries, cli.json)?;
+        },
+        
+        Commands::Daemon { action: DaemonCommands::LogLevel { level } } => {
+            let command = match level {
+                Some(level) => serde_json::json!({"action": "set_log_level", "params": {"level": level}}),
+                None => serde_json::json!({"action": "get_log_level", "params": {}}),
+            };
+            let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+                .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+            if let Some(error) = AnfError::from_response(&response) {
+                return Err(error.into());
+            }
+            println!("📜 Log level: {}", response.get("level").and_then(|v| v.as_str()).unwrap_or("unknown"));
+        },
+        
+        Commands::Daemon { action: DaemonCommands::Compact } => {
+            let command = serde_json::json!({"action": "compact", "params": {}});
+            let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+                .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+            if let Some(error) = AnfError::from_response(&response) {
+                return Err(error.into());
+            }
+            println!("🧹 Reclaimed {}", format_size(response["reclaimed_bytes"].as_u64().unwrap_or(0)));
+        },
+        
+        Commands::Collaborate { list_modes: true, .. } => print_values::<CollaborationMode>(cli.json)?,
+        
+        Commands::Collaborate { task, agents, mode, topology, timeout, timeout_per_agent, list_modes: false, output_dir } => {
+            let task = task.as_deref().unwrap_or_default();
+            let registry = client.fetch_agent_ids().await.ok();
+            let agent_list = parse_agent_list(
+                agents.as_deref().unwrap_or("backend-typescript-architect,reviewer,performance-optimizer"),
+                registry.as_deref(),
+            )?;
+            let agent_refs: Vec<&str> = agent_list.iter().map(String::as_str).collect();
             
-            ui.show_collaboration_progress(task, &agent_list).await?;
+            ui.show_collaboration_progress(task, &agent_refs).await?;
             
-            // Simulate coordination process
-            tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
+            let run = run_members(&client, &agent_list, task, *timeout, *timeout_per_agent).await?;
             
-            println!("🎉 Collaboration completed successfully!");
-            println!("Mode: {}", mode.as_deref().unwrap_or("hybrid"));
-            println!("Topology: {}", topology.as_deref().unwrap_or("adaptive"));
+            if run.all_completed {
+                println!("🎉 Collaboration completed successfully!");
+            } else {
+                println!("⚠️  Collaboration finished with failed members");
+            }
+            println!("Mode: {}", mode.unwrap_or_default());
+            println!("Topology: {}", topology.unwrap_or_default());
+            if let Some(dir) = output_dir {
+                print_collected(&run.artifacts, dir)?;
+            }
         },
         
         Commands::Swarm { action } => {
             match action {
                 SwarmCommands::Create { id, topology, agents, task: _ } => {
+                    let registry = client.fetch_agent_ids().await.ok();
+                    let agents = parse_agent_list(&agents.join(","), registry.as_deref())?;
+                    
                     println!("🐛 Creating swarm: {}", id);
-                    println!("Topology: {}", topology.as_deref().unwrap_or("adaptive"));
+                    let topology = topology.unwrap_or_default();
+                    println!("Topology: {}", topology);
                     println!("Agents: {:?}", agents);
                     
-                    ui.display_swarm_status(id, topology.as_deref().unwrap_or("adaptive"), agents.len()).await?;
+                    ui.display_swarm_status(&SwarmStatus::new(id, &topology.to_string(), agents)).await?;
+                },
+                SwarmCommands::Topologies => print_values::<Topology>(cli.json)?,
+                SwarmCommands::List { detailed: _ } if cli.porcelain => {
+                    let command = serde_json::json!({"action": "swarm_list", "params": {}});
+                    print_porcelain(&client, &command, PorcelainList::Swarms).await?;
                 },
                 SwarmCommands::List { detailed } => {
                     if *detailed {
-                        ui.display_swarm_status("default-swarm", "hierarchical", 5).await?;
+                        ui.display_swarm_status(&SwarmStatus::new("default-swarm", "hierarchical", Vec::new())).await?;
                     } else {
+                        let command = serde_json::json!({"action": "swarm_list", "params": {}});
+                        let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+                            .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+                        if let Some(error) = AnfError::from_response(&response) {
+                            return Err(error.into());
+                        }
+                        let rows: Vec<Vec<String>> = response.get("swarms").and_then(|v| v.as_array()).into_iter().flatten()
+                            .map(|swarm| vec![
+                                swarm["id"].as_str().unwrap_or("?").to_string(),
+                                swarm["topology"].as_str().unwrap_or("?").to_string(),
+                                swarm_member_count(swarm).to_string(),
+                            ])
+                            .collect();
+                        let width = size().map_or(120, |(width, _)| width as usize);
                         println!("📋 Active Swarms:");
-                        println!("  • default-swarm (hierarchical) - 5 agents");
-                        println!("  • research-swarm (collective) - 8 agents");
+                        print!("{}", render_table(&["ID", "TOPOLOGY", "AGENTS"], &rows, width, ui.glyphs));
+                    }
+                },
+                SwarmCommands::Execute { swarm_id, task, dry_run: true, .. } => {
+                    let swarm = fetch_swarm_status(&client, swarm_id).await?;
+                    let plan = Plan::for_swarm(&swarm, task, &CostModel::default());
+                    if cli.json {
+                        println!("{}", serde_json::to_string_pretty(&plan)?);
+                    } else {
+                        println!("📝 Dry run for swarm {} ({} members, nothing submitted)", swarm.id, plan.tasks.len());
+                        for planned in &plan.tasks {
+                            println!("  • {:<25} ~{} tokens  ${:.4}  ~{}ms  {}",
+                                planned.agent, planned.estimated_tokens, planned.estimated_cost_usd,
+                                planned.estimated_latency_ms, planned.prompt);
+                        }
+                        println!("Total: ${:.4}, ~{}ms (members run concurrently)", plan.estimated_cost_usd, plan.estimated_latency_ms);
                     }
                 },
-                SwarmCommands::Execute { swarm_id, task, timeout: _ } => {
+                SwarmCommands::Execute { swarm_id, task, timeout, timeout_per_agent, dry_run: false, aggregate: strategy, explain } => {
                     println!("⚡ Executing task with swarm: {}", swarm_id);
                     println!("Task: {}", task);
                     
-                    ui.display_swarm_status(swarm_id, "adaptive", 4).await?;
+                    let swarm = fetch_swarm_status(&client, swarm_id).await?;
+                    let run = run_members(&client, &swarm.members, task, *timeout, *timeout_per_agent).await?;
+                    match aggregate(*strategy, &run.results) {
+                        Some(aggregation) if cli.json && *explain => println!("{}", serde_json::to_string_pretty(&aggregation)?),
+                        Some(aggregation) => aggregation_lines(&aggregation, *explain).iter().for_each(|line| println!("{}", line)),
+                        None => println!("No member finished, so there is no result to aggregate"),
+                    }
+                    ui.display_swarm_status(&swarm).await?;
                 },
                 SwarmCommands::Dissolve { swarm_id, save_results } => {
                     println!("🧹 Dissolving swarm: {}", swarm_id);
@@ -890,9 +3887,13 @@ pub async fn run_cli(cli: Cli) -> anyhow::Result<()> {
                 },
                 SwarmCommands::Status { swarm_id, live } => {
                     if *live {
-                        ui.display_swarm_status(swarm_id, "mesh", 6).await?;
+                        let swarm = fetch_swarm_status(&client, swarm_id).await?;
+                        ui.display_swarm_status(&swarm).await?;
                     } else {
-                        println!("📊 Swarm Status: {}", swarm_id);
+                        let swarm = fetch_swarm_status(&client, swarm_id).await?;
+                        println!("📊 Swarm Status: {} ({}) - {} agents, {} running, {} queued, {} done, {} failed",
+                                 swarm.id, swarm.topology, swarm.members.len(),
+                                 swarm.running, swarm.queued, swarm.completed, swarm.failed);
                     }
                 },
             }
@@ -902,7 +3903,7 @@ pub async fn run_cli(cli: Cli) -> anyhow::Result<()> {
             match action {
                 HiveCommands::Init { agents, capabilities: _ } => {
                     println!("🧠 Initializing hive nodes for {} agents", agents.len());
-                    ui.display_hive_status(agents.len(), 0, 0).await?;
+                    ui.display_hive_status(agents.len(), 0, 0, None).await?;
                 },
                 HiveCommands::Decide { question, options, method, timeout: _ } => {
                     println!("🗳️ Initiating collective decision:");
@@ -910,7 +3911,7 @@ pub async fn run_cli(cli: Cli) -> anyhow::Result<()> {
                     println!("Options: {:?}", options);
                     println!("Method: {}", method.as_deref().unwrap_or("consensus"));
                     
-                    ui.display_hive_status(5, 1, 12).await?;
+                    ui.display_hive_status(5, 1, 12, None).await?;
                 },
                 HiveCommands::Remember { content, memory_type, contributors, confidence: _ } => {
                     println!("📚 Storing collective memory:");
@@ -929,21 +3930,984 @@ pub async fn run_cli(cli: Cli) -> anyhow::Result<()> {
                     println!("  • Security audit checklist (confidence: 0.81)");
                 },
                 HiveCommands::Status { nodes, memory, decisions } => {
-                    if *nodes || *memory || *decisions {
-                        ui.display_hive_status(8, 15, 42).await?;
+                    let command = serde_json::json!({"action": "hive_status", "params": {"nodes": nodes, "memory": memory, "decisions": decisions}});
+                    let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+                        .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+                    if let Some(error) = AnfError::from_response(&response) {
+                        return Err(error.into());
+                    }
+                    warn_if_stale(&response);
+                    let count = |key: &str| response["status"][key].as_u64().unwrap_or(0) as usize;
+                    let (node_count, decision_count, memory_count) = (count("nodes"), count("decision_history"), count("memory_fragments"));
+                    if cli.json {
+                        println!("{}", serde_json::to_string_pretty(&response)?);
+                    } else if *nodes || *memory || *decisions {
+                        ui.display_hive_status(node_count, decision_count, memory_count, response["status"]["collective_confidence"].as_f64()).await?;
                     } else {
-                        println!("🧠 Hive Status: 8 nodes, 15 decisions, 42 memories");
+                        println!("🧠 Hive Status: {} nodes, {} decisions, {} memories", node_count, decision_count, memory_count);
                     }
                 },
             }
         },
     }
 
+    if let Some(name) = &cli.save_session {
+        save_session(&client, name, &ActiveState::load()).await?;
+    }
+
+    Ok(())
+}
+
+async fn save_session(client: &DaemonClient, name: &str, active: &ActiveState) -> anyhow::Result<()> {
+    let session = current_session(client, name, active).await?;
+    let path = session.save()?;
+    println!("💾 Saved session '{}' ({} agents) to {}", name, session.agents.len(), path.display());
+    Ok(())
+}
+
+/// `name`, holding the daemon's spawned agents and `active`'s selection
+async fn current_session(client: &DaemonClient, name: &str, active: &ActiveState) -> anyhow::Result<Session> {
+    let command = serde_json::json!({"action": "spawned_agents", "params": {}});
+    let response: serde_json::Value = serde_json::from_str(&client.send_command(&command.to_string()).await?)
+        .map_err(|e| anyhow::anyhow!("Unexpected daemon response: {}", e))?;
+    let agents = response.get("agents")
+        .and_then(|v| v.as_array())
+        .map(|agents| agents.iter().filter_map(|a| a.as_str().map(str::to_string)).collect())
+        .unwrap_or_default();
+    
+    Ok(Session {
+        name: name.to_string(),
+        agents,
+        context: active.context.clone(),
+        selected_agent: active.agent.clone(),
+        saved_at: chrono::Utc::now(),
+    })
+}
+
+async fn restore_session(client: &DaemonClient, name: &str) -> anyhow::Result<()> {
+    let session = Session::load(name)?;
+    respawn_session(client, &session).await?;
+    ActiveState { context: session.context.clone(), agent: session.selected_agent.clone() }.save()?;
+    println!("♻️  Restored session '{}' ({} agents)", name, session.agents.len());
+    Ok(())
+}
+
+async fn respawn_session(client: &DaemonClient, session: &Session) -> anyhow::Result<()> {
+    for agent_id in &session.agents {
+        client.spawn_agent(agent_id, None).await?;
+    }
     Ok(())
 }
 
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let cli = Cli::parse();
-    run_cli(cli).await
-}
\ No newline at end of file
+async fn main() -> std::process::ExitCode {
+    let matches = <Cli as clap::CommandFactory>::command().get_matches();
+    let cli = <Cli as clap::FromArgMatches>::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let (verbose, format) = (cli.verbose, OutputFormat::of(&cli));
+    match run_cli(cli).await {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(e) => {
+            let code = anf::error::exit_code(&e);
+            if format == OutputFormat::Json && e.downcast_ref::<Reported>().is_none() {
+                println!("{}", json_report(&command_name(&matches), "error", serde_json::json!({"error": e.to_string(), "exit_code": code})));
+            }
+            // The cause chain only with --verbose; otherwise one line
+            if verbose {
+                eprintln!("Error: {:?}", e);
+            } else {
+                eprintln!("Error: {}", e);
+            }
+            std::process::ExitCode::from(code)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_template_renders_response() {
+        let response = serde_json::json!({
+            "success": true,
+            "task_id": "6f1c2a9e-0000-4000-8000-000000000001",
+            "agent": "rust-pro",
+            "duration_ms": 1250,
+            "output": {
+                "text": "Use an arena", "format": "text", "artifacts": [],
+                "tokens": {"prompt_tokens": 120, "completion_tokens": 300, "counted_by": "heuristic"}
+            }
+        });
+        let template = OutputTemplate::parse("{agent}: {result} ({duration}, ${cost}) [{task_id}] {{raw}}").unwrap();
+        assert_eq!(
+            template.render(&response),
+            "rust-pro: Use an arena (1250ms, $0.0042) [6f1c2a9e-0000-4000-8000-000000000001] {raw}"
+        );
+        
+        let mut untallied = response.clone();
+        untallied["output"]["tokens"] = serde_json::Value::Null;
+        assert_eq!(OutputTemplate::parse("[{cost}]").unwrap().render(&untallied), "[]");
+        
+        let error = OutputTemplate::parse("{agent} {tokens}").unwrap_err().to_string();
+        assert!(error.contains("Unknown placeholder {tokens}"), "{}", error);
+    }
+
+    #[test]
+    fn test_prompt_file_read_verbatim() {
+        let path = std::env::temp_dir().join(format!("anf-prompt-{}.txt", std::process::id()));
+        let prompt = "Review this:\n\n  - keep indentation\n  - and blank lines\n";
+        std::fs::write(&path, prompt).unwrap();
+        
+        assert_eq!(read_prompt(None, Some(&path), std::io::empty()).unwrap(), prompt);
+        assert_eq!(read_prompt(None, Some(Path::new("-")), "from\nstdin".as_bytes()).unwrap(), "from\nstdin");
+        std::fs::remove_file(&path).unwrap();
+        
+        let missing = read_prompt(None, Some(&path), std::io::empty()).unwrap_err();
+        assert!(missing.to_string().contains("Failed to read prompt file"));
+        
+        assert!(Cli::try_parse_from(["anf", "ask", "hi", "--prompt-file", "p.txt"]).is_err());
+        assert!(Cli::try_parse_from(["anf", "ask", "--prompt-file", "p.txt", "--context", "src/lib.rs"]).is_ok());
+        assert!(Cli::try_parse_from(["anf", "ask"]).is_err());
+    }
+
+    #[test]
+    fn test_wave_split_direction_is_parsed() {
+        let cli = Cli::try_parse_from(["anf", "wave", "split", "rust-pro", "--direction", "horizontal"]).unwrap();
+        assert!(matches!(cli.command, Some(Commands::Wave {
+            action: WaveCommands::Split { direction: SplitDirection::Horizontal, .. },
+        })));
+
+        let err = Cli::try_parse_from(["anf", "wave", "split", "rust-pro", "--direction", "sideways"]).err().unwrap();
+        assert!(err.to_string().contains("Invalid split direction 'sideways'"));
+    }
+
+    #[test]
+    fn test_agents_list_porcelain_columns() {
+        let cli = Cli::try_parse_from(["anf", "agents", "list", "--porcelain"]).unwrap();
+        assert!(cli.porcelain);
+        assert!(matches!(cli.command, Some(Commands::Agents { action: AgentCommands::List { .. } })));
+
+        let response = serde_json::json!({"success": true, "agents": [
+            {"id": "rust-pro", "name": "Rust Expert", "agent_type": "development", "capabilities": ["rust"], "priority": 8},
+            {"id": "odd\tagent", "name": "Odd", "agent_type": "multi\nline", "priority": -1},
+        ]});
+        let rows = porcelain_rows(PorcelainList::Agents, &response);
+        assert_eq!(rows, vec!["rust-pro\tdevelopment\t8", "odd agent\tmulti line\t-1"]);
+        assert!(rows.iter().all(|row| !row.contains('\x1b') && row.split('\t').count() == 3));
+
+        let swarms = serde_json::json!({"swarms": [
+            {"id": "research", "topology": "mesh", "agents": ["a", "b"]},
+            {"id": "build", "topology": "star", "agents": 3, "status": "active"},
+        ]});
+        assert_eq!(porcelain_rows(PorcelainList::Swarms, &swarms), vec!["research\tmesh\t2", "build\tstar\t3"]);
+    }
+
+    #[tokio::test]
+    async fn test_pipe_chat_sends_each_line_as_a_turn() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let (client, daemon) = tokio::io::duplex(4096);
+        let fake_daemon = tokio::spawn(async move {
+            let (reader, mut writer) = tokio::io::split(daemon);
+            let mut lines = BufReader::new(reader).lines();
+            let open = lines.next_line().await.unwrap().unwrap();
+            assert!(open.contains("\"chat\"") && open.contains("incident-responder"));
+            writer.write_all(b"{\"success\":true}\n").await.unwrap();
+            let mut turn = 0;
+            while let Some(line) = lines.next_line().await.unwrap() {
+                turn += 1;
+                let reply = serde_json::json!({"turn": turn, "output": {"text": format!("{}: {}", turn, line)}});
+                writer.write_all((reply.to_string() + "\n").as_bytes()).await.unwrap();
+            }
+            turn
+        });
+
+        let mut out = Vec::new();
+        let turns = pipe_chat("incident-responder", &b"disk full\noom\nrecovered\n"[..], client, &mut out).await.unwrap();
+        assert_eq!(turns, 3);
+        assert_eq!(String::from_utf8(out).unwrap(), "1: disk full\n2: oom\n3: recovered\n");
+        assert_eq!(fake_daemon.await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_pipe_raw_correlates_replies_in_order() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let (client, daemon) = tokio::io::duplex(4096);
+        let fake_daemon = tokio::spawn(async move {
+            let (reader, mut writer) = tokio::io::split(daemon);
+            let mut lines = BufReader::new(reader).lines();
+            assert_eq!(lines.next_line().await.unwrap().unwrap(), "batch");
+            writer.write_all(b"{\"success\":true,\"batch\":true}\n").await.unwrap();
+            let mut received = 0;
+            while let Some(line) = lines.next_line().await.unwrap() {
+                received += 1;
+                let command: serde_json::Value = serde_json::from_str(&line).unwrap();
+                let reply = serde_json::json!({"success": true, "id": command["id"], "echo": command["action"]});
+                writer.write_all((reply.to_string() + "\n").as_bytes()).await.unwrap();
+            }
+            received
+        });
+
+        let input = b"{\"action\": \"list_agents\"}\n{\"id\": \"mine\", \"action\": \"list_tasks\"}\nnot json\n{\"action\": \"spawned_agents\"}\n";
+        let mut out = Vec::new();
+        assert_eq!(pipe_raw(&input[..], client, &mut out).await.unwrap(), 4);
+        let replies: Vec<serde_json::Value> = String::from_utf8(out).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        let correlated: Vec<(serde_json::Value, serde_json::Value)> = replies.iter().map(|r| (r["id"].clone(), r["echo"].clone())).collect();
+        assert_eq!(correlated, vec![
+            (serde_json::json!(1), serde_json::json!("list_agents")),
+            (serde_json::json!("mine"), serde_json::json!("list_tasks")),
+            (serde_json::json!(3), serde_json::Value::Null),
+            (serde_json::json!(4), serde_json::json!("spawned_agents")),
+        ]);
+        assert!(replies[2]["error"].as_str().unwrap().starts_with("Invalid JSON on line 3"));
+        assert_eq!(fake_daemon.await.unwrap(), 3);
+    }
+
+    #[test]
+    fn test_ascii_header_box_is_plain_ascii() {
+        let ui = TerminalUI::new().with_encoding(OutputEncoding::Ascii);
+        let header = ui.header_line("Swarm: research (mesh)", 40);
+        let status = ui.box_line("Agents: 3 │ Running: 1 │ Efficiency: 80%", 48);
+        let controls = ui.glyphs.render("└──────\n[Enter] Continue │ [Ctrl+C] Interrupt\n🤖 rust-pro ✓\n");
+        
+        for line in [&header, &status, &controls] {
+            assert!(line.is_ascii(), "{:?}", line);
+        }
+        assert_eq!(header, format!("+- Swarm: research (mesh) {}\n", "-".repeat(14)));
+        assert!(status.starts_with("| Agents: 3 | Running: 1 | Efficiency: 80%"));
+        assert!(controls.ends_with("rust-pro [x]\n"));
+    }
+
+    #[test]
+    fn test_header_at_width_one_is_clamped_and_elided() {
+        let ui = TerminalUI::new().with_encoding(OutputEncoding::Utf8);
+        let header = ui.header_line("Swarm: research (mesh)", 1);
+        assert_eq!(header, "┌─ Swarm: researc… ─\n");
+        assert_eq!(header.trim_end().chars().count(), MIN_WIDTH);
+        
+        let status = ui.box_line("Agents: 3 │ Running: 1", 0);
+        assert_eq!(status.trim_end().chars().count(), MIN_WIDTH);
+        assert!(status.ends_with("… │\n"));
+        assert_eq!(ui.header_line("", 0), format!("┌─  {}\n", "─".repeat(16)));
+    }
+    
+    #[test]
+    fn test_table_truncates_long_cells_and_keeps_columns_aligned() {
+        let rows = vec![
+            vec!["rust-pro".to_string(), "Rust Expert".to_string(), "development".to_string()],
+            vec!["backend-typescript-architect-mk2".to_string(), "Backend".to_string(), "development".to_string()],
+        ];
+        let table = render_table(&["ID", "NAME", "CATEGORY"], &rows, 40, Glyphs::new(OutputEncoding::Utf8));
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines, vec![
+            "ID           │ NAME        │ CATEGORY",
+            "rust-pro     │ Rust Expert │ development",
+            "backend-typ… │ Backend     │ development",
+        ]);
+        let dividers = |line: &str| line.chars().enumerate().filter(|(_, c)| *c == '│').map(|(i, _)| i).collect::<Vec<_>>();
+        assert!(lines.iter().all(|line| dividers(line) == dividers(lines[0]) && line.chars().count() <= 40));
+        
+        // Wide enough: nothing is cut
+        assert!(!render_table(&["ID", "NAME", "CATEGORY"], &rows, 120, Glyphs::new(OutputEncoding::Utf8)).contains('…'));
+        
+        let ascii = render_table(&["ID", "NAME", "CATEGORY"], &rows, 40, Glyphs::new(OutputEncoding::Ascii));
+        assert_eq!(ascii.lines().last(), Some("backend-t... | Backend     | development"));
+    }
+    
+    #[test]
+    fn test_compact_agent_status_is_one_plain_line() {
+        let cli = Cli::try_parse_from(["anf", "agents", "info", "rust-pro", "--compact"]).unwrap();
+        assert!(cli.compact);
+
+        let ui = TerminalUI::new().with_encoding(OutputEncoding::Utf8).with_compact(true);
+        let load = serde_json::json!({
+            "running_tasks": 1, "queued_tasks": 3, "max_concurrent_tasks": 2, "available_memory": 512 * 1024 * 1024, "throttled": false
+        });
+        let line = ui.compact_agent_line("rust-pro", "busy", Some(&load));
+        assert_eq!(line, "rust-pro | busy | tasks 1/2 | queue 3 | mem 512MB free");
+        assert_eq!(ui.compact_agent_line("rust-pro", "Processing", None), "rust-pro | Processing");
+        let unlimited = serde_json::json!({"running_tasks": 0, "queued_tasks": 0, "max_concurrent_tasks": 2, "available_memory": null});
+        assert_eq!(ui.compact_agent_line("coder", "idle", Some(&unlimited)), "coder | idle | tasks 0/2 | queue 0 | mem no limit");
+        assert_eq!(ui.compact_hive_line(3, 1, 7, Some(0.5)), "hive | nodes 3 | decisions 1 | memory 7 | confidence 50%");
+        assert_eq!(ui.compact_hive_line(0, 0, 0, None), "hive | nodes 0 | decisions 0 | memory 0 | confidence n/a");
+        assert!(!line.contains('\n'));
+        assert!(!line.chars().any(|c| ('\u{2500}'..='\u{257f}').contains(&c) || c == '▓' || c == '░'), "{}", line);
+    }
+
+    #[tokio::test]
+    async fn test_pooled_client_reuses_at_most_pool_size_connections() {
+        let socket = std::env::temp_dir().join(format!("anf-pool-{}.sock", uuid::Uuid::new_v4()));
+        let listener = tokio::net::UnixListener::bind(&socket).unwrap();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let counter = accepted.clone();
+        let fake_daemon = tokio::spawn(async move {
+            let mut open = Vec::new();
+            while let Ok((mut stream, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                open.push(tokio::spawn(async move {
+                    while let Ok(Some(command)) = framing::read_frame(&mut stream).await {
+                        framing::write_frame(&mut stream, &command).await.unwrap();
+                    }
+                }));
+            }
+        });
+
+        let client = DaemonClient::new(socket.to_string_lossy().into_owned()).with_pool(4);
+        let handles: Vec<_> = (0..20)
+            .map(|i| {
+                let client = client.clone();
+                tokio::spawn(async move { client.send_command(&format!("task {}", i)).await })
+            })
+            .collect();
+        for handle in handles {
+            handle.await.unwrap().unwrap();
+        }
+
+        let opened = client.pool.as_ref().unwrap().opened();
+        assert!((1..=4).contains(&opened), "opened {}", opened);
+        assert!(accepted.load(Ordering::SeqCst) <= 4);
+        fake_daemon.abort();
+        std::fs::remove_file(socket).unwrap();
+    }
+
+    #[test]
+    fn test_explain_lists_member_scores_and_marks_the_winner() {
+        let response = serde_json::json!({"tasks": [
+            {"agent_id": "coder", "error": null, "output": {"text": "use a Vec", "confidence": 0.6}},
+            {"agent_id": "rust-pro", "error": null, "output": {"text": "use a VecDeque\nbecause", "confidence": 0.9}},
+            {"agent_id": "reviewer", "error": "timed out", "output": null}
+        ]});
+        let results = member_results(&response);
+        assert_eq!(results.len(), 2);
+        let aggregation = aggregate(AggregationStrategy::BestByConfidence, &results).unwrap();
+        assert_eq!(aggregation_lines(&aggregation, true), vec![
+            "🏆 Result (best_by_confidence) from rust-pro:",
+            "use a VecDeque\nbecause",
+            "Candidates:",
+            "    coder                     score 0.6    use a Vec",
+            "  → rust-pro                  score 0.9    use a VecDeque",
+            "Why: rust-pro reported the highest confidence (0.90)",
+        ]);
+        assert_eq!(aggregation_lines(&aggregation, false).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_mixed_batch_separates_submitted_from_rejected() {
+        let batch = "# nightly reviews\n{\"agent_id\": \"coder\", \"prompt\": \"a\"}\n\n{\"agent_id\": \"ghost\", \"prompt\": \"b\"}\nnot json\n{\"agent_id\": \"coder\", \"prompt\": \"c\"}\n";
+        let submitted = std::sync::Mutex::new(Vec::new());
+        let result = submit_batch(batch, |command| {
+            let agent = command["params"]["agent_id"].as_str().unwrap().to_string();
+            let reply = match agent.as_str() {
+                "ghost" => serde_json::json!({"error": "Agent ghost not found"}),
+                _ => serde_json::json!({"success": true, "task_id": uuid::Uuid::new_v4()}),
+            };
+            submitted.lock().unwrap().push(agent);
+            async move { Ok(reply) }
+        }).await;
+
+        assert_eq!(result.submitted.iter().map(|(line, _)| *line).collect::<Vec<_>>(), vec![2, 6]);
+        assert_eq!(result.rejected[0], (4, "Agent ghost not found".to_string()));
+        assert_eq!(result.rejected[1].0, 5);
+        assert!(result.rejected[1].1.starts_with("invalid JSON"));
+        assert_eq!(submitted.lock().unwrap().len(), 3);
+        let error = result.check(false).unwrap_err();
+        assert_eq!((anf::error::exit_code(&error), error.to_string()), (1, "2 of 4 tasks rejected".to_string()));
+        assert!(result.check(true).is_ok());
+        assert_eq!(result.summary_lines()[0], "📦 Submitted 2 of 4 tasks");
+        assert!(BatchResult::default().check(false).is_ok());
+    }
+
+    #[test]
+    fn test_agent_info_shows_description_only_when_present() {
+        let mut agent = serde_json::json!({
+            "id": "rust-pro", "name": "Rust Expert", "agent_type": "development",
+            "capabilities": ["rust", "systems"], "models": ["sonnet"],
+            "description": "Reviews and writes idiomatic, safe Rust", "version": "1.2.0"
+        });
+        assert_eq!(agent_info_lines(&agent), vec![
+            "Rust Expert (rust-pro)",
+            "Reviews and writes idiomatic, safe Rust",
+            "Type: development",
+            "Capabilities: rust, systems",
+            "Models: sonnet",
+            "Version: 1.2.0",
+            "Author: unknown",
+        ]);
+        
+        agent["description"] = serde_json::Value::Null;
+        let lines = agent_info_lines(&agent);
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[1], "Type: development");
+    }
+    
+    #[tokio::test]
+    async fn test_json_reports_parse_and_name_their_command() {
+        let matches = <Cli as clap::CommandFactory>::command().try_get_matches_from(["anf", "agents", "list", "--json"]).unwrap();
+        assert_eq!(command_name(&matches), "agents list");
+        let cli = <Cli as clap::FromArgMatches>::from_arg_matches(&matches).unwrap();
+        assert_eq!(OutputFormat::of(&cli), OutputFormat::Json);
+        assert_eq!(OutputFormat::of(&Cli::try_parse_from(["anf", "agents", "list"]).unwrap()), OutputFormat::Human);
+        let reparse = |report: serde_json::Value| serde_json::from_str::<serde_json::Value>(&report.to_string()).unwrap();
+        
+        let client = DaemonClient::embedded(EmbeddedDaemon::start(AgentPool::new()).await.unwrap());
+        let agents = client.fetch_agents(&AgentQuery::default()).await.unwrap();
+        let listed = reparse(json_report("agents list", "ok", serde_json::json!({"agents": agents})));
+        assert_eq!((listed["command"].as_str(), listed["status"].as_str()), (Some("agents list"), Some("ok")));
+        assert_eq!(listed["agents"][0]["id"], "backend-typescript-architect");
+        
+        let spawned = client.spawn_agent("rust-pro", None).await.unwrap();
+        let spawn = reparse(json_report("spawn", "ok", serde_json::json!({"agent": "rust-pro", "message": spawned.message})));
+        assert_eq!(spawn["agent"], "rust-pro");
+        assert_eq!(spawn["status"], "ok");
+        
+        let options = AskOptions { agent: Some("coder".to_string()), ..AskOptions::default() };
+        let answered = reparse(ask_report(Some("coder"), &ask_once(&client, "explain borrowing", &options).await.unwrap()));
+        assert_eq!((answered["command"].as_str(), answered["status"].as_str()), (Some("ask"), Some("ok")));
+        assert_eq!(answered["output"], "Processing: explain borrowing");
+        assert_eq!(answered["agent"], "coder");
+        assert!(answered["task_id"].is_string());
+        
+        let failed = reparse(ask_report(Some("nobody"), r#"{"error": "Agent nobody not found"}"#));
+        assert_eq!((failed["status"].as_str(), failed["agent"].as_str()), (Some("error"), Some("nobody")));
+        assert_eq!(failed["error"], "Agent nobody not found");
+        // A bare `ask:` answer still comes out as an object
+        let plain = reparse(ask_report(None, "just text"));
+        assert_eq!((plain["output"].as_str(), plain["agent"].is_null()), (Some("just text"), true));
+    }
+    
+    #[test]
+    fn test_agents_create_takes_a_name_or_a_config_file() {
+        let cli = Cli::try_parse_from(["anf", "agents", "create", "--config", "rust-tiny.json"]).unwrap();
+        assert!(matches!(cli.command, Some(Commands::Agents { action: AgentCommands::Create { name: None, config: Some(_), .. } })));
+        assert!(Cli::try_parse_from(["anf", "agents", "create", "Rust Lite", "--base", "rust-pro"]).is_ok());
+        assert!(Cli::try_parse_from(["anf", "agents", "create"]).is_err());
+        assert!(Cli::try_parse_from(["anf", "agents", "create", "Rust Lite", "--config", "rust-tiny.json"]).is_err());
+    }
+    
+    #[test]
+    fn test_agentless_ask_names_the_flags_it_would_drop() {
+        let cli = Cli::try_parse_from(["anf", "ask", "--model", "opus", "--system", "Be terse.", "--context-redact", "hi"]).unwrap();
+        assert_eq!(agent_only_flags(cli.command.as_ref().unwrap()), vec!["--context-redact", "--system", "--model"]);
+        let cli = Cli::try_parse_from(["anf", "ask", "hi"]).unwrap();
+        assert!(agent_only_flags(cli.command.as_ref().unwrap()).is_empty());
+    }
+    
+    #[tokio::test]
+    async fn test_ask_timeout_reaches_the_daemon() {
+        let cli = Cli::try_parse_from(["anf", "ask", "--agent", "coder", "--timeout", "20ms", "--on-timeout", "fail", "hi"]).unwrap();
+        let Some(Commands::Ask { timeout, on_timeout, .. }) = cli.command else { panic!("not an ask") };
+        assert_eq!(timeout, Some(Duration::from_millis(20)));
+        
+        // The simulated agent takes 100ms
+        let client = DaemonClient::embedded(EmbeddedDaemon::start(AgentPool::new()).await.unwrap());
+        let options = AskOptions { agent: Some("coder".to_string()), timeout, on_timeout, ..AskOptions::default() };
+        let response = ask_once(&client, "explain lifetimes", &options).await.unwrap();
+        let reply: serde_json::Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(reply["error"], "timed out after 20ms");
+        assert_eq!(reply_error(&response).unwrap().exit_code(), 5);
+    }
+    
+    #[tokio::test]
+    async fn test_ask_with_overrides_is_queued_and_answers_like_exec() {
+        let cli = Cli::try_parse_from(["anf", "ask", "--agent", "coder", "--override", "priority=10", "--agent-config-override", "max_concurrent_tasks=4", "hi"]).unwrap();
+        let Some(Commands::Ask { overrides, .. }) = cli.command else { panic!("not an ask") };
+        assert_eq!(AgentOverrides::parse(&overrides).unwrap().max_concurrent_tasks, Some(4));
+        
+        let client = DaemonClient::embedded(EmbeddedDaemon::start(AgentPool::new()).await.unwrap());
+        let options = AskOptions {
+            agent: Some("coder".to_string()),
+            overrides: AgentOverrides::parse(&overrides).unwrap(),
+            queue_timeout: Duration::from_secs(5),
+            ..AskOptions::default()
+        };
+        let response = ask_once(&client, "explain lifetimes", &options).await.unwrap();
+        assert_eq!(IterationOutput::from_response(&response).text, "Processing: explain lifetimes");
+        let report = ask_report(Some("coder"), &response);
+        assert_eq!((report["status"].as_str(), report["agent"].as_str()), (Some("ok"), Some("coder")));
+    }
+    
+    #[tokio::test]
+    async fn test_queued_ask_times_out_on_its_own_timeout_and_cancels_the_task() {
+        let client = DaemonClient::embedded(EmbeddedDaemon::start(AgentPool::new()).await.unwrap());
+        let options = AskOptions {
+            agent: Some("coder".to_string()),
+            timeout: Some(Duration::from_millis(1)),
+            overrides: AgentOverrides::parse(&["priority=10".to_string()]).unwrap(),
+            queue_timeout: Duration::from_secs(60),
+            ..AskOptions::default()
+        };
+        let started = std::time::Instant::now();
+        let err = ask_once(&client, "explain lifetimes", &options).await.unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        let error = err.downcast_ref::<AnfError>().unwrap();
+        assert_eq!(error.exit_code(), 5);
+        assert!(error.to_string().ends_with(", cancelled)"), "{}", error);
+        
+        let task_id = client.fetch_tasks(None).await.unwrap()[0]["id"].as_str().unwrap().to_string();
+        let outcome = wait_for_task(Duration::from_millis(20), Duration::from_secs(5), || {
+            let command = serde_json::json!({"action": "get_task", "params": {"task_id": task_id}});
+            let client = &client;
+            async move { Ok(serde_json::from_str(&client.send_command(&command.to_string()).await?)?) }
+        }, |_| {}).await.unwrap();
+        assert!(matches!(outcome, WaitOutcome::Finished(ref task) if task["status"] == "Cancelled"));
+    }
+    
+    #[tokio::test]
+    async fn test_restored_session_spawns_its_agents_in_a_fresh_pool() {
+        let dir = std::env::temp_dir().join(format!("anf-sessions-{}", uuid::Uuid::new_v4()));
+        let saved = AgentPool::new();
+        let client = DaemonClient::embedded(EmbeddedDaemon::start(saved.clone()).await.unwrap());
+        for agent_id in ["rust-pro", "coder"] {
+            client.spawn_agent(agent_id, None).await.unwrap();
+        }
+        let active = ActiveState { context: None, agent: Some("coder".to_string()) };
+        current_session(&client, "pairing", &active).await.unwrap().save_in(&dir).unwrap();
+        
+        let fresh = AgentPool::new();
+        let client = DaemonClient::embedded(EmbeddedDaemon::start(fresh.clone()).await.unwrap());
+        let session = Session::load_from(&dir, "pairing").unwrap();
+        assert_eq!(session.selected_agent.as_deref(), Some("coder"));
+        respawn_session(&client, &session).await.unwrap();
+        assert_eq!(fresh.spawned_agents().await, vec!["coder", "rust-pro"]);
+        assert_eq!(fresh.spawned_agents().await, saved.spawned_agents().await);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[tokio::test]
+    async fn test_no_daemon_agents_list_reads_the_registry_without_a_socket() {
+        let cli = Cli::try_parse_from(["anf", "--no-daemon", "agents", "list"]).unwrap();
+        assert!(cli.no_daemon);
+        assert!(Cli::try_parse_from(["anf", "--no-daemon", "--socket", "/tmp/x.sock", "agents", "list"]).is_err());
+        
+        let client = DaemonClient::embedded(EmbeddedDaemon::start(AgentPool::new()).await.unwrap());
+        let rows = client.fetch_agent_rows(&AgentQuery::default()).await.unwrap();
+        let ids: Vec<&str> = rows.iter().map(|row| row[0].as_str()).collect();
+        assert!(ids.contains(&"coder") && ids.contains(&"rust-pro"), "{:?}", ids);
+        assert!(ids.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(rows.iter().find(|row| row[0] == "performance-optimizer").unwrap()[3], "10");
+        
+        // Submitted work runs in-process too
+        let submit = serde_json::json!({"action": "submit_task", "params": {"agent_id": "coder", "prompt": "hi"}});
+        let submitted: serde_json::Value = serde_json::from_str(&client.send_command(&submit.to_string()).await.unwrap()).unwrap();
+        let get = || serde_json::json!({"action": "task_status", "params": {"task_id": submitted["task_id"]}}).to_string();
+        let status = wait_for_task(Duration::from_millis(20), Duration::from_secs(5), || {
+            let client = client.clone();
+            async move { Ok(serde_json::from_str(&client.send_command(&get()).await?)?) }
+        }, |_| {}).await.unwrap();
+        assert!(matches!(status, WaitOutcome::Finished(ref task) if task["status"] == "Completed"));
+        
+        let swarm = serde_json::json!({"action": "swarm_list", "params": {}});
+        let refused: serde_json::Value = serde_json::from_str(&client.send_command(&swarm.to_string()).await.unwrap()).unwrap();
+        let error = AnfError::from_response(&refused).unwrap();
+        assert!(matches!(error, AnfError::Usage(_)) && error.to_string().contains("without --no-daemon"), "{}", error);
+        assert!(client.connect().await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_ndjson_history_is_one_object_per_matching_task() {
+        let cli = Cli::try_parse_from(["anf", "agents", "history", "--agent", "coder", "--format", "ndjson"]).unwrap();
+        assert!(matches!(cli.command, Some(Commands::Agents { action: AgentCommands::History { format: LineFormat::Ndjson, .. } })));
+        
+        let client = DaemonClient::embedded(EmbeddedDaemon::start(AgentPool::new()).await.unwrap());
+        for (agent, prompt) in [("coder", "one"), ("rust-pro", "two"), ("coder", "three\nwith a newline")] {
+            let submit = serde_json::json!({"action": "submit_task", "params": {"agent_id": agent, "prompt": prompt}});
+            client.send_command(&submit.to_string()).await.unwrap();
+        }
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        while client.fetch_tasks(None).await.unwrap().iter().any(|task| task_position(task).is_none()) {
+            assert!(tokio::time::Instant::now() < deadline, "tasks never finished");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        
+        let mut out = Vec::new();
+        let tasks = client.fetch_tasks(Some("coder")).await.unwrap();
+        assert_eq!(write_ndjson(&tasks, &mut out).unwrap(), 2);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.ends_with('\n'));
+        let lines: Vec<&str> = out.split_terminator('\n').collect();
+        assert_eq!(lines.len(), 2);
+        for line in lines {
+            let task: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert!(task.is_object());
+            assert_eq!(task["agent_id"], "coder");
+        }
+        
+        // Live mode passes each event through as its own line
+        let (ours, theirs) = tokio::io::duplex(4096);
+        let daemon = tokio::spawn(async move {
+            use tokio::io::{AsyncReadExt, AsyncWriteExt};
+            let mut theirs = theirs;
+            let mut opening = [0; 10];
+            theirs.read_exact(&mut opening).await.unwrap();
+            assert_eq!(&opening, b"subscribe\n");
+            theirs.write_all(b"{\"success\":true,\"subscribed\":true}\n{\"event\":\"agent_busy\",\"agent_id\":\"coder\"}\n{\"event\":\"lagged\",\"skipped\":3}\n").await.unwrap();
+        });
+        let mut streamed = Vec::new();
+        assert_eq!(follow_events(ours, LineFormat::Ndjson, &mut streamed).await.unwrap(), 2);
+        daemon.await.unwrap();
+        assert_eq!(String::from_utf8(streamed).unwrap(), "{\"agent_id\":\"coder\",\"event\":\"agent_busy\"}\n{\"event\":\"lagged\",\"skipped\":3}\n");
+        assert_eq!(event_line(&serde_json::json!({"event": "lagged", "skipped": 3})), "fell behind, missed 3 events");
+    }
+    
+    #[tokio::test]
+    async fn test_cache_clear_older_than_zero_empties_and_stats_count_hits() {
+        let client = DaemonClient::embedded(EmbeddedDaemon::start(AgentPool::new().with_response_cache(Some(Default::default()))).await.unwrap());
+        let options = |file: &str| AskOptions {
+            agent: Some("coder".to_string()),
+            context: serde_json::json!({"content": file}),
+            ..AskOptions::default()
+        };
+        // The same prompt about another file is a miss, not the first file's answer
+        for (prompt, file) in [("review this", "a.rs"), ("review this", "a.rs"), ("explain lifetimes", "a.rs"), ("review this", "a.rs"), ("review this", "b.rs")] {
+            let response = ask_once(&client, prompt, &options(file)).await.unwrap();
+            assert_eq!(IterationOutput::from_response(&response).text, format!("Processing: {}", prompt));
+        }
+        
+        let stats = send_cache_command(&client, "cache_stats", serde_json::json!({})).await.unwrap();
+        assert_eq!((stats["stats"]["hits"].as_u64(), stats["stats"]["misses"].as_u64()), (Some(2), Some(3)));
+        assert_eq!(stats["stats"]["hit_ratio"].as_f64(), Some(0.4));
+        assert_eq!(cache_stats_lines(&stats), vec!["Entries: 3 (213B)", "Lookups: 2 hits, 3 misses, 40.0% hit ratio"]);
+        let listed = send_cache_command(&client, "cache_list", serde_json::json!({})).await.unwrap();
+        assert!(listed["entries"].as_array().unwrap().iter().all(|entry| entry["agent_id"] == "coder"));
+        
+        let cli = Cli::try_parse_from(["anf", "cache", "clear", "--older-than", "0s"]).unwrap();
+        let Some(Commands::Cache { action: CacheCommands::Clear { agent: None, older_than } }) = cli.command else { panic!("not a cache clear") };
+        assert_eq!(older_than, Some(Duration::ZERO));
+        let params = serde_json::json!({"older_than_ms": older_than.map(|age| age.as_millis() as u64)});
+        let cleared = send_cache_command(&client, "cache_clear", params).await.unwrap();
+        assert_eq!(cleared["removed"], 3);
+        let listed = send_cache_command(&client, "cache_list", serde_json::json!({})).await.unwrap();
+        assert_eq!(listed["entries"], serde_json::json!([]));
+        // Clearing forgets answers, not how often the cache was useful
+        let stats = send_cache_command(&client, "cache_stats", serde_json::json!({})).await.unwrap();
+        assert_eq!((stats["stats"]["entries"].as_u64(), stats["stats"]["hits"].as_u64()), (Some(0), Some(2)));
+    }
+    
+    #[tokio::test]
+    async fn test_list_agents_renders_what_the_daemon_loaded() {
+        let pool = AgentPool::new();
+        let client = DaemonClient::embedded(EmbeddedDaemon::start(pool.clone()).await.unwrap());
+        let ui = TerminalUI::new();
+        let listed = ui.list_agents(&client, &AgentQuery::default()).await.unwrap();
+        assert_eq!(listed, pool.list_agents(None).await.len());
+        
+        let development = AgentQuery { category: Some("development".to_string()), ..AgentQuery::default() };
+        assert_eq!(ui.list_agents(&client, &development).await.unwrap(), pool.list_agents(Some("development")).await.len());
+        // Nothing is running, so every agent has a free slot
+        let available = AgentQuery { available: true, ..AgentQuery::default() };
+        assert_eq!(ui.list_agents(&client, &available).await.unwrap(), listed);
+        
+        let active = AgentQuery { active: true, ..AgentQuery::default() };
+        assert_eq!(ui.list_agents(&client, &active).await.unwrap(), 0);
+        client.spawn_agent("rust-pro", None).await.unwrap();
+        let rows = client.fetch_agent_rows(&active).await.unwrap();
+        assert_eq!(rows.iter().map(|row| row[0].as_str()).collect::<Vec<_>>(), vec!["rust-pro"]);
+    }
+    
+    #[test]
+    fn test_task_status_lines_show_only_reached_milestones() {
+        let cli = Cli::try_parse_from(["anf", "task", "status", "42"]).unwrap();
+        assert!(matches!(cli.command, Some(Commands::Task { action: TaskCommands::Status { ref task_id } }) if task_id == "42"));
+        
+        let mut task = serde_json::json!({
+            "id": "42", "agent_id": "coder", "status": "Queued",
+            "created_at": "2026-01-01T00:00:00Z", "started_at": null, "completed_at": null
+        });
+        assert_eq!(task_status_lines(&task), vec!["Task 42: Queued", "Agent: coder", "Created: 2026-01-01T00:00:00Z"]);
+        
+        task["status"] = "Failed".into();
+        task["started_at"] = "2026-01-01T00:00:01Z".into();
+        task["completed_at"] = "2026-01-01T00:00:02Z".into();
+        task["status_detail"] = "timeout".into();
+        task["error"] = "timed out after 50ms".into();
+        assert_eq!(task_status_lines(&task)[3..], [
+            "Started: 2026-01-01T00:00:01Z",
+            "Finished: 2026-01-01T00:00:02Z",
+            "Reason: timeout (timed out after 50ms)",
+        ]);
+        
+        task["status"] = "Completed".into();
+        task["status_detail"] = serde_json::Value::Null;
+        task["error"] = serde_json::Value::Null;
+        task["output"] = serde_json::json!({"tokens": {"prompt_tokens": 6, "completion_tokens": 8, "counted_by": "heuristic"}});
+        assert_eq!(task_status_lines(&task)[5], "Tokens: 6 prompt, 8 completion (estimated by heuristic)");
+        task["output"]["tokens"]["counted_by"] = serde_json::Value::Null;
+        assert_eq!(task_status_lines(&task)[5], "Tokens: 6 prompt, 8 completion (exact)");
+    }
+    
+    #[test]
+    fn test_output_dir_collects_every_artifact_and_suffixes_collisions() {
+        let root = std::env::temp_dir().join(format!("anf-collect-{}", uuid::Uuid::new_v4()));
+        let store = root.join("store");
+        for task in ["t1", "t2"] {
+            std::fs::create_dir_all(store.join(task)).unwrap();
+            std::fs::write(store.join(task).join("report.md"), format!("from {}", task)).unwrap();
+        }
+        let artifact = |task: &str| serde_json::json!({"name": "report.md", "mime_type": "text/markdown", "path": store.join(task).join("report.md")});
+        let response = serde_json::json!({
+            "success": true,
+            "tasks": [{"output": {"artifacts": [artifact("t1")]}}, {"output": {"artifacts": [artifact("t2")]}}]
+        });
+        let produced = produced_artifacts(&response);
+        assert_eq!(produced.len(), 2);
+        
+        let out = root.join("out");
+        let collected = collect_artifacts(&produced, &out).unwrap();
+        assert_eq!(collected, vec![out.join("report.md"), out.join("report-1.md")]);
+        assert_eq!(std::fs::read_to_string(&collected[0]).unwrap(), "from t1");
+        assert_eq!(std::fs::read_to_string(&collected[1]).unwrap(), "from t2");
+        
+        // Names are reduced to their file name, and ones without one are refused
+        let escaping = ProducedArtifact { name: "../../escaped.md".to_string(), path: store.join("t1").join("report.md") };
+        assert_eq!(collect_artifacts(&[escaping], &out).unwrap(), vec![out.join("escaped.md")]);
+        assert!(!root.join("escaped.md").exists());
+        let dotdot = ProducedArtifact { name: "..".to_string(), path: store.join("t1").join("report.md") };
+        assert!(collect_artifacts(&[dotdot], &out).is_err());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+    
+    #[test]
+    fn test_bad_progress_template_falls_back_to_default_bar() {
+        let mut warning = None;
+        let style = bar_style_or_default("{pos:>x}", |e| warning = Some(e.to_string()));
+        assert!(warning.is_some());
+        let bar = ProgressBar::hidden();
+        bar.set_style(style.progress_chars("#>-"));
+        bar.inc(1);
+        
+        let mut warned = false;
+        bar_style_or_default("{bar} {pos}/{len}", |_| warned = true);
+        assert!(!warned);
+    }
+    
+    #[tokio::test]
+    async fn test_wait_returns_on_terminal_status_with_matching_result() {
+        let poll = |statuses: Vec<&'static str>| {
+            let statuses = std::sync::Mutex::new(statuses.into_iter());
+            move || {
+                let status = statuses.lock().unwrap().next().unwrap_or("Running");
+                async move {
+                    Ok(serde_json::json!({"success": true, "task": {
+                        "status": status, "error": "agent crashed", "output": {"text": "all green"}
+                    }}))
+                }
+            }
+        };
+        
+        let mut seen = Vec::new();
+        let interval = Duration::from_millis(1);
+        let outcome = wait_for_task(interval, Duration::from_secs(1), poll(vec!["Queued", "Running", "Completed"]), |s| seen.push(s.to_string())).await.unwrap();
+        assert_eq!(seen, vec!["Queued", "Running", "Completed"]);
+        assert_eq!(wait_result("t1", &outcome).unwrap(), "all green");
+        
+        let failed = wait_for_task(interval, Duration::from_secs(1), poll(vec!["Running", "Failed"]), |_| {}).await.unwrap();
+        assert_eq!(wait_result("t1", &failed).unwrap_err().to_string(), "Task t1 failed: agent crashed");
+        
+        let stuck = wait_for_task(interval, Duration::from_millis(20), poll(vec![]), |_| {}).await.unwrap();
+        assert_eq!(stuck, WaitOutcome::TimedOut("Running".to_string()));
+        assert!(wait_result("t1", &stuck).is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_fan_out_asks_each_agent_once_and_keys_answers() {
+        let agents: Vec<String> = ["rust-pro", "coder", "reviewer"].iter().map(|a| a.to_string()).collect();
+        let submitted = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        
+        let answers = fan_out(&agents, |agent| {
+            let submitted = submitted.clone();
+            async move {
+                submitted.lock().unwrap().push(agent.clone());
+                match agent.as_str() {
+                    "reviewer" => Err(anyhow::anyhow!("reviewer is unavailable")),
+                    _ => Ok(format!("{} says: use a trait", agent)),
+                }
+            }
+        }).await;
+        
+        let mut submitted = submitted.lock().unwrap().clone();
+        submitted.sort();
+        assert_eq!(submitted, vec!["coder", "reviewer", "rust-pro"]);
+        assert_eq!(answers.len(), 3);
+        assert_eq!(answers["rust-pro"].as_deref().unwrap(), "rust-pro says: use a trait");
+        assert!(answers["reviewer"].is_err());
+        
+        let utf8 = Glyphs::new(OutputEncoding::Utf8);
+        let stacked = render_fan_out(&agents, &answers, FanOutLayout::Stacked, 80, utf8);
+        assert!(stacked.starts_with("── rust-pro ──\nrust-pro says: use a trait\n"));
+        assert!(stacked.contains("── reviewer ──\nerror: reviewer is unavailable"));
+        let columns = render_fan_out(&agents, &answers, FanOutLayout::SideBySide, 80, utf8);
+        assert!(columns.lines().next().unwrap().starts_with("rust-pro"));
+        assert_eq!(fan_out_summary(&agents, &answers), "Summary: 2/3 answered (rust-pro, coder); failed: reviewer");
+        assert_eq!(fan_out_failure(&agents, &answers).unwrap().to_string(), "1 of 3 agents failed: reviewer");
+        
+        let ascii = Glyphs::new(OutputEncoding::Ascii);
+        assert!(render_fan_out(&agents, &answers, FanOutLayout::Stacked, 80, ascii).starts_with("-- rust-pro --\n"));
+        assert!(render_fan_out(&agents, &answers, FanOutLayout::SideBySide, 80, ascii).is_ascii());
+    }
+    
+    #[test]
+    fn test_error_replies_are_failures() {
+        assert!(reply_error(r#"{"success": true, "output": {"text": "done"}, "error": null}"#).is_none());
+        assert!(reply_error("plain answer").is_none());
+        let error = reply_error(r#"{"error": "Agent ghost not found"}"#).unwrap();
+        assert_eq!(error.exit_code(), 4);
+        let failed = reply_error(r#"{"success": false, "status": "Failed", "error": null}"#).unwrap();
+        assert_eq!(failed.to_string(), "Task ended Failed");
+    }
+
+    #[test]
+    fn test_parse_agent_list_trims_and_dedupes() {
+        let registry = vec!["rust-pro".to_string(), "coder".to_string()];
+        
+        let agents = parse_agent_list(" rust-pro , , coder ", Some(&registry)).unwrap();
+        assert_eq!(agents, vec!["rust-pro", "coder"]);
+        
+        let agents = parse_agent_list("coder,rust-pro,coder,", None).unwrap();
+        assert_eq!(agents, vec!["coder", "rust-pro"]);
+    }
+
+    #[test]
+    fn test_parse_agent_list_reports_unknown_agents() {
+        let registry = vec!["rust-pro".to_string(), "coder".to_string()];
+        
+        let err = parse_agent_list("rust-pro, ghost, phantom", Some(&registry)).unwrap_err();
+        assert_eq!(err.to_string(), "Unknown agents: ghost, phantom");
+        assert!(parse_agent_list(" , ", None).is_err());
+    }
+
+    #[test]
+    fn test_context_lang_detection() {
+        assert_eq!(detect_language(Path::new("src/main.rs")), Some("rust"));
+        assert_eq!(detect_language(Path::new("web/app.ts")), Some("typescript"));
+        assert_eq!(detect_language(Path::new("README")), None);
+        
+        assert_eq!(resolve_context_lang(None, Some(Path::new("lib.rs"))).as_deref(), Some("rust"));
+        assert_eq!(resolve_context_lang(Some("python"), Some(Path::new("lib.rs"))).as_deref(), Some("python"));
+        assert_eq!(resolve_context_lang(None, None), None);
+    }
+
+    #[test]
+    fn test_merged_context_drops_repeated_file_and_keeps_source_order() {
+        let dir = std::env::temp_dir().join(format!("anf-context-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("src/b.rs"), "fn b() {}\n").unwrap();
+        std::fs::write(dir.join("src/a.rs"), "fn a() {}\n").unwrap();
+        std::fs::write(dir.join("src/notes.txt"), "skip").unwrap();
+        let explicit = dir.join("src/b.rs");
+        let globs = vec![format!("{}/src/*.rs", dir.display())];
+        
+        let context = merge_context(Some(&explicit), &globs, Some("from stdin".to_string()), None, 1024, WalkLimits::default(), |w| panic!("{}", w)).unwrap();
+        let expected = format!(
+            "--- {b} ---\nfn b() {{}}\n--- {a} ---\nfn a() {{}}\n--- <stdin> ---\nfrom stdin\n",
+            b = explicit.display(), a = dir.join("src/a.rs").display()
+        );
+        assert_eq!(context["content"], expected);
+        assert_eq!(context["sources"].as_array().unwrap().len(), 3);
+        assert_eq!(context["lang"], "rust");
+        
+        let err = merge_context(Some(&explicit), &globs, None, None, 16, WalkLimits::default(), |_| {}).unwrap_err();
+        assert!(err.to_string().contains("over the agents.max_context_bytes limit of 16"), "{}", err);
+        let err = merge_context(Some(&explicit), &globs, None, None, 4, WalkLimits::default(), |_| {}).unwrap_err();
+        assert!(err.to_string().contains("over the agents.max_context_bytes limit of 4"), "{}", err);
+        
+        // Binary files are skipped with a warning; other bad UTF-8 is replaced
+        std::fs::write(dir.join("src/logo.png"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        std::fs::write(dir.join("src/latin1.txt"), b"caf\xe9\n").unwrap();
+        let mut warnings = Vec::new();
+        let context = merge_context(None, &[format!("{}/src/l*", dir.display())], None, None, 1024, WalkLimits::default(), |w| warnings.push(w.to_string())).unwrap();
+        assert_eq!(context["content"], format!("--- {} ---\ncaf\u{fffd}\n", dir.join("src/latin1.txt").display()));
+        assert_eq!(warnings, vec![format!("{} looks binary, skipped", dir.join("src/logo.png").display())]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_context_directory_stops_at_max_depth_and_reports_it() {
+        let dir = std::env::temp_dir().join(format!("anf-context-dir-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("src/deep/deeper")).unwrap();
+        std::fs::write(dir.join("lib.rs"), "// top\n").unwrap();
+        std::fs::write(dir.join("src/mod.rs"), "// one down\n").unwrap();
+        std::fs::write(dir.join("src/deep/deeper/far.rs"), "// too far\n").unwrap();
+        
+        let cli = Cli::try_parse_from(["anf", "ask", "--context", &dir.display().to_string(), "--max-depth", "2", "q"]).unwrap();
+        let Some(Commands::Ask { max_depth, max_files, .. }) = cli.command else { panic!("not ask") };
+        let mut warnings = Vec::new();
+        let context = merge_context(Some(&dir), &[], None, None, 1024, WalkLimits { max_depth, max_files }, |w| warnings.push(w.to_string())).unwrap();
+        assert_eq!(context["sources"], serde_json::json!([dir.join("lib.rs").display().to_string(), dir.join("src/mod.rs").display().to_string()]));
+        assert_eq!(warnings, vec![format!("{} is deeper than --max-depth 2, skipped", dir.join("src/deep").display())]);
+        
+        let globbed = expand_glob(&format!("{}/**/*.rs", dir.display()), WalkLimits { max_depth: 2, max_files: 1 }, &mut |w| warnings.push(w.to_string())).unwrap();
+        assert_eq!(globbed, vec![dir.join("lib.rs")]);
+        assert!(warnings.last().unwrap().ends_with("Stopped after 1 files (--max-files)"), "{:?}", warnings);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn test_system_flags_join_into_context_field() {
+        let cli = Cli::try_parse_from(["anf", "ask", "--system", "You are terse.", "--prompt-prefix", "Answer in Rust.", "why?"]).unwrap();
+        let Some(Commands::Ask { system, .. }) = cli.command else { panic!("not an ask") };
+        let mut options = AskOptions { context: serde_json::json!({"lang": "rust"}), ..AskOptions::default() };
+        with_system(&mut options.context, &system);
+        
+        let command = exec_command("coder", "why?", &options);
+        assert_eq!(command["params"]["prompt"], "why?");
+        assert_eq!(command["params"]["context"], serde_json::json!({"lang": "rust", "system": "You are terse.\n\nAnswer in Rust."}));
+    }
+    
+    #[test]
+    fn test_context_redact_masks_key_before_exec_params() {
+        let path = std::env::temp_dir().join(format!("anf-redact-{}.env", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "ANTHROPIC_API_KEY=sk-ant-REDACTED
+REGION=us-east-1
+").unwrap();
+        let mut options = AskOptions { context: load_context(Some(&path), None).unwrap(), ..AskOptions::default() };
+        
+        let redactor = Redactor::new(&Config::default().redact.patterns).unwrap();
+        assert_eq!(redact_context(&mut options.context, &redactor), 1);
+        let command = exec_command("coder", "check my config", &options);
+        assert_eq!(command["params"]["context"]["content"], "ANTHROPIC_API_KEY=[REDACTED]\nREGION=us-east-1\n");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_render_markdown_tags_untagged_fences() {
+        let text = "Try:\n```\nfn main() {}\n```\n```toml\n[a]\n```";
+        assert_eq!(
+            render_markdown(text, Some("rust")),
+            "Try:\n```rust\nfn main() {}\n```\n```toml\n[a]\n```"
+        );
+        assert_eq!(render_markdown(text, None), text);
+    }
+
+    #[tokio::test]
+    async fn test_repeat_until_contains_stops_loop() {
+        let condition: RepeatCondition = "contains:DONE".parse().unwrap();
+        let mut seen_previous = Vec::new();
+        
+        let outcome = repeat_until_match(&condition, 5, |iteration, previous| {
+            seen_previous.push(previous.map(str::to_string));
+            async move {
+                let text = if iteration == 3 { "all DONE".to_string() } else { format!("draft {}", iteration) };
+                Ok(IterationOutput { text, exit_code: None })
+            }
+        }).await.unwrap();
+        
+        assert!(outcome.matched);
+        assert_eq!(outcome.iterations, 3);
+        assert_eq!(outcome.output, "all DONE");
+        assert_eq!(seen_previous, vec![None, Some("draft 1".to_string()), Some("draft 2".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_repeat_until_hits_iteration_cap() {
+        let condition: RepeatCondition = "exit-zero".parse().unwrap();
+        let outcome = repeat_until_match(&condition, 2, |_, _| async {
+            Ok(IterationOutput { text: "still failing".to_string(), exit_code: Some(1) })
+        }).await.unwrap();
+        
+        assert!(!outcome.matched);
+        assert_eq!(outcome.iterations, 2);
+        assert!("regex:[".parse::<RepeatCondition>().is_err());
+        assert!("startswith:x".parse::<RepeatCondition>().is_err());
+    }
+}
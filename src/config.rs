@@ -0,0 +1,677 @@
+// ANF Configuration - Layered settings from defaults, ~/.anf/config.toml, env and flags
+// Every resolved value remembers which layer it came from
+
+use std::collections::BTreeMap;
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+/// Keys accepted from the environment (`ANF_DAEMON_SOCKET_PATH`, ...) and flags
+pub const CONFIG_KEYS: &[&str] = &[
+    "daemon.socket_path",
+    "daemon.log_level",
+    "daemon.log_file",
+    "daemon.max_agents",
+    "daemon.max_output_bytes",
+    "daemon.max_connections",
+    "daemon.read_timeout_secs",
+    "daemon.idle_timeout_secs",
+    "daemon.event_buffer",
+    "daemon.checkpoint_secs",
+    "daemon.memory_budget",
+    "daemon.max_queued_tasks",
+    "daemon.max_finished_tasks",
+    "daemon.finished_task_ttl_secs",
+    "daemon.bridge_socket_path",
+    "daemon.shutdown_grace_secs",
+    "daemon.tcp_listen",
+    "daemon.tls_cert",
+    "daemon.tls_key",
+    "daemon.tls_ca",
+    "interface.theme",
+    "interface.colors",
+    "interface.encoding",
+    "agents.default_agent",
+    "agents.default_timeout",
+    "agents.on_timeout",
+    "agents.max_retries",
+    "agents.max_context_bytes",
+    "agents.phase_concurrency",
+    "agents.tokenizer_file",
+    "agents.response_cache",
+    "agents.response_cache_max_entries",
+    "agents.response_cache_max_bytes",
+    "agents.hook_timeout_secs",
+    "rate_limit.enabled",
+    "rate_limit.mutating.per_sec",
+    "rate_limit.mutating.burst",
+    "rate_limit.query.per_sec",
+    "rate_limit.query.burst",
+    "auth.token",
+    "bridge.allow",
+    "bridge.deny",
+    "hive.memory_backend",
+    "hive.memory_path",
+];
+
+const SECRET_KEYS: &[&str] = &["auth.token"];
+
+/// Short name for `ANF_DAEMON_SOCKET_PATH`; wins over it when both are set
+pub const SOCKET_ENV: &str = "ANF_SOCKET";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub daemon: DaemonSettings,
+    pub interface: InterfaceSettings,
+    pub agents: AgentSettings,
+    pub auth: AuthSettings,
+    pub rate_limit: RateLimitSettings,
+    pub bridge: BridgePolicy,
+    pub hive: HiveSettings,
+    pub redact: RedactSettings,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DaemonSettings {
+    pub socket_path: String,
+    pub log_level: String,
+    /// Where the daemon also writes its log; `~/.anf/logs/anfd.log` when unset
+    pub log_file: Option<String>,
+    pub max_agents: u32,
+    /// Task output beyond this is truncated
+    pub max_output_bytes: usize,
+    /// Connections beyond this are refused as busy
+    pub max_connections: usize,
+    /// Deadline for receiving one complete command
+    pub read_timeout_secs: u64,
+    /// Longest gap allowed between reads within a command
+    pub idle_timeout_secs: u64,
+    /// Events kept per subscriber before a slow one starts missing them
+    pub event_buffer: usize,
+    /// How often the write-ahead log is folded into a snapshot and truncated
+    pub checkpoint_secs: u64,
+    /// Bytes of agent `memory_limit` that may be spawned at once
+    pub memory_budget: u64,
+    /// Tasks waiting to run beyond this are refused
+    pub max_queued_tasks: usize,
+    /// Finished tasks kept for `get_task`; the oldest are evicted beyond this
+    pub max_finished_tasks: usize,
+    /// Finished tasks older than this are evicted
+    pub finished_task_ttl_secs: u64,
+    /// Where the Python swarm-hive bridge listens
+    pub bridge_socket_path: String,
+    /// How long running tasks get to finish on shutdown before they are cancelled
+    pub shutdown_grace_secs: u64,
+    /// `host:port` the daemon also takes commands on, besides its socket
+    pub tcp_listen: Option<String>,
+    /// PEM certificate chain and private key securing `tcp_listen` with TLS
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    /// PEM certificates a client trusts for a `tls://` daemon address
+    pub tls_ca: Option<String>,
+}
+
+impl Default for DaemonSettings {
+    fn default() -> Self {
+        Self {
+            socket_path: "/tmp/anf.sock".to_string(),
+            log_level: "info".to_string(),
+            log_file: None,
+            max_agents: 50,
+            max_output_bytes: 1024 * 1024,
+            max_connections: 64,
+            read_timeout_secs: 10,
+            idle_timeout_secs: 5,
+            event_buffer: 1024,
+            checkpoint_secs: 60,
+            memory_budget: 8 * 1024 * 1024 * 1024,
+            max_queued_tasks: 1000,
+            max_finished_tasks: 1000,
+            finished_task_ttl_secs: 24 * 60 * 60,
+            bridge_socket_path: "/tmp/anf_python.sock".to_string(),
+            shutdown_grace_secs: 30,
+            tcp_listen: None,
+            tls_cert: None,
+            tls_key: None,
+            tls_ca: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InterfaceSettings {
+    pub theme: String,
+    pub colors: bool,
+    pub encoding: OutputEncoding,
+}
+
+impl Default for InterfaceSettings {
+    fn default() -> Self {
+        Self {
+            theme: "hacker".to_string(),
+            colors: true,
+            encoding: OutputEncoding::Auto,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputEncoding {
+    /// UTF-8 when `LANG`/`LC_*` say so, ASCII otherwise
+    #[default]
+    Auto,
+    Utf8,
+    /// Plain stand-ins for box drawing and checkmarks, no emoji
+    Ascii,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AgentSettings {
+    pub default_agent: Option<String>,
+    /// Seconds
+    pub default_timeout: u64,
+    /// What a task does when an attempt times out, unless the task says otherwise
+    pub on_timeout: TimeoutPolicy,
+    /// Re-runs allowed under the `retry` policy
+    pub max_retries: u32,
+    /// Combined size of merged context sources
+    pub max_context_bytes: usize,
+    /// Members of one collaboration phase running at once
+    pub phase_concurrency: usize,
+    /// A `.tiktoken` ranks file to count tokens with (needs the `tiktoken` feature);
+    /// counts are estimated from the text when unset
+    pub tokenizer_file: Option<String>,
+    /// Answer a repeated prompt to the same agent and model from the daemon's cache
+    pub response_cache: bool,
+    /// Past either cap, the least recently used cached answers are dropped
+    pub response_cache_max_entries: usize,
+    pub response_cache_max_bytes: usize,
+    /// A `pre_spawn`/`post_despawn` hook still running after this is killed
+    pub hook_timeout_secs: u64,
+}
+
+impl Default for AgentSettings {
+    fn default() -> Self {
+        Self {
+            default_agent: None,
+            default_timeout: 300,
+            on_timeout: TimeoutPolicy::Fail,
+            max_retries: 2,
+            max_context_bytes: 256 * 1024,
+            phase_concurrency: 4,
+            tokenizer_file: None,
+            response_cache: false,
+            response_cache_max_entries: 1000,
+            response_cache_max_bytes: 64 * 1024 * 1024,
+            hook_timeout_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeoutPolicy {
+    /// Mark the task failed
+    #[default]
+    Fail,
+    /// Re-run on the same agent, up to `max_retries` times
+    Retry,
+    /// Move on to the next fallback agent
+    Fallback,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthSettings {
+    pub token: Option<String>,
+}
+
+/// Per-client command limits; mutating actions (spawn, exec, swarms) get their own
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitSettings {
+    /// Single-user setups can turn limiting off entirely
+    pub enabled: bool,
+    pub mutating: Rate,
+    pub query: Rate,
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            mutating: Rate { per_sec: 10.0, burst: 20 },
+            query: Rate { per_sec: 50.0, burst: 100 },
+        }
+    }
+}
+
+/// Which of the actions forwarded to the Python bridge (`swarm_*`, `hive_*`,
+/// `collaborate`) the daemon lets through; everything by default
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BridgePolicy {
+    /// Only these actions, when not empty
+    pub allow: Vec<String>,
+    /// Never these, even if allowed
+    pub deny: Vec<String>,
+}
+
+impl BridgePolicy {
+    pub fn permits(&self, action: &str) -> bool {
+        (self.allow.is_empty() || self.allow.iter().any(|a| a == action))
+            && !self.deny.iter().any(|d| d == action)
+    }
+}
+
+/// Where the Python bridge keeps hive memory. The bridge reads these itself from
+/// the same file and `ANF_HIVE_*` variables; they are here so they validate and show.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HiveSettings {
+    pub memory_backend: MemoryBackendKind,
+    /// The SQLite file; `~/.anf/state/hive.db` when unset
+    pub memory_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MemoryBackendKind {
+    #[default]
+    Sqlite,
+    /// Nothing survives a bridge restart
+    Memory,
+}
+
+/// Secrets masked from context by `--context-redact`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RedactSettings {
+    /// Regexes; a `secret` group, when present, is the part masked
+    pub patterns: Vec<String>,
+}
+
+impl Default for RedactSettings {
+    fn default() -> Self {
+        Self { patterns: crate::redact::DEFAULT_PATTERNS.iter().map(|p| p.to_string()).collect() }
+    }
+}
+
+/// Token bucket: `burst` commands at once, refilled at `per_sec`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Rate {
+    pub per_sec: f64,
+    pub burst: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigSource {
+    Default,
+    File,
+    Env,
+    Flag,
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Default => "default",
+            Self::File => "file",
+            Self::Env => "env",
+            Self::Flag => "flag",
+        };
+        f.write_str(name)
+    }
+}
+
+/// One line of `anf config show`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigEntry {
+    pub key: String,
+    pub value: String,
+    pub source: ConfigSource,
+}
+
+/// Effective configuration plus the layer each value was taken from
+#[derive(Debug, Clone)]
+pub struct ResolvedConfig {
+    pub config: Config,
+    merged: toml::Table,
+    sources: BTreeMap<String, ConfigSource>,
+}
+
+impl Config {
+    pub fn path() -> std::path::PathBuf {
+        crate::paths::anf_path("config.toml")
+    }
+
+    /// Resolve from the user's config file and process environment
+    pub fn load(flags: &[(String, String)]) -> anyhow::Result<ResolvedConfig> {
+        let path = Self::path();
+        let file = match std::fs::read_to_string(&path) {
+            Ok(contents) => Some(contents),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(anyhow::anyhow!("Failed to read {}: {}", path.display(), e)),
+        };
+
+        Self::resolve(file.as_deref(), |name| std::env::var(name).ok(), flags)
+    }
+
+    /// Layer defaults < file < env < flags; later layers win per key.
+    /// The default socket is `$XDG_RUNTIME_DIR/anf.sock` when that is set, so
+    /// users sharing a machine each get their own.
+    pub fn resolve(
+        file: Option<&str>,
+        env: impl Fn(&str) -> Option<String>,
+        flags: &[(String, String)],
+    ) -> anyhow::Result<ResolvedConfig> {
+        let mut merged = toml::Table::try_from(Config::default())?;
+        if let Some(runtime_dir) = env("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+            let socket = format!("{}/anf.sock", runtime_dir.trim_end_matches('/'));
+            set_key(&mut merged, "daemon.socket_path", toml::Value::String(socket));
+        }
+        let mut sources = BTreeMap::new();
+        for (key, _) in flatten(&merged) {
+            sources.insert(key, ConfigSource::Default);
+        }
+
+        if let Some(contents) = file {
+            let table: toml::Table = contents.parse()
+                .map_err(|e| anyhow::anyhow!("Invalid config file: {}", e))?;
+            for (key, value) in flatten(&table) {
+                set_key(&mut merged, &key, value);
+                sources.insert(key, ConfigSource::File);
+            }
+        }
+
+        for key in CONFIG_KEYS {
+            if let Some(raw) = env(&env_var_name(key)) {
+                let value = typed_value(&merged, key, &raw);
+                set_key(&mut merged, key, value);
+                sources.insert(key.to_string(), ConfigSource::Env);
+            }
+        }
+        if let Some(socket) = env(SOCKET_ENV) {
+            set_key(&mut merged, "daemon.socket_path", toml::Value::String(socket));
+            sources.insert("daemon.socket_path".to_string(), ConfigSource::Env);
+        }
+
+        for (key, raw) in flags {
+            if !CONFIG_KEYS.contains(&key.as_str()) {
+                return Err(anyhow::anyhow!("Unknown config key: {}", key));
+            }
+            let value = typed_value(&merged, key, raw);
+            set_key(&mut merged, key, value);
+            sources.insert(key.clone(), ConfigSource::Flag);
+        }
+
+        let config: Config = toml::Value::Table(merged.clone()).try_into()
+            .map_err(|e| anyhow::anyhow!("Invalid configuration: {}", e))?;
+
+        Ok(ResolvedConfig { config, merged, sources })
+    }
+}
+
+impl ResolvedConfig {
+    pub fn source(&self, key: &str) -> Option<ConfigSource> {
+        self.sources.get(key).copied()
+    }
+
+    /// Every effective value with its source; secrets are redacted
+    pub fn entries(&self) -> Vec<ConfigEntry> {
+        flatten(&self.merged)
+            .into_iter()
+            .map(|(key, value)| {
+                let value = if SECRET_KEYS.contains(&key.as_str()) {
+                    "<redacted>".to_string()
+                } else {
+                    match value {
+                        toml::Value::String(s) => s,
+                        other => other.to_string(),
+                    }
+                };
+                let source = self.source(&key).unwrap_or(ConfigSource::Default);
+                ConfigEntry { key, value, source }
+            })
+            .collect()
+    }
+}
+
+/// `daemon.socket_path` -> `ANF_DAEMON_SOCKET_PATH`
+pub fn env_var_name(key: &str) -> String {
+    format!("ANF_{}", key.replace('.', "_").to_uppercase())
+}
+
+fn flatten(table: &toml::Table) -> Vec<(String, toml::Value)> {
+    let mut out = Vec::new();
+    for (key, value) in table {
+        match value {
+            toml::Value::Table(inner) => {
+                for (inner_key, inner_value) in flatten(inner) {
+                    out.push((format!("{}.{}", key, inner_key), inner_value));
+                }
+            }
+            other => out.push((key.clone(), other.clone())),
+        }
+    }
+    out
+}
+
+fn set_key(table: &mut toml::Table, key: &str, value: toml::Value) {
+    match key.split_once('.') {
+        Some((head, rest)) => {
+            let entry = table.entry(head.to_string())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            if let toml::Value::Table(inner) = entry {
+                set_key(inner, rest, value);
+            }
+        }
+        None => {
+            table.insert(key.to_string(), value);
+        }
+    }
+}
+
+const SIZE_UNITS: &[(&str, u64)] = &[
+    ("tb", 1 << 40),
+    ("gb", 1 << 30),
+    ("mb", 1 << 20),
+    ("kb", 1 << 10),
+    ("b", 1),
+];
+
+/// A human size such as `512MB`, `2GB`, `1.5gb` or plain bytes, in bytes.
+/// Units are binary (1MB = 1024KB); `G`, `GiB` and friends work too.
+pub fn parse_size(size: &str) -> anyhow::Result<u64> {
+    let invalid = || anyhow::anyhow!("Invalid size '{}': expected a number with an optional KB, MB, GB or TB suffix", size);
+    let lower = size.trim().to_ascii_lowercase().replace("ib", "b");
+    let split = lower.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(lower.len());
+    let (number, unit) = lower.split_at(split);
+    let unit = unit.trim();
+    let multiplier = match unit {
+        "" => 1,
+        _ => SIZE_UNITS.iter()
+            .find(|(suffix, _)| *suffix == unit || suffix.strip_suffix('b') == Some(unit))
+            .map(|(_, multiplier)| *multiplier)
+            .ok_or_else(invalid)?,
+    };
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    Ok((number * multiplier as f64).round() as u64)
+}
+
+/// `bytes` in the largest unit that keeps it at least 1, e.g. `1.5GB`
+pub fn format_size(bytes: u64) -> String {
+    let (suffix, multiplier) = SIZE_UNITS.iter()
+        .find(|(_, multiplier)| bytes >= *multiplier)
+        .copied()
+        .unwrap_or(("b", 1));
+    let value = format!("{:.1}", bytes as f64 / multiplier as f64);
+    format!("{}{}", value.trim_end_matches(".0"), suffix.to_ascii_uppercase())
+}
+
+/// A duration such as `500ms`, `1s`, `5m` or `1h`; a bare number is seconds
+pub fn parse_duration(duration: &str) -> anyhow::Result<std::time::Duration> {
+    let invalid = || anyhow::anyhow!("Invalid duration '{}': expected a number with an ms, s, m or h suffix", duration);
+    let trimmed = duration.trim();
+    let split = trimmed.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+    let millis_per_unit: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "ms" => 1,
+        "" | "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        _ => return Err(invalid()),
+    };
+    let too_long = || anyhow::anyhow!("Invalid duration '{}': too long", duration);
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if (whole.is_empty() && fraction.is_empty()) || fraction.contains('.') {
+        return Err(invalid());
+    }
+    let whole: u64 = match whole {
+        "" => 0,
+        // Only digits are left, so a parse failure means it didn't fit
+        whole => whole.parse().map_err(|_| too_long())?,
+    };
+    let fraction: f64 = format!("0.{}", fraction).parse().map_err(|_| invalid())?;
+    let whole = whole.checked_mul(millis_per_unit).map(std::time::Duration::from_millis).ok_or_else(too_long)?;
+    whole.checked_add(std::time::Duration::from_secs_f64(fraction * millis_per_unit as f64 / 1000.0)).ok_or_else(too_long)
+}
+
+fn get_key<'a>(table: &'a toml::Table, key: &str) -> Option<&'a toml::Value> {
+    match key.split_once('.') {
+        Some((head, rest)) => match table.get(head)? {
+            toml::Value::Table(inner) => get_key(inner, rest),
+            _ => None,
+        },
+        None => table.get(key),
+    }
+}
+
+/// Interpret a raw env/flag string using the type of the value it replaces
+fn typed_value(merged: &toml::Table, key: &str, raw: &str) -> toml::Value {
+    match get_key(merged, key) {
+        Some(toml::Value::Integer(_)) => raw.parse().map(toml::Value::Integer).unwrap_or_else(|_| raw.into()),
+        Some(toml::Value::Boolean(_)) => raw.parse().map(toml::Value::Boolean).unwrap_or_else(|_| raw.into()),
+        Some(toml::Value::Float(_)) => raw.parse().map(toml::Value::Float).unwrap_or_else(|_| raw.into()),
+        Some(toml::Value::Array(_)) => toml::Value::Array(
+            raw.split(',').map(str::trim).filter(|item| !item.is_empty()).map(toml::Value::from).collect()
+        ),
+        _ => toml::Value::String(raw.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = r#"
+[daemon]
+socket_path = "/run/anf-file.sock"
+
+[interface]
+theme = "minimal"
+
+[auth]
+token = "s3cret"
+"#;
+
+    fn entry<'a>(entries: &'a [ConfigEntry], key: &str) -> &'a ConfigEntry {
+        entries.iter().find(|e| e.key == key).unwrap()
+    }
+
+    #[test]
+    fn test_parse_size_accepts_human_units() {
+        assert_eq!(parse_size("2GB").unwrap(), 2 * 1024 * 1024 * 1024);
+        assert_eq!(parse_size("512mb").unwrap(), 512 * 1024 * 1024);
+        assert_eq!(parse_size("1.5G").unwrap(), 3 * 512 * 1024 * 1024);
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+        assert_eq!(format_size(3 * 512 * 1024 * 1024), "1.5GB");
+        assert_eq!(format_size(512 * 1024 * 1024), "512MB");
+
+        for bad in ["", "GB", "2XB", "two GB", "1.2.3MB"] {
+            let err = parse_size(bad).unwrap_err().to_string();
+            assert!(err.starts_with(&format!("Invalid size '{}'", bad)), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_parse_duration_accepts_human_units() {
+        assert_eq!(parse_duration("5m").unwrap(), std::time::Duration::from_secs(300));
+        assert_eq!(parse_duration("250ms").unwrap(), std::time::Duration::from_millis(250));
+        assert_eq!(parse_duration("2").unwrap(), std::time::Duration::from_secs(2));
+        assert_eq!(parse_duration("1.5s").unwrap(), std::time::Duration::from_millis(1500));
+        for bad in ["5 days", "", ".", "1.2.3s"] {
+            assert!(parse_duration(bad).is_err(), "{}", bad);
+        }
+        for huge in ["99999999999999999999h", "18446744073709551615h"] {
+            let err = parse_duration(huge).unwrap_err().to_string();
+            assert!(err.ends_with("too long"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_env_overrides_file_in_dump() {
+        let env = |name: &str| match name {
+            "ANF_DAEMON_SOCKET_PATH" => Some("/run/anf-env.sock".to_string()),
+            "ANF_DAEMON_MAX_AGENTS" => Some("8".to_string()),
+            _ => None,
+        };
+        let resolved = Config::resolve(Some(FILE), env, &[]).unwrap();
+        assert_eq!(resolved.config.daemon.socket_path, "/run/anf-env.sock");
+        assert_eq!(resolved.config.daemon.max_agents, 8);
+
+        let entries = resolved.entries();
+        assert_eq!(entry(&entries, "daemon.socket_path"), &ConfigEntry {
+            key: "daemon.socket_path".to_string(),
+            value: "/run/anf-env.sock".to_string(),
+            source: ConfigSource::Env,
+        });
+        assert_eq!(entry(&entries, "interface.theme").source, ConfigSource::File);
+        assert_eq!(entry(&entries, "daemon.log_level").source, ConfigSource::Default);
+    }
+
+    #[test]
+    fn test_socket_path_precedence() {
+        let socket = |file: Option<&str>, env: &[(&str, &str)], flag: Option<&str>| {
+            let flags: Vec<(String, String)> = flag.iter()
+                .map(|path| ("daemon.socket_path".to_string(), path.to_string()))
+                .collect();
+            let lookup = |name: &str| env.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string());
+            let resolved = Config::resolve(file, lookup, &flags).unwrap();
+            (resolved.config.daemon.socket_path.clone(), resolved.source("daemon.socket_path").unwrap())
+        };
+        let runtime = ("XDG_RUNTIME_DIR", "/run/user/1000/");
+        let long = ("ANF_DAEMON_SOCKET_PATH", "/run/anf-long.sock");
+        let short = (SOCKET_ENV, "/run/anf-short.sock");
+
+        assert_eq!(socket(None, &[], None), ("/tmp/anf.sock".to_string(), ConfigSource::Default));
+        assert_eq!(socket(None, &[("XDG_RUNTIME_DIR", "")], None).0, "/tmp/anf.sock");
+        assert_eq!(socket(None, &[runtime], None), ("/run/user/1000/anf.sock".to_string(), ConfigSource::Default));
+        assert_eq!(socket(Some(FILE), &[runtime], None), ("/run/anf-file.sock".to_string(), ConfigSource::File));
+        assert_eq!(socket(Some(FILE), &[runtime, long], None).0, "/run/anf-long.sock");
+        assert_eq!(socket(Some(FILE), &[runtime, long, short], None), ("/run/anf-short.sock".to_string(), ConfigSource::Env));
+        assert_eq!(socket(Some(FILE), &[runtime, long, short], Some("/run/anf-flag.sock")),
+                   ("/run/anf-flag.sock".to_string(), ConfigSource::Flag));
+    }
+
+    #[test]
+    fn test_flags_win_and_secrets_redacted() {
+        let env = |name: &str| (name == "ANF_INTERFACE_THEME").then(|| "env-theme".to_string());
+        let flags = vec![("interface.theme".to_string(), "flag-theme".to_string())];
+        let resolved = Config::resolve(Some(FILE), env, &flags).unwrap();
+        assert_eq!(resolved.config.interface.theme, "flag-theme");
+        assert_eq!(resolved.source("interface.theme"), Some(ConfigSource::Flag));
+
+        let entries = resolved.entries();
+        assert_eq!(entry(&entries, "auth.token").value, "<redacted>");
+        assert!(!entries.iter().any(|e| e.value.contains("s3cret")));
+
+        let bad = vec![("nope.key".to_string(), "1".to_string())];
+        assert!(Config::resolve(None, |_| None, &bad).is_err());
+    }
+}
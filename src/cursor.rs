@@ -0,0 +1,108 @@
+// ANF Cursors - Last-seen positions for `--since-last` polling
+// Stored by name in ~/.anf/state/cursors.json so repeated polls see each entry once
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+
+/// Position of an entry: its timestamp, with the id breaking ties
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Cursor {
+    pub at: chrono::DateTime<chrono::Utc>,
+    pub id: uuid::Uuid,
+}
+
+#[derive(Debug, Clone)]
+pub struct CursorStore {
+    path: PathBuf,
+}
+
+impl CursorStore {
+    pub fn default_path() -> PathBuf {
+        crate::paths::anf_path("state/cursors.json")
+    }
+
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Missing or unreadable cursors mean nothing has been seen yet
+    fn load(&self) -> BTreeMap<String, Cursor> {
+        std::fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, cursors: &BTreeMap<String, Cursor>) -> anyhow::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            crate::paths::ensure_dir(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(cursors)?)?;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<Cursor> {
+        self.load().get(name).copied()
+    }
+
+    pub fn set(&self, name: &str, cursor: Cursor) -> anyhow::Result<()> {
+        let mut cursors = self.load();
+        cursors.insert(name.to_string(), cursor);
+        self.save(&cursors)
+    }
+
+    pub fn reset(&self, name: &str) -> anyhow::Result<()> {
+        let mut cursors = self.load();
+        if cursors.remove(name).is_some() {
+            self.save(&cursors)?;
+        }
+        Ok(())
+    }
+
+    /// Entries after the `name` cursor, oldest first, advancing the cursor past
+    /// them. Entries without a position (e.g. unfinished tasks) are left for later.
+    pub fn advance<T>(&self, name: &str, entries: Vec<T>, position: impl Fn(&T) -> Option<Cursor>) -> anyhow::Result<Vec<T>> {
+        let last = self.get(name);
+        let mut newer: Vec<(Cursor, T)> = entries.into_iter()
+            .filter_map(|entry| position(&entry).map(|at| (at, entry)))
+            .filter(|(at, _)| last.is_none_or(|last| *at > last))
+            .collect();
+        newer.sort_by_key(|(at, _)| *at);
+        if let Some((at, _)) = newer.last() {
+            self.set(name, *at)?;
+        }
+        Ok(newer.into_iter().map(|(_, entry)| entry).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_consecutive_polls_return_disjoint_increasing_windows() {
+        let dir = std::env::temp_dir().join(format!("anf-cursors-{}", uuid::Uuid::new_v4()));
+        let store = CursorStore::new(dir.join("cursors.json"));
+        let start = chrono::Utc::now();
+        let finished = |minutes: i64| Cursor { at: start + chrono::Duration::minutes(minutes), id: uuid::Uuid::new_v4() };
+
+        let mut tasks = vec![Some(finished(2)), Some(finished(1)), None, Some(finished(3))];
+        let first = store.advance("history/default", tasks.clone(), |t| *t).unwrap();
+        assert_eq!(first.len(), 3);
+        assert!(first.windows(2).all(|w| w[0] < w[1]));
+
+        tasks.extend([Some(finished(5)), Some(finished(4))]);
+        let second = store.advance("history/default", tasks.clone(), |t| *t).unwrap();
+        assert_eq!(second, vec![Some(tasks[5].unwrap()), Some(tasks[4].unwrap())]);
+        assert!(first.last() < second.first());
+
+        // Nothing new: empty window, cursor unchanged; other names are independent
+        assert!(store.advance("history/default", tasks.clone(), |t| *t).unwrap().is_empty());
+        assert_eq!(store.advance("history/ci", tasks.clone(), |t| *t).unwrap().len(), 5);
+
+        store.reset("history/default").unwrap();
+        assert_eq!(store.advance("history/default", tasks, |t| *t).unwrap().len(), 5);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,101 @@
+// ANF Errors - Failure categories and the exit codes `anf` reports for them
+//
+//   0  success
+//   1  anything else, including errors the daemon reports without a category
+//   2  usage: bad arguments or input (clap's own parse errors use 2 as well)
+//   3  the daemon socket can't be reached
+//   4  an agent, task or other named thing doesn't exist
+//   5  a deadline passed
+//   6  the daemon rate-limited the command
+
+#[derive(Debug, thiserror::Error)]
+pub enum AnfError {
+    #[error("{0}")]
+    Usage(String),
+    #[error("Daemon unreachable at {socket}: {reason}")]
+    DaemonUnreachable { socket: String, reason: String },
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Timeout(String),
+    #[error("{0}")]
+    RateLimited(String),
+    /// An error response in no more specific category
+    #[error("{0}")]
+    Daemon(String),
+}
+
+impl AnfError {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            AnfError::Daemon(_) => 1,
+            AnfError::Usage(_) => 2,
+            AnfError::DaemonUnreachable { .. } => 3,
+            AnfError::NotFound(_) => 4,
+            AnfError::Timeout(_) => 5,
+            AnfError::RateLimited(_) => 6,
+        }
+    }
+
+    /// The error in a daemon response, if it has one. The `code` decides when
+    /// present; otherwise the message does, since most daemon errors carry none.
+    pub fn from_response(response: &serde_json::Value) -> Option<Self> {
+        let message = response.get("error").filter(|error| !error.is_null())?.as_str().unwrap_or("Unknown daemon error").to_string();
+        let lower = message.to_ascii_lowercase();
+        Some(match response.get("code").and_then(|v| v.as_str()) {
+            Some("RATE_LIMITED") => AnfError::RateLimited(message),
+            Some("TIMEOUT") => AnfError::Timeout(message),
+            Some("NOT_FOUND") => AnfError::NotFound(message),
+            Some("DAEMON_REQUIRED") => AnfError::Usage(message),
+            _ if lower.contains("not found") || lower.starts_with("unknown agent") => AnfError::NotFound(message),
+            _ if lower.contains("timed out") => AnfError::Timeout(message),
+            _ => AnfError::Daemon(message),
+        })
+    }
+}
+
+/// A failure the command already printed its `--json` report for, so `main`
+/// sets the exit code without printing a second one
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct Reported(pub AnfError);
+
+/// The exit code for an error from anywhere in the CLI
+pub fn exit_code(error: &anyhow::Error) -> u8 {
+    if let Some(error) = error.downcast_ref::<AnfError>() {
+        return error.exit_code();
+    }
+    if let Some(Reported(error)) = error.downcast_ref::<Reported>() {
+        return error.exit_code();
+    }
+    if let Some(error) = error.downcast_ref::<clap::Error>() {
+        return error.exit_code().clamp(0, 255) as u8;
+    }
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_each_error_category_has_its_exit_code() {
+        let code = |error: AnfError| exit_code(&error.into());
+        assert_eq!(code(AnfError::Usage("No prompt given".to_string())), 2);
+        assert_eq!(code(AnfError::DaemonUnreachable { socket: "/tmp/anf.sock".to_string(), reason: "refused".to_string() }), 3);
+        assert_eq!(code(AnfError::NotFound("Task x not found".to_string())), 4);
+        assert_eq!(code(AnfError::Timeout("Timed out".to_string())), 5);
+        assert_eq!(code(AnfError::RateLimited("slow down".to_string())), 6);
+        assert_eq!(exit_code(&Reported(AnfError::Timeout("Timed out".to_string())).into()), 5);
+        assert_eq!(exit_code(&anyhow::anyhow!("anything else")), 1);
+        assert_eq!(exit_code(&clap::Error::new(clap::error::ErrorKind::InvalidSubcommand).into()), 2);
+
+        let from = |response: serde_json::Value| AnfError::from_response(&response).map(|e| e.exit_code());
+        assert_eq!(from(serde_json::json!({"code": "RATE_LIMITED", "error": "Rate limit exceeded"})), Some(6));
+        assert_eq!(from(serde_json::json!({"error": "Agent ghost not found"})), Some(4));
+        assert_eq!(from(serde_json::json!({"error": "Task 1 timed out after 30s"})), Some(5));
+        assert_eq!(from(serde_json::json!({"code": "INVALID_JSON", "error": "bad"})), Some(1));
+        assert_eq!(from(serde_json::json!({"success": true})), None);
+        assert_eq!(from(serde_json::json!({"success": true, "error": null})), None);
+    }
+}
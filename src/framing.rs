@@ -0,0 +1,124 @@
+// ANF Framing - Length-prefixed messages on the daemon and bridge sockets
+// A frame is a 4-byte big-endian length and then that many bytes. Frames never reach
+// 16MiB, so their first byte is always zero, which no newline-terminated line starts with
+
+use std::ops::Range;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+pub const MAX_FRAME_BYTES: usize = (1 << 24) - 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    LengthPrefixed,
+    /// The older protocol: everything up to a trailing `\n`
+    Newline,
+}
+
+/// Where the first message in a read buffer lies
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub framing: Framing,
+    pub body: Range<usize>,
+    /// Bytes to drop from the buffer to get to whatever was sent after it
+    pub consumed: usize,
+}
+
+/// The body length a prefix announces, when it is a plausible one
+pub fn frame_length(prefix: [u8; 4]) -> Option<usize> {
+    let length = u32::from_be_bytes(prefix) as usize;
+    (length > 0 && length <= MAX_FRAME_BYTES).then_some(length)
+}
+
+/// The first message in `buffer` once all of it has arrived. A newline message
+/// is the whole buffer once it ends in `\n`, so pipelined chat turns stay with it.
+pub fn complete_message(buffer: &[u8]) -> std::io::Result<Option<Message>> {
+    match buffer.first() {
+        None => Ok(None),
+        Some(0) => {
+            let Some(prefix) = buffer.get(..4) else { return Ok(None) };
+            let length = frame_length(prefix.try_into().expect("four bytes"))
+                .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "Frame length out of range"))?;
+            Ok((buffer.len() >= 4 + length).then(|| Message {
+                framing: Framing::LengthPrefixed,
+                body: 4..4 + length,
+                consumed: 4 + length,
+            }))
+        },
+        Some(_) => Ok(buffer.ends_with(b"\n").then_some(Message {
+            framing: Framing::Newline,
+            body: 0..buffer.len(),
+            consumed: buffer.len(),
+        })),
+    }
+}
+
+pub fn encode(body: &[u8]) -> std::io::Result<Vec<u8>> {
+    if body.is_empty() || body.len() > MAX_FRAME_BYTES {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("A frame holds 1 to {} bytes, not {}", MAX_FRAME_BYTES, body.len()),
+        ));
+    }
+    let mut frame = Vec::with_capacity(4 + body.len());
+    frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    frame.extend_from_slice(body);
+    Ok(frame)
+}
+
+pub async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, body: &[u8]) -> std::io::Result<()> {
+    writer.write_all(&encode(body)?).await?;
+    writer.flush().await
+}
+
+/// Read one message, framed or a newline-terminated line from a peer that
+/// doesn't frame; None when the peer closed before sending anything
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> std::io::Result<Option<Vec<u8>>> {
+    let mut first = [0u8; 1];
+    if reader.read(&mut first).await? == 0 {
+        return Ok(None);
+    }
+    if first[0] == 0 {
+        let mut prefix = [0u8; 4];
+        reader.read_exact(&mut prefix[1..]).await?;
+        let length = frame_length(prefix)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "Frame length out of range"))?;
+        let mut body = vec![0u8; length];
+        reader.read_exact(&mut body).await?;
+        return Ok(Some(body));
+    }
+    let mut line = first.to_vec();
+    let mut byte = [0u8; 1];
+    while line.last() != Some(&b'\n') && reader.read(&mut byte).await? == 1 {
+        line.push(byte[0]);
+    }
+    Ok(Some(line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_frames_keep_newlines_and_lines_still_parse() {
+        let body = b"{\n  \"prompt\": \"one\ntwo\"\n}";
+        let mut stream = encode(body).unwrap();
+        stream.extend_from_slice(&encode(b"next").unwrap());
+
+        let first = complete_message(&stream).unwrap().unwrap();
+        assert_eq!((first.framing, &stream[first.body.clone()]), (Framing::LengthPrefixed, &body[..]));
+        assert_eq!(complete_message(&stream[first.consumed..]).unwrap().unwrap().body, 4..8);
+        assert_eq!(complete_message(&stream[..10]).unwrap(), None);
+
+        let line = complete_message(b"{\"action\":\"list_agents\"}\n").unwrap().unwrap();
+        assert_eq!(line.framing, Framing::Newline);
+        assert_eq!(complete_message(b"{\"action\"").unwrap(), None);
+        assert!(complete_message(&[0, 0, 0, 0, b'{']).is_err());
+
+        let mut reader = &stream[..];
+        assert_eq!(read_frame(&mut reader).await.unwrap().unwrap(), body);
+        assert_eq!(read_frame(&mut reader).await.unwrap().unwrap(), b"next");
+        assert_eq!(read_frame(&mut reader).await.unwrap(), None);
+        let mut legacy = &b"{\"code\":\"BUSY\"}\n"[..];
+        assert_eq!(read_frame(&mut legacy).await.unwrap().unwrap(), b"{\"code\":\"BUSY\"}\n");
+    }
+}
@@ -0,0 +1,95 @@
+// ANF Glyphs - Box drawing, bullets and emoji with plain-ASCII stand-ins
+// TerminalUI renders every string through a Glyphs so restricted terminals get readable output
+
+use crate::config::OutputEncoding;
+
+/// Non-ASCII characters the UI uses and what ASCII mode prints instead
+const ASCII_FALLBACKS: &[(char, &str)] = &[
+    ('┌', "+"), ('┐', "+"), ('└', "+"), ('┘', "+"), ('├', "+"), ('┤', "+"),
+    ('─', "-"), ('│', "|"),
+    ('▓', "#"), ('░', "."),
+    ('•', "*"), ('→', "->"), ('…', "..."), ('—', "-"),
+    ('✓', "[x]"), ('✔', "[x]"), ('✅', "[x]"), ('☐', "[ ]"), ('⏳', "[ ]"),
+    ('❌', "[!]"), ('✗', "[!]"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Glyphs {
+    ascii: bool,
+}
+
+impl Glyphs {
+    pub fn new(encoding: OutputEncoding) -> Self {
+        let ascii = match encoding {
+            OutputEncoding::Utf8 => false,
+            OutputEncoding::Ascii => true,
+            OutputEncoding::Auto => !locale_is_utf8(|name| std::env::var(name).ok()),
+        };
+        Self { ascii }
+    }
+
+    pub fn is_ascii(&self) -> bool {
+        self.ascii
+    }
+
+    /// `text` as-is in UTF-8 mode. In ASCII mode known glyphs are substituted,
+    /// emoji are dropped along with the space after them, and any other
+    /// non-ASCII character becomes `?`.
+    pub fn render(&self, text: &str) -> String {
+        if !self.ascii || text.is_ascii() {
+            return text.to_string();
+        }
+        let mut rendered = String::with_capacity(text.len());
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c.is_ascii() {
+                rendered.push(c);
+            } else if let Some((_, fallback)) = ASCII_FALLBACKS.iter().find(|(glyph, _)| *glyph == c) {
+                rendered.push_str(fallback);
+            } else if c.is_alphanumeric() {
+                rendered.push('?');
+            } else {
+                // Emoji (and their variation selectors): leave no gap behind
+                while chars.peek().is_some_and(|next| !next.is_ascii() && !next.is_alphanumeric()
+                    && !ASCII_FALLBACKS.iter().any(|(glyph, _)| glyph == next)) {
+                    chars.next();
+                }
+                if chars.peek() == Some(&' ') {
+                    chars.next();
+                }
+            }
+        }
+        rendered
+    }
+}
+
+/// The first of `LC_ALL`, `LC_CTYPE`, `LANG` that is set decides; none set
+/// (the C locale) means no UTF-8
+fn locale_is_utf8(var: impl Fn(&str) -> Option<String>) -> bool {
+    ["LC_ALL", "LC_CTYPE", "LANG"].iter()
+        .find_map(|name| var(name).filter(|value| !value.is_empty()))
+        .is_some_and(|locale| {
+            let locale = locale.to_ascii_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_substitutes_and_drops_emoji() {
+        let ascii = Glyphs::new(OutputEncoding::Ascii);
+        assert_eq!(ascii.render("🤖 rust-pro │ ✓ done → next"), "rust-pro | [x] done -> next");
+        assert_eq!(ascii.render("💾 Results ▓▓░"), "Results ##.");
+        assert_eq!(Glyphs::new(OutputEncoding::Utf8).render("🤖 │"), "🤖 │");
+
+        let env = |pairs: &'static [(&'static str, &'static str)]| {
+            move |name: &str| pairs.iter().find(|(k, _)| *k == name).map(|(_, v)| v.to_string())
+        };
+        assert!(locale_is_utf8(env(&[("LANG", "en_US.UTF-8")])));
+        assert!(!locale_is_utf8(env(&[("LC_ALL", "C"), ("LANG", "en_US.UTF-8")])));
+        assert!(!locale_is_utf8(env(&[])));
+    }
+}
@@ -0,0 +1,110 @@
+// ANF Learned Strengths - Per-agent success rates by capability, from task outcomes
+// Kept in ~/.anf/state/learned.json; routing only uses them when asked to (--use-learned)
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::capabilities::Ontology;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tally {
+    pub successes: u32,
+    pub failures: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LearnedStrengths {
+    /// agent -> capability -> outcomes
+    pub tallies: BTreeMap<String, BTreeMap<String, Tally>>,
+}
+
+impl LearnedStrengths {
+    pub fn default_path() -> std::path::PathBuf {
+        crate::paths::anf_path("state/learned.json")
+    }
+
+    /// Nothing learned yet when the file is missing
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|e| anyhow::anyhow!("Invalid learned strengths {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(anyhow::anyhow!("Failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Write-then-rename, so readers and crashes never see a half-written file
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            crate::paths::ensure_dir(parent)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn record(&mut self, agent: &str, capabilities: &[String], success: bool) {
+        let agent = self.tallies.entry(agent.to_string()).or_default();
+        for capability in capabilities {
+            let tally = agent.entry(capability.clone()).or_default();
+            if success {
+                tally.successes += 1;
+            } else {
+                tally.failures += 1;
+            }
+        }
+    }
+
+    /// Smoothed success rate in (0, 1); 0.5 for anything not seen yet, so routing
+    /// is unchanged until there is evidence either way
+    pub fn strength(&self, agent: &str, capability: &str) -> f32 {
+        let tally = self.tallies.get(agent).and_then(|caps| caps.get(capability)).copied().unwrap_or_default();
+        (tally.successes as f32 + 1.0) / ((tally.successes + tally.failures) as f32 + 2.0)
+    }
+}
+
+/// Capabilities a task exercised: an explicit `capabilities` list in its context,
+/// or else the words of the prompt that name a capability some agent has
+pub fn infer_capabilities(prompt: &str, context: &serde_json::Value, known: &BTreeSet<String>, ontology: &Ontology) -> Vec<String> {
+    if let Some(explicit) = context.get("capabilities").and_then(|v| v.as_array()) {
+        let explicit: BTreeSet<String> = explicit.iter().filter_map(|c| c.as_str()).map(|c| ontology.normalize(c)).collect();
+        return explicit.into_iter().collect();
+    }
+    let words: BTreeSet<String> = prompt
+        .split(|c: char| !c.is_alphanumeric() && c != '-')
+        .filter(|word| !word.is_empty())
+        .map(|word| ontology.normalize(word))
+        .filter(|word| known.contains(word))
+        .collect();
+    words.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strength_moves_with_outcomes_and_persists() {
+        let known: BTreeSet<String> = ["performance", "rust"].iter().map(|c| c.to_string()).collect();
+        let caps = infer_capabilities("Improve perf of the parser", &serde_json::json!({}), &known, &Ontology::default());
+        assert_eq!(caps, vec!["performance"]);
+        let explicit = infer_capabilities("anything", &serde_json::json!({"capabilities": ["RS"]}), &known, &Ontology::default());
+        assert_eq!(explicit, vec!["rust"]);
+
+        let mut learned = LearnedStrengths::default();
+        assert_eq!(learned.strength("coder", "performance"), 0.5);
+        for _ in 0..3 {
+            learned.record("coder", &caps, true);
+        }
+        learned.record("reviewer", &caps, false);
+        assert_eq!(learned.strength("coder", "performance"), 0.8);
+        assert!(learned.strength("reviewer", "performance") < 0.5);
+
+        let path = std::env::temp_dir().join(format!("anf-learned-{}.json", uuid::Uuid::new_v4()));
+        learned.save(&path).unwrap();
+        assert_eq!(LearnedStrengths::load(&path).unwrap(), learned);
+        std::fs::remove_file(path).unwrap();
+    }
+}
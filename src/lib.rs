@@ -0,0 +1,26 @@
+// AgentNativeFramework - Shared library for the anf CLI and anfd daemon
+
+pub mod cache;
+pub mod capabilities;
+pub mod config;
+pub mod cursor;
+pub mod daemon;
+pub mod error;
+pub mod framing;
+pub mod glyphs;
+pub mod learning;
+pub mod logfile;
+pub mod metrics;
+pub mod paths;
+pub mod plan;
+pub mod ratelimit;
+pub mod redact;
+pub mod scaffold;
+pub mod session;
+pub mod tokens;
+pub mod swarm;
+pub mod transport;
+pub mod wal;
+pub mod walk;
+pub mod wave_integration;
+pub mod workflow;
@@ -0,0 +1,206 @@
+// ANF Log files - The daemon's log file, written and followed across rotation
+// Rotation means the file at the path was truncated or replaced (renamed away
+// and recreated); both the writer and the follower notice and reopen
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// `~/.anf/logs/anfd.log`, unless `daemon.log_file` says otherwise
+pub fn default_path() -> PathBuf {
+    crate::paths::anf_path("logs/anfd.log")
+}
+
+/// How often a `LogFile` checks whether its path was rotated
+pub const ROTATION_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Appends to `path`, reopening whenever the file there is no longer the one
+/// held open, so lines after a rotation land in the new file. The path is
+/// checked at most once per `rotation_check`, not on every write.
+#[derive(Debug, Clone)]
+pub struct LogFile {
+    path: PathBuf,
+    open: Arc<Mutex<Option<OpenLog>>>,
+    rotation_check: Duration,
+}
+
+#[derive(Debug)]
+struct OpenLog {
+    file: File,
+    inode: u64,
+    checked: Instant,
+}
+
+impl LogFile {
+    pub fn open(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let log = Self { path, open: Arc::new(Mutex::new(None)), rotation_check: ROTATION_CHECK_INTERVAL };
+        drop(log.current()?);
+        Ok(log)
+    }
+
+    pub fn with_rotation_check(mut self, interval: Duration) -> Self {
+        self.rotation_check = interval;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn current(&self) -> std::io::Result<std::sync::MutexGuard<'_, Option<OpenLog>>> {
+        let mut open = self.open.lock().unwrap_or_else(|e| e.into_inner());
+        if open.as_ref().is_some_and(|open| open.checked.elapsed() < self.rotation_check) {
+            return Ok(open);
+        }
+        let on_disk = std::fs::metadata(&self.path).ok().map(|metadata| metadata.ino());
+        match open.as_mut() {
+            Some(current) if on_disk == Some(current.inode) => current.checked = Instant::now(),
+            _ => {
+                let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+                let inode = file.metadata()?.ino();
+                *open = Some(OpenLog { file, inode, checked: Instant::now() });
+            },
+        }
+        Ok(open)
+    }
+}
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut open = self.current()?;
+        open.as_mut().expect("opened by current").file.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.open.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            Some(open) => open.file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Reads what has been appended to a log since the last poll. A file that
+/// shrank was truncated and one with a new inode was replaced; either way
+/// reading restarts at its beginning. A missing file reads as nothing yet.
+#[derive(Debug)]
+pub struct Follower {
+    path: PathBuf,
+    open: Option<(File, u64)>,
+    offset: u64,
+}
+
+impl Follower {
+    /// Follow from the current end of the file, after the last `lines` lines.
+    /// Only the tail is read, scanning back from the end a block at a time.
+    pub fn from_tail(path: impl Into<PathBuf>, lines: usize) -> std::io::Result<(Self, String)> {
+        let mut follower = Self { path: path.into(), open: None, offset: 0 };
+        let Ok(mut file) = File::open(&follower.path) else {
+            return Ok((follower, String::new()));
+        };
+        let metadata = file.metadata()?;
+        follower.offset = tail_start(&mut file, metadata.len(), lines)?;
+        follower.open = Some((file, metadata.ino()));
+        let tail = follower.poll()?;
+        Ok((follower, tail))
+    }
+
+    pub fn poll(&mut self) -> std::io::Result<String> {
+        let Ok(metadata) = std::fs::metadata(&self.path) else {
+            return Ok(String::new());
+        };
+        let replaced = self.open.as_ref().is_some_and(|(_, inode)| *inode != metadata.ino());
+        if self.open.is_none() || replaced {
+            self.open = Some((File::open(&self.path)?, metadata.ino()));
+            self.offset = 0;
+        }
+        let (file, _) = self.open.as_mut().expect("opened above");
+        if file.metadata()?.len() < self.offset {
+            self.offset = 0;
+        }
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut appended = Vec::new();
+        file.read_to_end(&mut appended)?;
+        self.offset += appended.len() as u64;
+        Ok(String::from_utf8_lossy(&appended).into_owned())
+    }
+}
+
+/// Where the last `lines` lines of the first `len` bytes of `file` start: just
+/// past the newline before them, or 0 when there are no more lines than that
+fn tail_start(file: &mut File, len: u64, lines: usize) -> std::io::Result<u64> {
+    const BLOCK: u64 = 8 * 1024;
+    let mut block = vec![0; BLOCK as usize];
+    let (mut end, mut newlines) = (len, 0);
+    while end > 0 {
+        let start = end.saturating_sub(BLOCK);
+        let chunk = &mut block[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(chunk)?;
+        for (i, byte) in chunk.iter().enumerate().rev() {
+            if *byte == b'\n' {
+                if newlines == lines {
+                    return Ok(start + i as u64 + 1);
+                }
+                newlines += 1;
+            }
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_follower_and_writer_continue_across_rotation() {
+        let dir = std::env::temp_dir().join(format!("anf-logs-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("anfd.log");
+        let mut log = LogFile::open(&path).unwrap().with_rotation_check(Duration::ZERO);
+        writeln!(log, "one\ntwo\nthree").unwrap();
+
+        let (mut follower, tail) = Follower::from_tail(&path, 2).unwrap();
+        assert_eq!(tail, "two\nthree\n");
+        assert_eq!(follower.poll().unwrap(), "");
+        writeln!(log, "four").unwrap();
+        assert_eq!(follower.poll().unwrap(), "four\n");
+
+        // copytruncate-style rotation
+        std::fs::OpenOptions::new().write(true).open(&path).unwrap().set_len(0).unwrap();
+        writeln!(log, "five").unwrap();
+        assert_eq!(follower.poll().unwrap(), "five\n");
+
+        // Rename-and-recreate rotation: the writer moves to the new file too
+        std::fs::rename(&path, dir.join("anfd.log.1")).unwrap();
+        assert_eq!(follower.poll().unwrap(), "");
+        writeln!(log, "six").unwrap();
+        assert_eq!(follower.poll().unwrap(), "six\n");
+        assert_eq!(std::fs::read_to_string(dir.join("anfd.log.1")).unwrap(), "five\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_tail_spans_blocks_and_short_files() {
+        let dir = std::env::temp_dir().join(format!("anf-tail-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("anfd.log");
+        let mut log = LogFile::open(&path).unwrap();
+        for i in 0..5000 {
+            writeln!(log, "line {}", i).unwrap();
+        }
+        let (_, tail) = Follower::from_tail(&path, 3).unwrap();
+        assert_eq!(tail, "line 4997\nline 4998\nline 4999\n");
+        let (_, everything) = Follower::from_tail(&path, 10_000).unwrap();
+        assert_eq!(everything.lines().count(), 5000);
+        let (_, missing) = Follower::from_tail(dir.join("nope.log"), 3).unwrap();
+        assert_eq!(missing, "");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
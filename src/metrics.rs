@@ -0,0 +1,168 @@
+// ANF Metrics - Task latency percentiles over a rolling window of completed tasks
+// Durations go into log-spaced buckets, so percentiles are approximate (within BUCKET_GROWTH)
+
+use std::collections::{BTreeMap, VecDeque};
+use serde::{Deserialize, Serialize};
+
+/// Completed tasks each window remembers
+pub const WINDOW: usize = 1024;
+
+/// Each bucket's upper bound is this much above the previous one's
+const BUCKET_GROWTH: f64 = 1.05;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct LatencySummary {
+    pub count: usize,
+    pub p50_ms: u64,
+    pub p90_ms: u64,
+    pub p99_ms: u64,
+}
+
+/// The last `capacity` durations, with their bucket counts kept alongside so
+/// percentiles don't need a sort
+#[derive(Debug, Clone)]
+pub struct LatencyWindow {
+    capacity: usize,
+    recent: VecDeque<u64>,
+    buckets: Vec<u32>,
+}
+
+impl Default for LatencyWindow {
+    fn default() -> Self {
+        Self::new(WINDOW)
+    }
+}
+
+impl LatencyWindow {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity: capacity.max(1), recent: VecDeque::new(), buckets: Vec::new() }
+    }
+
+    pub fn record(&mut self, duration_ms: u64) {
+        if self.recent.len() == self.capacity {
+            if let Some(oldest) = self.recent.pop_front() {
+                self.buckets[bucket(oldest)] -= 1;
+            }
+        }
+        let index = bucket(duration_ms);
+        if self.buckets.len() <= index {
+            self.buckets.resize(index + 1, 0);
+        }
+        self.buckets[index] += 1;
+        self.recent.push_back(duration_ms);
+    }
+
+    pub fn len(&self) -> usize {
+        self.recent.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recent.is_empty()
+    }
+
+    /// The upper bound of the bucket holding the `quantile` (0..=1) duration;
+    /// 0 while nothing has been recorded
+    pub fn percentile(&self, quantile: f64) -> u64 {
+        let rank = ((quantile.clamp(0.0, 1.0) * self.len() as f64).ceil() as usize).max(1);
+        let mut seen = 0;
+        for (index, count) in self.buckets.iter().enumerate() {
+            seen += *count as usize;
+            if seen >= rank {
+                return upper_bound(index);
+            }
+        }
+        0
+    }
+
+    pub fn summary(&self) -> LatencySummary {
+        LatencySummary {
+            count: self.len(),
+            p50_ms: self.percentile(0.5),
+            p90_ms: self.percentile(0.9),
+            p99_ms: self.percentile(0.99),
+        }
+    }
+}
+
+/// Bucket 0 holds 0ms; bucket `i` holds durations up to `BUCKET_GROWTH^(i-1)`
+fn bucket(duration_ms: u64) -> usize {
+    if duration_ms == 0 {
+        return 0;
+    }
+    ((duration_ms as f64).ln() / BUCKET_GROWTH.ln()).ceil() as usize + 1
+}
+
+fn upper_bound(index: usize) -> u64 {
+    match index {
+        0 => 0,
+        i => BUCKET_GROWTH.powi(i as i32 - 1).floor() as u64,
+    }
+}
+
+/// What `anf daemon bench` measured; latency runs from submission to completion
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BenchReport {
+    pub tasks: usize,
+    pub concurrency: usize,
+    pub elapsed_ms: u64,
+    pub throughput_per_sec: f64,
+    pub p50_ms: u64,
+    pub p99_ms: u64,
+    pub max_queue_depth: usize,
+}
+
+/// Latency across all tasks and per agent that fulfilled them
+#[derive(Debug, Clone, Default)]
+pub struct TaskLatencies {
+    overall: LatencyWindow,
+    agents: BTreeMap<String, LatencyWindow>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct LatencyReport {
+    pub overall: LatencySummary,
+    pub agents: BTreeMap<String, LatencySummary>,
+}
+
+impl TaskLatencies {
+    pub fn record(&mut self, agent: &str, duration_ms: u64) {
+        self.overall.record(duration_ms);
+        self.agents.entry(agent.to_string()).or_default().record(duration_ms);
+    }
+
+    pub fn report(&self) -> LatencyReport {
+        LatencyReport {
+            overall: self.overall.summary(),
+            agents: self.agents.iter().map(|(agent, window)| (agent.clone(), window.summary())).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles_of_known_durations_within_bucket_tolerance() {
+        let mut latencies = TaskLatencies::default();
+        for ms in 1..=1000 {
+            latencies.record(if ms % 2 == 0 { "coder" } else { "reviewer" }, ms);
+        }
+        let report = latencies.report();
+        let close = |actual: u64, expected: u64| (actual as f64 - expected as f64).abs() <= expected as f64 * (BUCKET_GROWTH - 1.0);
+        assert_eq!(report.overall.count, 1000);
+        assert!(close(report.overall.p50_ms, 500), "p50 {}", report.overall.p50_ms);
+        assert!(close(report.overall.p90_ms, 900), "p90 {}", report.overall.p90_ms);
+        assert!(close(report.overall.p99_ms, 990), "p99 {}", report.overall.p99_ms);
+        assert_eq!(report.agents["coder"].count, 500);
+
+        // The window forgets the oldest durations
+        let mut window = LatencyWindow::new(10);
+        for ms in [5000; 10].into_iter().chain([10; 10]) {
+            window.record(ms);
+        }
+        assert_eq!(window.len(), 10);
+        assert!(close(window.percentile(0.99), 10));
+        assert_eq!(LatencyWindow::default().summary(), LatencySummary::default());
+    }
+}
@@ -0,0 +1,138 @@
+// ANF Paths - The one place that decides where ~/.anf lives
+// Everything that needs the home directory (config, sessions, workflows, artifacts, state) goes through here
+
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+static ANF_DIR: OnceLock<Result<PathBuf, String>> = OnceLock::new();
+
+/// `~/.anf`, or a per-user directory under the temp dir when there is no home
+/// directory or it can't be written; resolved once per process
+pub fn anf_dir() -> PathBuf {
+    match try_anf_dir() {
+        Ok(dir) => dir,
+        // Writes under it fail through `ensure_dir`, which repeats the reason
+        Err(_) => candidates(dirs::home_dir(), &std::env::temp_dir()).remove(0),
+    }
+}
+
+/// `anf_dir`, or why no writable ANF directory could be found
+pub fn try_anf_dir() -> anyhow::Result<PathBuf> {
+    ANF_DIR.get_or_init(|| resolve_anf_dir(dirs::home_dir(), &std::env::temp_dir()).map_err(|e| e.to_string()))
+        .clone()
+        .map_err(anyhow::Error::msg)
+}
+
+/// A path inside the ANF directory, e.g. `anf_path("sessions")`
+pub fn anf_path(relative: impl AsRef<Path>) -> PathBuf {
+    anf_dir().join(relative)
+}
+
+/// Create `dir` if needed, explaining the failure in terms of the ANF directory
+pub fn ensure_dir(dir: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dir).map_err(|e| match try_anf_dir() {
+        Err(unresolved) if dir.starts_with(anf_dir()) => unresolved,
+        _ => anyhow::anyhow!(
+            "No writable ANF directory: could not create {} ({}); set HOME to a writable location",
+            dir.display(), e
+        ),
+    })
+}
+
+/// Whether `name` can be used as one file name inside an ANF directory:
+/// letters, digits, `-`, `_` and `.`, with no leading dot and no `..`
+pub fn is_slug(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && !name.contains("..")
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// `<home>/.anf` when there is a home, then `<temp>/anf-<user>`
+fn candidates(home: Option<PathBuf>, temp: &Path) -> Vec<PathBuf> {
+    let user = std::env::var("USER").unwrap_or_else(|_| "user".to_string());
+    home.filter(|home| !home.as_os_str().is_empty())
+        .map(|home| home.join(".anf"))
+        .into_iter()
+        .chain(std::iter::once(temp.join(format!("anf-{}", user))))
+        .collect()
+}
+
+/// The first candidate that can be created and written to
+fn resolve_anf_dir(home: Option<PathBuf>, temp: &Path) -> anyhow::Result<PathBuf> {
+    let mut tried = Vec::new();
+    for dir in candidates(home, temp) {
+        match writable(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) => tried.push(format!("{} ({})", dir.display(), e)),
+        }
+    }
+    Err(anyhow::anyhow!("No writable ANF directory: tried {}; set HOME to a writable location", tried.join(", ")))
+}
+
+fn writable(dir: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let probe = dir.join(format!(".write-test-{}", std::process::id()));
+    std::fs::write(&probe, b"")?;
+    std::fs::remove_file(probe)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::Session;
+
+    fn scratch() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("anf-paths-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_no_home_falls_back_to_temp_dir() {
+        let (home, temp) = (scratch(), scratch());
+        assert_eq!(resolve_anf_dir(Some(home.clone()), &temp).unwrap(), home.join(".anf"));
+
+        for missing in [None, Some(PathBuf::new())] {
+            let dir = resolve_anf_dir(missing, &temp).unwrap();
+            assert!(dir.starts_with(&temp), "{}", dir.display());
+            let session = Session {
+                name: "no-home".to_string(),
+                agents: vec!["rust-pro".to_string()],
+                context: None,
+                selected_agent: None,
+                saved_at: chrono::Utc::now(),
+            };
+            let path = session.save_in(&dir.join("sessions")).unwrap();
+            assert!(path.starts_with(&temp) && !path.starts_with("/.anf"), "{}", path.display());
+        }
+        std::fs::remove_dir_all(home).unwrap();
+        std::fs::remove_dir_all(temp).unwrap();
+    }
+
+    #[test]
+    fn test_unwritable_home_falls_back_and_nothing_writable_is_an_error() {
+        let temp = scratch();
+        // A file where a directory should be can't be written to, even as root
+        let home = temp.join("home-is-a-file");
+        std::fs::write(&home, b"").unwrap();
+        let dir = resolve_anf_dir(Some(home.clone()), &temp).unwrap();
+        assert_eq!(dir.parent(), Some(temp.as_path()));
+
+        let err = resolve_anf_dir(Some(home.clone()), &home).unwrap_err().to_string();
+        assert!(err.starts_with("No writable ANF directory: tried "), "{}", err);
+        assert!(err.contains(&home.join(".anf").display().to_string()), "{}", err);
+        assert!(err.ends_with("set HOME to a writable location"), "{}", err);
+        std::fs::remove_dir_all(temp).unwrap();
+    }
+
+    #[test]
+    fn test_slugs_stay_inside_their_directory() {
+        for name in ["rust-pro", "team_notes", "v1.2"] {
+            assert!(is_slug(name), "{}", name);
+        }
+        for name in ["", "..", "../../x", "a/b", "a\\b", ".hidden", "a..b", "two words"] {
+            assert!(!is_slug(name), "{}", name);
+        }
+    }
+}
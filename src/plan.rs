@@ -0,0 +1,106 @@
+// ANF Plans - What a run would submit, with rough cost and latency, without submitting it
+// Estimates come from prompt size only; they are for comparing plans, not for billing
+
+use serde::{Deserialize, Serialize};
+use crate::swarm::SwarmStatus;
+
+/// Rates used to turn a token estimate into cost and latency
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CostModel {
+    pub usd_per_1k_tokens: f64,
+    /// Fixed overhead per task
+    pub base_latency_ms: u64,
+    pub ms_per_token: f64,
+    /// Answer length assumed on top of the prompt
+    pub expected_output_tokens: u64,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        Self {
+            usd_per_1k_tokens: 0.01,
+            base_latency_ms: 500,
+            ms_per_token: 20.0,
+            expected_output_tokens: 500,
+        }
+    }
+}
+
+impl CostModel {
+    /// About four characters per token
+    pub fn estimate_tokens(prompt: &str) -> u64 {
+        (prompt.chars().count() as u64).div_ceil(4)
+    }
+
+    pub fn cost_usd(&self, tokens: u64) -> f64 {
+        tokens as f64 / 1000.0 * self.usd_per_1k_tokens
+    }
+
+    pub fn plan_task(&self, agent: &str, prompt: &str) -> PlannedTask {
+        let tokens = Self::estimate_tokens(prompt) + self.expected_output_tokens;
+        PlannedTask {
+            agent: agent.to_string(),
+            prompt: prompt.to_string(),
+            estimated_tokens: tokens,
+            estimated_cost_usd: self.cost_usd(tokens),
+            estimated_latency_ms: self.base_latency_ms + (tokens as f64 * self.ms_per_token) as u64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedTask {
+    pub agent: String,
+    /// Exactly what the agent would be sent
+    pub prompt: String,
+    pub estimated_tokens: u64,
+    pub estimated_cost_usd: f64,
+    pub estimated_latency_ms: u64,
+}
+
+/// Tasks that run concurrently: cost adds up, latency is the slowest task
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    pub tasks: Vec<PlannedTask>,
+    pub estimated_cost_usd: f64,
+    pub estimated_latency_ms: u64,
+}
+
+impl Plan {
+    pub fn concurrent(tasks: Vec<PlannedTask>) -> Self {
+        Self {
+            estimated_cost_usd: tasks.iter().map(|t| t.estimated_cost_usd).sum(),
+            estimated_latency_ms: tasks.iter().map(|t| t.estimated_latency_ms).max().unwrap_or(0),
+            tasks,
+        }
+    }
+
+    /// One task per member, each given the whole task, as `swarm execute` submits them
+    pub fn for_swarm(swarm: &SwarmStatus, task: &str, costs: &CostModel) -> Self {
+        Self::concurrent(swarm.members.iter().map(|member| costs.plan_task(member, task)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swarm_plan_has_one_task_per_member() {
+        let swarm = SwarmStatus::new("research", "mesh", vec!["rust-pro".to_string(), "coder".to_string(), "reviewer".to_string()]);
+        let plan = Plan::for_swarm(&swarm, "Audit the unsafe blocks", &CostModel::default());
+
+        let agents: Vec<&str> = plan.tasks.iter().map(|t| t.agent.as_str()).collect();
+        assert_eq!(agents, vec!["rust-pro", "coder", "reviewer"]);
+        assert!(plan.tasks.iter().all(|t| t.prompt == "Audit the unsafe blocks"));
+
+        // 23 chars -> 6 tokens, plus 500 expected output
+        assert_eq!(plan.tasks[0].estimated_tokens, 506);
+        assert!((plan.estimated_cost_usd - 3.0 * 0.00506).abs() < 1e-9);
+        assert_eq!(plan.estimated_latency_ms, plan.tasks[0].estimated_latency_ms);
+
+        let empty = Plan::for_swarm(&SwarmStatus::new("idle", "mesh", Vec::new()), "x", &CostModel::default());
+        assert!(empty.tasks.is_empty());
+        assert_eq!(empty.estimated_latency_ms, 0);
+    }
+}
@@ -0,0 +1,140 @@
+// ANF Rate Limits - Token buckets per client and action class at the daemon boundary
+// Mutating actions draw from their own bucket so a spawn flood can't also starve queries
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::config::{Rate, RateLimitSettings};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ActionClass {
+    Mutating,
+    Query,
+}
+
+impl ActionClass {
+    pub fn of(action: &str) -> Self {
+        if crate::wal::MUTATING_ACTIONS.contains(&action) {
+            Self::Mutating
+        } else {
+            Self::Query
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    mutating: Rate,
+    query: Rate,
+    /// Only clients that drew recently: a bucket back at burst is the same as none
+    buckets: Mutex<HashMap<(String, ActionClass), Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(mutating: Rate, query: Rate) -> Self {
+        Self { mutating, query, buckets: Mutex::new(HashMap::new()) }
+    }
+
+    /// No limiter when limiting is turned off
+    pub fn from_settings(settings: &RateLimitSettings) -> Option<Self> {
+        settings.enabled.then(|| Self::new(settings.mutating, settings.query))
+    }
+
+    /// Take a token for `client` running `action`, or say how long until one is free
+    pub fn check(&self, client: &str, action: &str) -> Result<(), Duration> {
+        self.check_at(client, action, Instant::now())
+    }
+
+    pub fn check_at(&self, client: &str, action: &str, now: Instant) -> Result<(), Duration> {
+        let class = ActionClass::of(action);
+        let rate = self.rate(class);
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        buckets.retain(|(_, class), bucket| self.level(*class, bucket, now) < self.rate(*class).burst as f64);
+        let bucket = buckets.entry((client.to_string(), class))
+            .or_insert(Bucket { tokens: rate.burst as f64, refilled: now });
+
+        bucket.tokens = self.level(class, bucket, now);
+        bucket.refilled = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        if rate.per_sec <= 0.0 {
+            return Err(Duration::MAX);
+        }
+        Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate.per_sec))
+    }
+
+    /// Whether some client would be refused a `class` action right now. Looks
+    /// without taking a token.
+    pub fn throttled(&self, class: ActionClass) -> bool {
+        self.throttled_at(class, Instant::now())
+    }
+
+    pub fn throttled_at(&self, class: ActionClass, now: Instant) -> bool {
+        let buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        buckets.iter()
+            .filter(|((_, bucket_class), _)| *bucket_class == class)
+            .any(|(_, bucket)| self.level(class, bucket, now) < 1.0)
+    }
+
+    fn rate(&self, class: ActionClass) -> Rate {
+        match class {
+            ActionClass::Mutating => self.mutating,
+            ActionClass::Query => self.query,
+        }
+    }
+
+    /// Tokens `bucket` would hold at `now`
+    fn level(&self, class: ActionClass, bucket: &Bucket, now: Instant) -> f64 {
+        let rate = self.rate(class);
+        let elapsed = now.saturating_duration_since(bucket.refilled).as_secs_f64();
+        (bucket.tokens + elapsed * rate.per_sec).min(rate.burst as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets_refill_per_client_and_class() {
+        let limiter = RateLimiter::new(Rate { per_sec: 2.0, burst: 2 }, Rate { per_sec: 100.0, burst: 5 });
+        let start = Instant::now();
+
+        assert!(limiter.check_at("uid:1000", "spawn_agent", start).is_ok());
+        assert!(!limiter.throttled_at(ActionClass::Mutating, start));
+        assert!(limiter.check_at("uid:1000", "exec", start).is_ok());
+        assert!(limiter.throttled_at(ActionClass::Mutating, start));
+        assert!(!limiter.throttled_at(ActionClass::Query, start));
+        assert_eq!(limiter.check_at("uid:1000", "spawn_agent", start), Err(Duration::from_millis(500)));
+
+        // Queries and other clients have their own buckets
+        assert!(limiter.check_at("uid:1000", "list_agents", start).is_ok());
+        assert!(limiter.check_at("token", "spawn_agent", start).is_ok());
+
+        assert!(limiter.check_at("uid:1000", "spawn_agent", start + Duration::from_millis(500)).is_ok());
+        assert!(limiter.check_at("uid:1000", "spawn_agent", start + Duration::from_millis(500)).is_err());
+    }
+
+    #[test]
+    fn test_refilled_buckets_are_dropped() {
+        let limiter = RateLimiter::new(Rate { per_sec: 2.0, burst: 2 }, Rate { per_sec: 100.0, burst: 5 });
+        let start = Instant::now();
+        for uid in 0..100 {
+            assert!(limiter.check_at(&format!("uid:{}", uid), "spawn_agent", start).is_ok());
+        }
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 100);
+
+        // Half a second refills one token, back to burst: only the new client is left
+        assert!(limiter.check_at("uid:1000", "list_agents", start + Duration::from_millis(500)).is_ok());
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 1);
+        assert!(!limiter.throttled_at(ActionClass::Mutating, start + Duration::from_millis(500)));
+    }
+}
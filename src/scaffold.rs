@@ -0,0 +1,125 @@
+// ANF Scaffold - `anf init`: the ~/.anf layout with a starter config, agent and workflow
+// Existing files are left alone unless forced, so running it again is harmless
+
+use std::path::{Path, PathBuf};
+
+pub const DIRECTORIES: &[&str] = &["agents", "workflows", "templates", "sessions", "state", "logs"];
+
+pub const STARTER_CONFIG: &str = r#"# ANF configuration. Every key is optional; `anf config show` lists the
+# effective values and where each came from.
+
+[daemon]
+# Defaults to $XDG_RUNTIME_DIR/anf.sock, or /tmp/anf.sock without it;
+# ANF_SOCKET or `anf --socket` override it
+# socket_path = "/tmp/anf.sock"
+log_level = "info"
+
+[interface]
+# auto, utf8 or ascii
+encoding = "auto"
+
+[agents]
+# default_agent = "coder"
+default_timeout = 300
+on_timeout = "fail"
+
+[rate_limit]
+enabled = true
+
+[hive]
+# sqlite or memory
+memory_backend = "sqlite"
+"#;
+
+pub const SAMPLE_AGENT: &str = r#"# A custom agent built on the stock coder. Anything left out is inherited from `base`.
+id = "example-reviewer"
+name = "Example Reviewer"
+base = "coder"
+description = "Reviews changes for correctness and readability"
+capabilities = ["code-review", "quality"]
+"#;
+
+pub const SAMPLE_WORKFLOW: &str = r#"# Run with `anf run example`
+name = "example"
+description = "Draft a change, then review it"
+
+[[steps]]
+id = "draft"
+agent = "coder"
+prompt = "Sketch a function that parses an ISO 8601 date"
+
+[[steps]]
+id = "review"
+agent = "example-reviewer"
+prompt = "Review the draft for edge cases"
+depends_on = ["draft"]
+"#;
+
+/// Starter files, relative to the ANF directory
+pub const FILES: &[(&str, &str)] = &[
+    ("config.toml", STARTER_CONFIG),
+    ("agents/example-reviewer.toml", SAMPLE_AGENT),
+    ("workflows/example.toml", SAMPLE_WORKFLOW),
+];
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct InitReport {
+    pub created: Vec<PathBuf>,
+    /// Files that were already there and kept
+    pub skipped: Vec<PathBuf>,
+}
+
+/// Lay out `root`. Directories are created as needed; starter files are written
+/// only where nothing exists yet, or over what's there with `force`.
+pub fn init(root: &Path, force: bool) -> anyhow::Result<InitReport> {
+    let mut report = InitReport::default();
+    for dir in std::iter::once(root.to_path_buf()).chain(DIRECTORIES.iter().map(|dir| root.join(dir))) {
+        if !dir.is_dir() {
+            crate::paths::ensure_dir(&dir)?;
+            report.created.push(dir);
+        }
+    }
+    for (relative, contents) in FILES {
+        let path = root.join(relative);
+        if path.exists() && !force {
+            report.skipped.push(path);
+            continue;
+        }
+        std::fs::write(&path, contents)
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))?;
+        report.created.push(path);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_lays_out_dirs_and_is_idempotent() {
+        let root = std::env::temp_dir().join(format!("anf-init-{}", uuid::Uuid::new_v4())).join(".anf");
+        let first = init(&root, false).unwrap();
+        for dir in DIRECTORIES {
+            assert!(root.join(dir).is_dir(), "{} missing", dir);
+        }
+        assert_eq!(first.created.len(), 1 + DIRECTORIES.len() + FILES.len());
+
+        let config = std::fs::read_to_string(root.join("config.toml")).unwrap();
+        let resolved = crate::config::Config::resolve(Some(&config), |_| None, &[]).unwrap();
+        assert_eq!(resolved.source("daemon.log_level"), Some(crate::config::ConfigSource::File));
+        let workflow = crate::workflow::Workflow::parse(SAMPLE_WORKFLOW).unwrap();
+        assert_eq!(workflow.execution_order().unwrap().len(), 2);
+
+        std::fs::write(root.join("config.toml"), "# mine\n").unwrap();
+        let second = init(&root, false).unwrap();
+        assert!(second.created.is_empty());
+        assert_eq!(second.skipped.len(), FILES.len());
+        assert_eq!(std::fs::read_to_string(root.join("config.toml")).unwrap(), "# mine\n");
+
+        let forced = init(&root, true).unwrap();
+        assert_eq!(forced.created.len(), FILES.len());
+        assert_eq!(std::fs::read_to_string(root.join("config.toml")).unwrap(), STARTER_CONFIG);
+        std::fs::remove_dir_all(root.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,115 @@
+// ANF Sessions - Terminal-agnostic snapshots of spawned agents and the active selection
+// Wave tab/pane layouts are saved separately by WaveIntegration on top of these
+
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub name: String,
+    /// Agents spawned in the daemon when the session was saved
+    pub agents: Vec<String>,
+    pub context: Option<PathBuf>,
+    pub selected_agent: Option<String>,
+    pub saved_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl Session {
+    pub fn dir() -> PathBuf {
+        crate::paths::anf_path("sessions")
+    }
+
+    pub fn save(&self) -> anyhow::Result<PathBuf> {
+        self.save_in(&Self::dir())
+    }
+
+    pub fn load(name: &str) -> anyhow::Result<Self> {
+        Self::load_from(&Self::dir(), name)
+    }
+
+    pub fn save_in(&self, dir: &Path) -> anyhow::Result<PathBuf> {
+        let path = Self::file_in(dir, &self.name)?;
+        crate::paths::ensure_dir(dir)?;
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+
+    pub fn load_from(dir: &Path, name: &str) -> anyhow::Result<Self> {
+        let path = Self::file_in(dir, name)?;
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("Session file not found: {} ({})", path.display(), e))?;
+        serde_json::from_str(&contents).map_err(|e| anyhow::anyhow!("Invalid session {}: {}", path.display(), e))
+    }
+
+    /// `<dir>/<name>.json`, for names that can't reach outside `dir`
+    pub fn file_in(dir: &Path, name: &str) -> anyhow::Result<PathBuf> {
+        if !crate::paths::is_slug(name) {
+            anyhow::bail!("Invalid session name '{}': use letters, digits, '-', '_' or '.'", name);
+        }
+        Ok(dir.join(format!("{}.json", name)))
+    }
+}
+
+/// The context and agent the CLI falls back to when a command doesn't name one
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ActiveState {
+    pub context: Option<PathBuf>,
+    pub agent: Option<String>,
+}
+
+impl ActiveState {
+    pub fn path() -> PathBuf {
+        crate::paths::anf_path("state/active.json")
+    }
+
+    /// Missing or unreadable state means nothing is selected
+    pub fn load() -> Self {
+        std::fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            crate::paths::ensure_dir(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_round_trips_spawned_agents() {
+        let dir = std::env::temp_dir().join(format!("anf-sessions-{}", uuid::Uuid::new_v4()));
+        let session = Session {
+            name: "incident".to_string(),
+            agents: vec!["incident-responder".to_string(), "rust-pro".to_string()],
+            context: Some(PathBuf::from("/srv/api")),
+            selected_agent: Some("rust-pro".to_string()),
+            saved_at: chrono::Utc::now(),
+        };
+
+        let path = session.save_in(&dir).unwrap();
+        assert_eq!(path, dir.join("incident.json"));
+
+        let restored = Session::load_from(&dir, "incident").unwrap();
+        assert_eq!(restored, session);
+        assert!(Session::load_from(&dir, "missing").is_err());
+
+        for name in ["../escaped", "nested/name", ".hidden", ""] {
+            let bad = Session { name: name.to_string(), ..session.clone() };
+            let err = bad.save_in(&dir).unwrap_err().to_string();
+            assert!(err.starts_with("Invalid session name"), "{}", err);
+            assert!(Session::load_from(&dir, name).is_err());
+        }
+        assert!(!dir.parent().unwrap().join("escaped.json").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
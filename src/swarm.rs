@@ -0,0 +1,258 @@
+// ANF Swarm Types - Structured views of swarm state reported by the Python bridge,
+// the topology and collaboration mode names it accepts, and how member results are aggregated
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SwarmStatus {
+    pub id: String,
+    pub topology: String,
+    pub members: Vec<String>,
+    pub running: u32,
+    pub queued: u32,
+    pub completed: u32,
+    pub failed: u32,
+    /// Share of finished tasks that completed; `None` until a task finishes
+    pub efficiency: Option<f64>,
+}
+
+impl SwarmStatus {
+    pub fn new(id: &str, topology: &str, members: Vec<String>) -> Self {
+        Self {
+            id: id.to_string(),
+            topology: topology.to_string(),
+            members,
+            running: 0,
+            queued: 0,
+            completed: 0,
+            failed: 0,
+            efficiency: None,
+        }
+    }
+
+    /// Build from the bridge's `swarm_status` payload, computing efficiency from its tallies
+    pub fn from_bridge(status: &serde_json::Value) -> Option<Self> {
+        let count = |key: &str| status.get(key).and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+        let members = status.get("active_agents")
+            .and_then(|v| v.as_array())
+            .map(|agents| agents.iter()
+                .filter_map(|agent| agent.get("agent_id").and_then(|v| v.as_str()).map(str::to_string))
+                .collect())
+            .unwrap_or_default();
+
+        let mut swarm = Self::new(
+            status.get("swarm_id")?.as_str()?,
+            status.get("topology").and_then(|v| v.as_str()).unwrap_or("adaptive"),
+            members,
+        );
+        swarm.running = count("running_tasks");
+        swarm.queued = count("task_queue_size");
+        swarm.completed = count("completed_tasks");
+        swarm.failed = count("failed_tasks");
+        swarm.efficiency = efficiency(swarm.completed, swarm.failed);
+        Some(swarm)
+    }
+
+    pub fn total_tasks(&self) -> u32 {
+        self.running + self.queued + self.completed + self.failed
+    }
+}
+
+/// How a swarm's members coordinate; the names the Python bridge accepts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Topology {
+    /// Queen-led: one agent oversees the others
+    Hierarchical,
+    /// Peer-to-peer: members coordinate directly
+    Mesh,
+    /// Hive mind: members pool what they find
+    Collective,
+    /// Switches topology to suit the team and task
+    #[default]
+    Adaptive,
+}
+
+/// How `collaborate` combines swarm and hive coordination
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum CollaborationMode {
+    /// Swarm coordination only
+    SwarmOnly,
+    /// Hive intelligence only
+    HiveOnly,
+    /// Swarm coordination informed by the hive
+    #[default]
+    Hybrid,
+    /// Picks a mode per task
+    Adaptive,
+}
+
+/// How a swarm picks one result from its members' outputs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum AggregationStrategy {
+    /// The output most members gave; ties go to the first member to give it
+    #[default]
+    MajorityVote,
+    /// The output its member was most confident in
+    BestByConfidence,
+}
+
+/// One member's finished output
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberResult {
+    pub agent: String,
+    pub output: String,
+    pub confidence: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Candidate {
+    pub agent: String,
+    pub output: String,
+    /// Votes for this output under `MajorityVote`, the reported confidence under `BestByConfidence`
+    pub score: f64,
+}
+
+/// The chosen result, and what it was chosen from and why
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Aggregation {
+    pub strategy: AggregationStrategy,
+    pub candidates: Vec<Candidate>,
+    /// Index into `candidates`
+    pub winner: usize,
+    pub rationale: String,
+}
+
+impl Aggregation {
+    pub fn result(&self) -> &Candidate {
+        &self.candidates[self.winner]
+    }
+}
+
+/// None when no member finished
+pub fn aggregate(strategy: AggregationStrategy, results: &[MemberResult]) -> Option<Aggregation> {
+    let scores: Vec<f64> = match strategy {
+        AggregationStrategy::MajorityVote => results.iter()
+            .map(|result| results.iter().filter(|other| other.output.trim() == result.output.trim()).count() as f64)
+            .collect(),
+        AggregationStrategy::BestByConfidence => results.iter().map(|result| result.confidence.unwrap_or(0.0)).collect(),
+    };
+    // The earliest of the best, so ties are stable
+    let winner = (0..results.len()).reduce(|best, i| if scores[i] > scores[best] { i } else { best })?;
+    let agent = &results[winner].agent;
+    let rationale = match strategy {
+        AggregationStrategy::MajorityVote => format!(
+            "{} of {} members gave {}'s output", scores[winner], results.len(), agent
+        ),
+        AggregationStrategy::BestByConfidence if results.iter().all(|result| result.confidence.is_none()) => format!(
+            "No member reported a confidence, so {}'s output was taken as the first", agent
+        ),
+        AggregationStrategy::BestByConfidence => format!(
+            "{} reported the highest confidence ({:.2})", agent, scores[winner]
+        ),
+    };
+    Some(Aggregation {
+        strategy,
+        candidates: results.iter().zip(scores)
+            .map(|(result, score)| Candidate { agent: result.agent.clone(), output: result.output.clone(), score })
+            .collect(),
+        winner,
+        rationale,
+    })
+}
+
+/// Every value of a CLI enum with the first line of its doc comment
+pub fn value_descriptions<T: clap::ValueEnum>() -> Vec<(String, String)> {
+    T::value_variants().iter()
+        .filter_map(T::to_possible_value)
+        .map(|value| (value.get_name().to_string(), value.get_help().map(|help| help.to_string()).unwrap_or_default()))
+        .collect()
+}
+
+impl std::fmt::Display for Topology {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = clap::ValueEnum::to_possible_value(self).expect("no skipped variants");
+        f.write_str(value.get_name())
+    }
+}
+
+impl std::fmt::Display for AggregationStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = clap::ValueEnum::to_possible_value(self).expect("no skipped variants");
+        f.write_str(value.get_name())
+    }
+}
+
+impl std::fmt::Display for CollaborationMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = clap::ValueEnum::to_possible_value(self).expect("no skipped variants");
+        f.write_str(value.get_name())
+    }
+}
+
+/// completed / (completed + failed)
+pub fn efficiency(completed: u32, failed: u32) -> Option<f64> {
+    let finished = completed + failed;
+    (finished > 0).then(|| f64::from(completed) / f64::from(finished))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_efficiency_from_tallies() {
+        let status = serde_json::json!({
+            "swarm_id": "research",
+            "topology": "mesh",
+            "active_agents": [{"agent_id": "rust-pro"}, {"agent_id": "coder"}],
+            "running_tasks": 1,
+            "task_queue_size": 2,
+            "completed_tasks": 8,
+            "failed_tasks": 2
+        });
+        let swarm = SwarmStatus::from_bridge(&status).unwrap();
+
+        assert_eq!(swarm.members, vec!["rust-pro", "coder"]);
+        assert_eq!(swarm.total_tasks(), 13);
+        assert!((swarm.efficiency.unwrap() - 0.8).abs() < f64::EPSILON);
+        assert_eq!(efficiency(0, 0), None);
+    }
+
+    #[test]
+    fn test_best_by_confidence_explains_each_candidate_and_the_winner() {
+        let member = |agent: &str, output: &str, confidence| MemberResult {
+            agent: agent.to_string(), output: output.to_string(), confidence,
+        };
+        let results = [member("coder", "use a Vec", Some(0.6)), member("rust-pro", "use a VecDeque", Some(0.9)), member("reviewer", "use a Vec", None)];
+
+        let explained = aggregate(AggregationStrategy::BestByConfidence, &results).unwrap();
+        let scores: Vec<(&str, f64)> = explained.candidates.iter().map(|c| (c.agent.as_str(), c.score)).collect();
+        assert_eq!(scores, vec![("coder", 0.6), ("rust-pro", 0.9), ("reviewer", 0.0)]);
+        assert_eq!(explained.result().agent, "rust-pro");
+        assert_eq!(explained.rationale, "rust-pro reported the highest confidence (0.90)");
+
+        let voted = aggregate(AggregationStrategy::MajorityVote, &results).unwrap();
+        assert_eq!((voted.result().agent.as_str(), voted.result().score), ("coder", 2.0));
+        assert_eq!(voted.rationale, "2 of 3 members gave coder's output");
+        assert_eq!(aggregate(AggregationStrategy::MajorityVote, &[]), None);
+    }
+
+    #[test]
+    fn test_topology_list_covers_every_variant() {
+        let topologies = value_descriptions::<Topology>();
+        let names: Vec<&str> = topologies.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["hierarchical", "mesh", "collective", "adaptive"]);
+        assert_eq!(topologies.len(), <Topology as clap::ValueEnum>::value_variants().len());
+        assert!(topologies.iter().all(|(_, description)| !description.is_empty()));
+
+        let modes = value_descriptions::<CollaborationMode>();
+        assert_eq!(modes[0].0, "swarm_only");
+        assert_eq!(serde_json::to_value(CollaborationMode::SwarmOnly).unwrap(), "swarm_only");
+        assert_eq!(Topology::default().to_string(), "adaptive");
+    }
+}
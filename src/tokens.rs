@@ -0,0 +1,189 @@
+// ANF Tokens - Counting tokens in prompts and output when an executor reports none
+// The heuristic needs nothing and is close enough for limits; with the `tiktoken`
+// feature a tiktoken ranks file gives the counts of the model family it belongs to
+
+use std::fmt;
+use std::sync::Arc;
+
+pub trait Tokenizer: Send + Sync + fmt::Debug {
+    /// Recorded alongside the counts, so they can be told apart from exact ones
+    fn name(&self) -> &str;
+
+    fn count(&self, text: &str) -> u32;
+}
+
+/// Roughly four characters to a token: each run of letters and digits counts
+/// a token per four characters and every other visible character one of its own
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeuristicTokenizer;
+
+impl Tokenizer for HeuristicTokenizer {
+    fn name(&self) -> &str {
+        "heuristic"
+    }
+
+    fn count(&self, text: &str) -> u32 {
+        let mut tokens = 0;
+        let mut run: u32 = 0;
+        for c in text.chars() {
+            if c.is_alphanumeric() {
+                run += 1;
+                continue;
+            }
+            tokens += run.div_ceil(4);
+            run = 0;
+            if !c.is_whitespace() {
+                tokens += 1;
+            }
+        }
+        tokens + run.div_ceil(4)
+    }
+}
+
+/// The tokenizer for `agents.tokenizer_file`: the heuristic when it is unset,
+/// otherwise the BPE ranks in that file
+pub fn configured(tokenizer_file: Option<&str>) -> anyhow::Result<Arc<dyn Tokenizer>> {
+    match tokenizer_file {
+        None => Ok(Arc::new(HeuristicTokenizer)),
+        #[cfg(feature = "tiktoken")]
+        Some(path) => Ok(Arc::new(BpeTokenizer::load(std::path::Path::new(path))?)),
+        #[cfg(not(feature = "tiktoken"))]
+        Some(path) => anyhow::bail!("agents.tokenizer_file is {} but anf was built without the tiktoken feature", path),
+    }
+}
+
+#[cfg(feature = "tiktoken")]
+pub use self::bpe::BpeTokenizer;
+
+#[cfg(feature = "tiktoken")]
+mod bpe {
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    /// Byte-pair encoding over the ranks in a `.tiktoken` file (`cl100k_base.tiktoken`
+    /// and friends: one base64 token and its rank per line). Text is split into pieces
+    /// the way cl100k does, minus the lookahead that keeps a trailing space for the
+    /// next word, so counts can differ from tiktoken's by a token around runs of spaces.
+    #[derive(Debug)]
+    pub struct BpeTokenizer {
+        name: String,
+        ranks: HashMap<Vec<u8>, u32>,
+        pieces: regex::Regex,
+    }
+
+    impl BpeTokenizer {
+        pub fn load(path: &Path) -> anyhow::Result<Self> {
+            let contents = std::fs::read_to_string(path)
+                .map_err(|e| anyhow::anyhow!("Failed to read tokenizer ranks {}: {}", path.display(), e))?;
+            let mut ranks = HashMap::new();
+            for (number, line) in contents.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+                let parsed = line.split_once(' ').and_then(|(token, rank)| Some((decode_base64(token)?, rank.trim().parse().ok()?)));
+                let Some((token, rank)) = parsed else {
+                    anyhow::bail!("{}:{}: expected a base64 token and a rank", path.display(), number + 1);
+                };
+                ranks.insert(token, rank);
+            }
+            let name = path.file_stem().map_or_else(|| "bpe".to_string(), |stem| stem.to_string_lossy().into_owned());
+            Ok(Self::new(name, ranks))
+        }
+
+        pub fn new(name: impl Into<String>, ranks: HashMap<Vec<u8>, u32>) -> Self {
+            let pieces = regex::Regex::new(
+                r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+",
+            ).expect("valid piece pattern");
+            Self { name: name.into(), ranks, pieces }
+        }
+
+        /// Tokens in one piece: merge the adjacent pair with the lowest rank until
+        /// no pair is in the vocabulary
+        fn encode_piece(&self, piece: &[u8]) -> usize {
+            if self.ranks.contains_key(piece) {
+                return 1;
+            }
+            let mut parts: Vec<&[u8]> = piece.chunks(1).collect();
+            loop {
+                let lowest = parts.windows(2).enumerate()
+                    .filter_map(|(i, pair)| {
+                        let merged = &piece[offset(piece, pair[0])..offset(piece, pair[1]) + pair[1].len()];
+                        self.ranks.get(merged).map(|rank| (*rank, i, merged))
+                    })
+                    .min();
+                let Some((_, i, merged)) = lowest else { return parts.len() };
+                parts[i] = merged;
+                parts.remove(i + 1);
+            }
+        }
+    }
+
+    fn offset(piece: &[u8], part: &[u8]) -> usize {
+        part.as_ptr() as usize - piece.as_ptr() as usize
+    }
+
+    impl super::Tokenizer for BpeTokenizer {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn count(&self, text: &str) -> u32 {
+            self.pieces.find_iter(text).map(|piece| self.encode_piece(piece.as_str().as_bytes())).sum::<usize>() as u32
+        }
+    }
+
+    fn decode_base64(text: &str) -> Option<Vec<u8>> {
+        let value = |c: u8| match c {
+            b'A'..=b'Z' => Some(c - b'A'),
+            b'a'..=b'z' => Some(c - b'a' + 26),
+            b'0'..=b'9' => Some(c - b'0' + 52),
+            b'+' => Some(62),
+            b'/' => Some(63),
+            _ => None,
+        };
+        let mut bytes = Vec::new();
+        let (mut buffer, mut bits) = (0u32, 0);
+        for c in text.trim_end_matches('=').bytes() {
+            buffer = (buffer << 6) | u32::from(value(c)?);
+            bits += 6;
+            if bits >= 8 {
+                bits -= 8;
+                bytes.push((buffer >> bits) as u8);
+            }
+        }
+        Some(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heuristic_count_is_stable_and_grows_with_the_prompt() {
+        let prompt = "Refactor the connection pool so idle sockets close after 30s, then add tests (unit + integration).";
+        let counts: Vec<u32> = prompt.char_indices()
+            .map(|(i, c)| HeuristicTokenizer.count(&prompt[..i + c.len_utf8()]))
+            .collect();
+        assert!(counts.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", counts);
+        assert_eq!(HeuristicTokenizer.count(prompt), *counts.last().unwrap());
+        assert_eq!(HeuristicTokenizer.count(prompt), 29);
+        assert_eq!(HeuristicTokenizer.count(&prompt.repeat(10)), 290);
+        assert_eq!(HeuristicTokenizer.count("   "), 0);
+    }
+
+    #[cfg(feature = "tiktoken")]
+    #[test]
+    fn test_bpe_merges_lowest_ranked_pairs_first() {
+        let dir = std::env::temp_dir().join(format!("anf-tokens-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tiny.tiktoken");
+        // "ab" 0, "abc" 1, "cd" 2, "bc" 3
+        std::fs::write(&path, "YWI= 0\nYWJj 1\nY2Q= 2\nYmM= 3\n").unwrap();
+        let tokenizer = BpeTokenizer::load(&path).unwrap();
+        assert_eq!(tokenizer.name(), "tiny");
+        assert_eq!(tokenizer.count("abc"), 1);
+        // ab goes first, then abc, which leaves d out of cd
+        assert_eq!(tokenizer.count("abcd"), 2);
+        // The space starts the second piece and is in no pair
+        assert_eq!(tokenizer.count("abcd abc"), 4);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,164 @@
+// ANF Transport - How clients reach the daemon: its Unix socket, or TCP
+// TCP is TLS unless the daemon was started with `--insecure`; clients trust only
+// the certificates in daemon.tls_ca, a CA or a self-signed daemon's own certificate
+
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpStream, UnixStream};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::{self, ClientConfig, RootCertStore, ServerConfig};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+use crate::config::DaemonSettings;
+
+/// Where a client reaches the daemon: a socket path, `tcp://host:port` or `tls://host:port`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    Unix(String),
+    Tcp(String),
+    Tls { addr: String, server_name: ServerName<'static> },
+}
+
+impl Endpoint {
+    pub fn parse(address: &str) -> anyhow::Result<Self> {
+        if let Some(addr) = address.strip_prefix("tcp://") {
+            return Ok(Self::Tcp(addr.to_string()));
+        }
+        let Some(addr) = address.strip_prefix("tls://") else {
+            return Ok(Self::Unix(address.to_string()));
+        };
+        let host = match addr.rsplit_once(':') {
+            Some((host, port)) if port.parse::<u16>().is_ok() => host.trim_start_matches('[').trim_end_matches(']'),
+            _ => anyhow::bail!("Invalid daemon address '{}': expected tls://host:port", address),
+        };
+        let server_name = ServerName::try_from(host.to_string())
+            .map_err(|e| anyhow::anyhow!("Invalid daemon address '{}': {}", address, e))?;
+        Ok(Self::Tls { addr: addr.to_string(), server_name })
+    }
+}
+
+/// A connection to the daemon, whichever transport carries it
+pub trait DaemonStream: AsyncRead + AsyncWrite + Unpin + Send {
+    /// Idle connections have nothing to read; EOF, stray bytes or an error
+    /// mean the connection can't be trusted with the next command
+    fn is_healthy(&self) -> bool;
+}
+
+fn idle(read: std::io::Result<usize>) -> bool {
+    matches!(read, Err(e) if e.kind() == std::io::ErrorKind::WouldBlock)
+}
+
+impl DaemonStream for UnixStream {
+    fn is_healthy(&self) -> bool {
+        idle(self.try_read(&mut [0u8; 1]))
+    }
+}
+
+impl DaemonStream for TcpStream {
+    fn is_healthy(&self) -> bool {
+        idle(self.try_read(&mut [0u8; 1]))
+    }
+}
+
+impl DaemonStream for tokio_rustls::client::TlsStream<TcpStream> {
+    /// Any record arriving unasked, even a close_notify, retires the connection
+    fn is_healthy(&self) -> bool {
+        self.get_ref().0.is_healthy()
+    }
+}
+
+/// Connect to `endpoint`; a `tls://` one needs `tls` to verify the daemon against
+pub async fn connect(endpoint: &Endpoint, tls: Option<&Arc<ClientConfig>>) -> std::io::Result<Box<dyn DaemonStream>> {
+    match endpoint {
+        Endpoint::Unix(path) => Ok(Box::new(UnixStream::connect(path).await?)),
+        Endpoint::Tcp(addr) => Ok(Box::new(TcpStream::connect(addr).await?)),
+        Endpoint::Tls { addr, server_name } => {
+            let tls = tls.ok_or_else(|| std::io::Error::other("a tls:// daemon address needs daemon.tls_ca to verify it"))?;
+            let stream = TcpStream::connect(addr).await?;
+            Ok(Box::new(TlsConnector::from(tls.clone()).connect(server_name.clone(), stream).await?))
+        },
+    }
+}
+
+/// Trust only the certificates in the PEM file at `ca`
+pub fn client_config(ca: &Path) -> anyhow::Result<Arc<ClientConfig>> {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_file_iter(ca).map_err(|e| anyhow::anyhow!("Failed to read {}: {}", ca.display(), e))? {
+        let cert = cert.map_err(|e| anyhow::anyhow!("Invalid certificate in {}: {}", ca.display(), e))?;
+        roots.add(cert).map_err(|e| anyhow::anyhow!("Invalid certificate in {}: {}", ca.display(), e))?;
+    }
+    if roots.is_empty() {
+        anyhow::bail!("No certificates in {}", ca.display());
+    }
+    let config = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+/// Present the certificate chain in `cert` with the private key in `key`, both PEM
+pub fn server_config(cert: &Path, key: &Path) -> anyhow::Result<Arc<ServerConfig>> {
+    let chain = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| anyhow::anyhow!("Failed to read certificates from {}: {}", cert.display(), e))?;
+    let key = PrivateKeyDer::from_pem_file(key)
+        .map_err(|e| anyhow::anyhow!("Failed to read a private key from {}: {}", key.display(), e))?;
+    let config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(chain, key)?;
+    Ok(Arc::new(config))
+}
+
+/// How the daemon's TCP listener is protected
+#[derive(Clone)]
+pub enum TcpSecurity {
+    Tls(TlsAcceptor),
+    /// Plaintext, acknowledged with `--insecure`
+    Insecure,
+}
+
+/// TLS from `daemon.tls_cert` and `daemon.tls_key`. Without them the listener
+/// only runs in plaintext when `insecure` acknowledges it.
+pub fn listen_security(settings: &DaemonSettings, insecure: bool) -> anyhow::Result<TcpSecurity> {
+    match (&settings.tls_cert, &settings.tls_key) {
+        (Some(cert), Some(key)) => Ok(TcpSecurity::Tls(TlsAcceptor::from(server_config(Path::new(cert), Path::new(key))?))),
+        (Some(_), None) | (None, Some(_)) => anyhow::bail!("daemon.tls_cert and daemon.tls_key must be set together"),
+        (None, None) if insecure => Ok(TcpSecurity::Insecure),
+        (None, None) => anyhow::bail!(
+            "daemon.tcp_listen without daemon.tls_cert and daemon.tls_key would take commands in plaintext; \
+             set them, or pass --insecure to accept that"
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_parses_each_transport() {
+        assert_eq!(Endpoint::parse("/tmp/anf.sock").unwrap(), Endpoint::Unix("/tmp/anf.sock".to_string()));
+        assert_eq!(Endpoint::parse("tcp://10.0.0.2:7420").unwrap(), Endpoint::Tcp("10.0.0.2:7420".to_string()));
+        assert_eq!(
+            Endpoint::parse("tls://anf.example.com:7420").unwrap(),
+            Endpoint::Tls { addr: "anf.example.com:7420".to_string(), server_name: ServerName::try_from("anf.example.com").unwrap() }
+        );
+        assert!(matches!(Endpoint::parse("tls://[::1]:7420").unwrap(), Endpoint::Tls { server_name: ServerName::IpAddress(_), .. }));
+        assert!(Endpoint::parse("tls://anf.example.com").is_err());
+    }
+
+    #[test]
+    fn test_plaintext_tcp_needs_insecure() {
+        let mut settings = DaemonSettings { tcp_listen: Some("127.0.0.1:7420".to_string()), ..DaemonSettings::default() };
+        let err = listen_security(&settings, false).err().unwrap().to_string();
+        assert!(err.contains("pass --insecure"), "{}", err);
+        assert!(matches!(listen_security(&settings, true).unwrap(), TcpSecurity::Insecure));
+
+        settings.tls_cert = Some("/nonexistent/cert.pem".to_string());
+        let err = listen_security(&settings, true).err().unwrap().to_string();
+        assert_eq!(err, "daemon.tls_cert and daemon.tls_key must be set together");
+    }
+}
@@ -0,0 +1,196 @@
+// ANF Write-Ahead Log - Mutating daemon commands appended before they are applied
+// Replayed on startup to rebuild state lost in a crash; truncated at each checkpoint
+// and compacted on request
+
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+
+/// Actions whose effects must survive a crash
+pub const MUTATING_ACTIONS: &[&str] = &["spawn_agent", "despawn_agent", "swarm_create", "swarm_dissolve", "exec", "submit_task"];
+
+/// Written once an `exec` or `submit_task` entry's task has finished, so replay doesn't re-queue it
+pub const TASK_DONE: &str = "task_done";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WalEntry {
+    pub seq: u64,
+    pub at: chrono::DateTime<chrono::Utc>,
+    pub action: String,
+    pub params: serde_json::Value,
+}
+
+#[derive(Debug)]
+pub struct CommandLog {
+    path: PathBuf,
+    /// Next sequence number; also serialises writers so lines never interleave
+    next_seq: Mutex<u64>,
+}
+
+impl CommandLog {
+    pub fn default_path() -> PathBuf {
+        crate::paths::anf_path("state/wal.jsonl")
+    }
+
+    pub fn open(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            crate::paths::ensure_dir(parent)?;
+        }
+        let next_seq = read_entries(&path)?.last().map_or(1, |entry| entry.seq + 1);
+        // Start the next entry on a fresh line if the last append was torn
+        if std::fs::read(&path).is_ok_and(|bytes| bytes.last().is_some_and(|b| *b != b'\n')) {
+            std::fs::OpenOptions::new().append(true).open(&path)?.write_all(b"\n")?;
+        }
+        Ok(Self { path, next_seq: Mutex::new(next_seq) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append and fsync one entry, returning its sequence number
+    pub fn append(&self, action: &str, params: &serde_json::Value) -> anyhow::Result<u64> {
+        let mut next_seq = self.next_seq.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = WalEntry {
+            seq: *next_seq,
+            at: chrono::Utc::now(),
+            action: action.to_string(),
+            params: params.clone(),
+        };
+
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all((serde_json::to_string(&entry)? + "\n").as_bytes())?;
+        file.sync_data()?;
+
+        *next_seq += 1;
+        Ok(entry.seq)
+    }
+
+    /// Entries not yet covered by a checkpoint, oldest first
+    pub fn entries(&self) -> anyhow::Result<Vec<WalEntry>> {
+        let _writer = self.next_seq.lock().unwrap_or_else(|e| e.into_inner());
+        read_entries(&self.path)
+    }
+
+    /// Drop every entry; call only once their effects are in a durable snapshot
+    pub fn truncate(&self) -> anyhow::Result<()> {
+        let _writer = self.next_seq.lock().unwrap_or_else(|e| e.into_inner());
+        let file = std::fs::OpenOptions::new().create(true).write(true).truncate(true).open(&self.path)?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    /// Rewrite the log without `exec`/`submit_task` entries whose task has
+    /// finished, along with their `task_done` markers. A marker whose entry
+    /// isn't in the log is kept. Appends wait for the rewrite, which replaces
+    /// the file by rename. Returns the bytes reclaimed.
+    pub fn compact(&self) -> anyhow::Result<u64> {
+        let _writer = self.next_seq.lock().unwrap_or_else(|e| e.into_inner());
+        let before = std::fs::metadata(&self.path).map_or(0, |meta| meta.len());
+        let entries = read_entries(&self.path)?;
+        let task_id = |entry: &WalEntry| entry.params.get("task_id").and_then(|v| v.as_str()).map(str::to_string);
+        let done: HashSet<String> = entries.iter()
+            .filter(|entry| entry.action == TASK_DONE)
+            .filter_map(task_id)
+            .collect();
+        let dropped: HashSet<String> = entries.iter()
+            .filter(|entry| matches!(entry.action.as_str(), "exec" | "submit_task"))
+            .filter_map(task_id)
+            .filter(|id| done.contains(id))
+            .collect();
+        let finished = |entry: &WalEntry| matches!(entry.action.as_str(), "exec" | "submit_task" | TASK_DONE)
+            && task_id(entry).is_some_and(|id| dropped.contains(&id));
+
+        let mut kept = String::new();
+        for entry in entries.iter().filter(|entry| !finished(entry)) {
+            kept.push_str(&(serde_json::to_string(entry)? + "\n"));
+        }
+        let tmp = self.path.with_extension("jsonl.tmp");
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(kept.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(before.saturating_sub(kept.len() as u64))
+    }
+}
+
+/// A torn final line (crash mid-append) is skipped; that command was never applied
+fn read_entries(path: &Path) -> anyhow::Result<Vec<WalEntry>> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(anyhow::anyhow!("Failed to read {}: {}", path.display(), e)),
+    };
+    Ok(contents.lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                tracing::warn!("Skipping unreadable WAL line in {}: {}", path.display(), e);
+                None
+            },
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_survives_reopen_and_torn_tail() {
+        let path = std::env::temp_dir().join(format!("anf-wal-{}.jsonl", uuid::Uuid::new_v4()));
+        let log = CommandLog::open(&path).unwrap();
+        assert_eq!(log.append("spawn_agent", &serde_json::json!({"agent_id": "rust-pro"})).unwrap(), 1);
+        assert_eq!(log.append("swarm_create", &serde_json::json!({"id": "research"})).unwrap(), 2);
+
+        // A crash halfway through the third append
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap()
+            .write_all(br#"{"seq":3,"at":"2026-"#).unwrap();
+
+        let reopened = CommandLog::open(&path).unwrap();
+        assert_eq!(reopened.append("swarm_dissolve", &serde_json::json!({"swarm_id": "research"})).unwrap(), 3);
+        let actions: Vec<String> = reopened.entries().unwrap().into_iter().map(|e| e.action).collect();
+        assert_eq!(actions, vec!["spawn_agent", "swarm_create", "swarm_dissolve"]);
+
+        reopened.truncate().unwrap();
+        assert!(reopened.entries().unwrap().is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_compact_keeps_only_unfinished_tasks() {
+        let path = std::env::temp_dir().join(format!("anf-wal-{}.jsonl", uuid::Uuid::new_v4()));
+        let log = CommandLog::open(&path).unwrap();
+        log.append("spawn_agent", &serde_json::json!({"agent_id": "coder"})).unwrap();
+        for i in 0..20 {
+            let task_id = format!("t{}", i);
+            let action = if i % 2 == 0 { "exec" } else { "submit_task" };
+            log.append(action, &serde_json::json!({"task_id": task_id})).unwrap();
+            if i != 7 && i != 8 {
+                log.append(TASK_DONE, &serde_json::json!({"task_id": task_id})).unwrap();
+            }
+        }
+        log.append(TASK_DONE, &serde_json::json!({"task_id": "checkpointed"})).unwrap();
+        let before = std::fs::metadata(&path).unwrap().len();
+
+        let reclaimed = log.compact().unwrap();
+        assert_eq!(reclaimed, before - std::fs::metadata(&path).unwrap().len());
+        let kept: Vec<(String, serde_json::Value)> = log.entries().unwrap().into_iter().map(|e| (e.action, e.params)).collect();
+        assert_eq!(kept, vec![
+            ("spawn_agent".to_string(), serde_json::json!({"agent_id": "coder"})),
+            ("submit_task".to_string(), serde_json::json!({"task_id": "t7"})),
+            ("exec".to_string(), serde_json::json!({"task_id": "t8"})),
+            (TASK_DONE.to_string(), serde_json::json!({"task_id": "checkpointed"})),
+        ]);
+        assert_eq!(log.compact().unwrap(), 0);
+
+        // Appending carries on after the rewrite
+        log.append("swarm_create", &serde_json::json!({"id": "research"})).unwrap();
+        assert_eq!(log.entries().unwrap().len(), 5);
+        std::fs::remove_file(path).unwrap();
+    }
+}
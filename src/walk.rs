@@ -0,0 +1,197 @@
+// ANF Walk - Directory traversal for `--context` directories and globs
+// Bounded by depth and file count, so a huge tree is reported rather than read;
+// .gitignore files along the way are honoured (the common subset: globs, `/`, `!`)
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalkLimits {
+    /// Directory levels to include; 1 is the files directly in the root
+    pub max_depth: usize,
+    pub max_files: usize,
+}
+
+impl Default for WalkLimits {
+    fn default() -> Self {
+        Self { max_depth: 8, max_files: 1000 }
+    }
+}
+
+/// Something the walk left out because of a limit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Skipped {
+    /// A directory below `max_depth`
+    TooDeep { dir: PathBuf, max_depth: usize },
+    /// The walk stopped once it had this many files
+    FileLimit(usize),
+}
+
+impl fmt::Display for Skipped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Skipped::TooDeep { dir, max_depth } => write!(f, "{} is deeper than --max-depth {}, skipped", dir.display(), max_depth),
+            Skipped::FileLimit(max_files) => write!(f, "Stopped after {} files (--max-files)", max_files),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Walk {
+    pub files: Vec<PathBuf>,
+    pub skipped: Vec<Skipped>,
+}
+
+/// An anchored regex for a glob: `*` and `?` stay within one path component
+/// and `**` spans any number of them
+pub fn glob_regex(pattern: &str) -> anyhow::Result<regex::Regex> {
+    let mut translated = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    translated.push_str("(?:.*/)?");
+                } else {
+                    translated.push_str(".*");
+                }
+            },
+            '*' => translated.push_str("[^/]*"),
+            '?' => translated.push_str("[^/]"),
+            c => translated.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    translated.push('$');
+    regex::Regex::new(&translated).map_err(|e| anyhow::anyhow!("Invalid glob '{}': {}", pattern, e))
+}
+
+/// The files under `root` that `accept` takes, in path order, within `limits`.
+/// Ignored entries and `.git` are passed over without counting against either limit.
+pub fn walk(root: &Path, limits: WalkLimits, accept: impl Fn(&Path) -> bool) -> Walk {
+    let mut walk = Walk::default();
+    let mut rules = Vec::new();
+    visit(root, 1, limits, &accept, &mut rules, &mut walk);
+    walk
+}
+
+fn visit(dir: &Path, depth: usize, limits: WalkLimits, accept: &dyn Fn(&Path) -> bool, rules: &mut Vec<IgnoreRule>, walk: &mut Walk) -> bool {
+    let inherited = rules.len();
+    rules.extend(IgnoreRule::load(dir));
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        rules.truncate(inherited);
+        return true;
+    };
+    let mut paths: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+    paths.sort();
+
+    let mut more = true;
+    for path in paths {
+        let is_dir = path.is_dir();
+        if path.file_name().is_some_and(|name| name == ".git") || IgnoreRule::ignores(rules, &path, is_dir) {
+            continue;
+        }
+        if is_dir {
+            if depth >= limits.max_depth {
+                walk.skipped.push(Skipped::TooDeep { dir: path, max_depth: limits.max_depth });
+            } else if !visit(&path, depth + 1, limits, accept, rules, walk) {
+                more = false;
+                break;
+            }
+        } else if path.is_file() && accept(&path) {
+            if walk.files.len() == limits.max_files {
+                walk.skipped.push(Skipped::FileLimit(limits.max_files));
+                more = false;
+                break;
+            }
+            walk.files.push(path);
+        }
+    }
+    rules.truncate(inherited);
+    more
+}
+
+/// One line of a .gitignore, relative to the directory it was found in
+struct IgnoreRule {
+    base: PathBuf,
+    matcher: regex::Regex,
+    negated: bool,
+    dir_only: bool,
+    /// Contains a `/`, so matched against the path from `base` instead of the name
+    anchored: bool,
+}
+
+impl IgnoreRule {
+    fn load(dir: &Path) -> Vec<Self> {
+        let Ok(contents) = std::fs::read_to_string(dir.join(".gitignore")) else { return Vec::new() };
+        contents.lines().filter_map(|line| {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+            let (negated, line) = line.strip_prefix('!').map_or((false, line), |rest| (true, rest));
+            let (dir_only, line) = line.strip_suffix('/').map_or((false, line), |rest| (true, rest));
+            let anchored = line.contains('/');
+            Some(Self {
+                base: dir.to_path_buf(),
+                matcher: glob_regex(line.trim_start_matches('/')).ok()?,
+                negated,
+                dir_only,
+                anchored,
+            })
+        }).collect()
+    }
+
+    /// Whether the last rule that matches `path` ignores it
+    fn ignores(rules: &[Self], path: &Path, is_dir: bool) -> bool {
+        let mut ignored = false;
+        for rule in rules.iter().filter(|rule| is_dir || !rule.dir_only) {
+            let Ok(relative) = path.strip_prefix(&rule.base) else { continue };
+            let subject = if rule.anchored {
+                relative.to_string_lossy()
+            } else {
+                path.file_name().unwrap_or_default().to_string_lossy()
+            };
+            if rule.matcher.is_match(&subject) {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_walk_stops_at_depth_and_file_limits_and_honours_gitignore() {
+        let root = std::env::temp_dir().join(format!("anf-walk-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("a/b/c")).unwrap();
+        std::fs::create_dir_all(root.join("target")).unwrap();
+        for file in ["top.rs", "a/one.rs", "a/b/two.rs", "a/b/c/three.rs", "a/debug.log", "a/keep.log", "target/out.rs"] {
+            std::fs::write(root.join(file), file).unwrap();
+        }
+        std::fs::write(root.join(".gitignore"), "/target/\n*.log\n").unwrap();
+        std::fs::write(root.join("a/.gitignore"), "!keep.log\n").unwrap();
+
+        let limits = WalkLimits { max_depth: 2, max_files: 100 };
+        let shallow = walk(&root, limits, |_| true);
+        let names: Vec<String> = shallow.files.iter()
+            .map(|path| path.strip_prefix(&root).unwrap().display().to_string())
+            .collect();
+        assert_eq!(names, vec![".gitignore", "a/.gitignore", "a/keep.log", "a/one.rs", "top.rs"]);
+        assert_eq!(shallow.skipped, vec![Skipped::TooDeep { dir: root.join("a/b"), max_depth: 2 }]);
+        assert_eq!(shallow.skipped[0].to_string(), format!("{} is deeper than --max-depth 2, skipped", root.join("a/b").display()));
+
+        let deep = walk(&root, WalkLimits::default(), |path| path.extension().is_some_and(|ext| ext == "rs"));
+        assert_eq!(deep.files.len(), 4);
+        assert!(deep.skipped.is_empty());
+
+        let capped = walk(&root, WalkLimits { max_depth: 8, max_files: 2 }, |path| path.extension().is_some_and(|ext| ext == "rs"));
+        assert_eq!(capped.files, vec![root.join("a/b/c/three.rs"), root.join("a/b/two.rs")]);
+        assert_eq!(capped.skipped, vec![Skipped::FileLimit(2)]);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
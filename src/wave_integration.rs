@@ -1,12 +1,14 @@
 // Wave Terminal Integration for ANF
-// Enhanced terminal features and seamless integration
+// Enhanced terminal features and seamless integration; agent tabs and panes
+// also open in tmux when anf isn't running inside Wave
 
-use std::collections::HashMap;
 use std::env;
-use std::process::{Command, Stdio};
+use std::fmt;
 use serde::{Deserialize, Serialize};
 use tokio::process::Command as AsyncCommand;
 
+use crate::daemon::BoxFuture;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WaveSession {
     pub session_id: String,
@@ -82,7 +84,7 @@ impl WaveIntegration {
 
         // Use Wave's session API if available
         let output = AsyncCommand::new("wave")
-            .args(&["session", "info", "--json"])
+            .args(["session", "info", "--json"])
             .output()
             .await?;
 
@@ -95,59 +97,46 @@ impl WaveIntegration {
         }
     }
 
-    /// Create new tab with agent
-    pub async fn create_agent_tab(&self, agent_id: &str, context_path: Option<&str>) -> anyhow::Result<String> {
-        if !Self::is_wave_terminal() {
-            return Err(anyhow::anyhow!("Not running in Wave Terminal"));
-        }
-
-        let tab_title = format!("🤖 {}", agent_id);
-        let mut cmd = AsyncCommand::new("wave");
-        cmd.args(&["tab", "create", "--title", &tab_title]);
-
-        if let Some(path) = context_path {
-            cmd.args(&["--cwd", path]);
-        }
+    /// The multiplexer anf is running inside, Wave or tmux
+    pub fn multiplexer(&self) -> anyhow::Result<Box<dyn Multiplexer>> {
+        self.multiplexer_for(PaneHost::detect())
+    }
 
-        // Start ANF in the new tab
-        cmd.args(&["--command", &format!("anf spawn {}", agent_id)]);
+    fn multiplexer_for(&self, host: Option<PaneHost>) -> anyhow::Result<Box<dyn Multiplexer>> {
+        host.map(PaneHost::multiplexer)
+            .ok_or_else(|| anyhow::anyhow!("Not running inside Wave Terminal or tmux; start anf in one of them to open agents in tabs and panes"))
+    }
 
-        let output = cmd.output().await?;
-        
-        if output.status.success() {
-            let tab_id = String::from_utf8(output.stdout)?;
-            Ok(tab_id.trim().to_string())
-        } else {
-            Err(anyhow::anyhow!("Failed to create Wave tab: {}", String::from_utf8_lossy(&output.stderr)))
-        }
+    /// Create new tab with agent
+    pub async fn create_agent_tab(&self, agent_id: &str, context_path: Option<&str>) -> anyhow::Result<String> {
+        self.multiplexer()?.open_tab(agent_id, context_path).await
     }
 
     /// Split pane with different agent
     pub async fn split_pane_with_agent(&self, agent_id: &str, direction: SplitDirection) -> anyhow::Result<String> {
-        if !Self::is_wave_terminal() {
-            return Err(anyhow::anyhow!("Not running in Wave Terminal"));
-        }
+        let multiplexer = self.multiplexer()?;
+        self.split_with(multiplexer.as_ref(), agent_id, direction).await
+    }
 
-        let direction_arg = match direction {
-            SplitDirection::Horizontal => "horizontal",
-            SplitDirection::Vertical => "vertical",
-        };
+    async fn split_with(&self, multiplexer: &dyn Multiplexer, agent_id: &str, direction: SplitDirection) -> anyhow::Result<String> {
+        if !self.config.enable_pane_splitting {
+            return Err(anyhow::anyhow!("Pane splitting is disabled in the Wave configuration"));
+        }
+        multiplexer.split_pane(agent_id, direction).await
+    }
 
-        let output = AsyncCommand::new("wave")
-            .args(&[
-                "pane", "split", 
-                "--direction", direction_arg,
-                "--command", &format!("anf spawn {}", agent_id)
-            ])
-            .output()
-            .await?;
+    /// `anf wave split`: split in Wave, or in tmux, or explain that neither is
+    /// available. Returns the line to print.
+    pub async fn split_from_cli(&self, agent_id: &str, direction: SplitDirection) -> anyhow::Result<String> {
+        self.split_in(PaneHost::detect(), agent_id, direction).await
+    }
 
-        if output.status.success() {
-            let pane_id = String::from_utf8(output.stdout)?;
-            Ok(pane_id.trim().to_string())
-        } else {
-            Err(anyhow::anyhow!("Failed to split pane: {}", String::from_utf8_lossy(&output.stderr)))
-        }
+    async fn split_in(&self, host: Option<PaneHost>, agent_id: &str, direction: SplitDirection) -> anyhow::Result<String> {
+        let Ok(multiplexer) = self.multiplexer_for(host) else {
+            return Ok(format!("Not running inside Wave Terminal or tmux; no pane split for {}", agent_id));
+        };
+        let pane_id = self.split_with(multiplexer.as_ref(), agent_id, direction).await?;
+        Ok(format!("Split {} {} pane {} for {}", direction, multiplexer.name(), pane_id, agent_id))
     }
 
     /// Save current session with active agents
@@ -161,22 +150,16 @@ impl WaveIntegration {
         
         if let Some(session) = session {
             // Save session configuration
-            let session_file = format!("{}/.anf/sessions/{}.json", 
-                env::var("HOME").unwrap_or_default(), 
-                session_name
-            );
-
-            // Ensure directory exists
-            if let Some(parent) = std::path::Path::new(&session_file).parent() {
-                std::fs::create_dir_all(parent)?;
-            }
+            let sessions_dir = crate::paths::anf_path("sessions");
+            let session_file = crate::session::Session::file_in(&sessions_dir, session_name)?;
+            crate::paths::ensure_dir(&sessions_dir)?;
 
             let session_json = serde_json::to_string_pretty(&session)?;
             std::fs::write(&session_file, session_json)?;
 
             // Also save in Wave's session format if supported
             let _ = AsyncCommand::new("wave")
-                .args(&["session", "save", session_name])
+                .args(["session", "save", session_name])
                 .output()
                 .await;
         }
@@ -186,13 +169,10 @@ impl WaveIntegration {
 
     /// Restore session with agents
     pub async fn restore_session(&self, session_name: &str) -> anyhow::Result<()> {
-        let session_file = format!("{}/.anf/sessions/{}.json", 
-            env::var("HOME").unwrap_or_default(), 
-            session_name
-        );
+        let session_file = crate::session::Session::file_in(&crate::paths::anf_path("sessions"), session_name)?;
 
-        if !std::path::Path::new(&session_file).exists() {
-            return Err(anyhow::anyhow!("Session file not found: {}", session_file));
+        if !session_file.exists() {
+            return Err(anyhow::anyhow!("Session file not found: {}", session_file.display()));
         }
 
         let session_data = std::fs::read_to_string(&session_file)?;
@@ -235,7 +215,7 @@ impl WaveIntegration {
 
         for (cmd, key, value) in config_commands {
             let _ = AsyncCommand::new("wave")
-                .args(&["config", cmd, key, value])
+                .args(["config", cmd, key, value])
                 .output()
                 .await;
         }
@@ -245,12 +225,11 @@ impl WaveIntegration {
 
     /// Create development environment layout
     pub async fn create_dev_environment(&self, project_path: &str, agents: &[&str]) -> anyhow::Result<()> {
-        if !Self::is_wave_terminal() {
-            return Err(anyhow::anyhow!("Wave Terminal required for environment creation"));
-        }
+        // Fail before opening anything outside Wave and tmux
+        self.multiplexer()?;
 
         // Create main tab for coordination
-        let main_tab = self.create_agent_tab("project-supervisor-orchestrator", Some(project_path)).await?;
+        let _main_tab = self.create_agent_tab("project-supervisor-orchestrator", Some(project_path)).await?;
 
         // Create specialized tabs for different agents
         for (i, &agent) in agents.iter().enumerate() {
@@ -276,7 +255,7 @@ impl WaveIntegration {
         if Self::is_wave_terminal() {
             // Get terminal dimensions from Wave
             if let Ok(output) = std::process::Command::new("wave")
-                .args(&["info", "terminal", "--json"])
+                .args(["info", "terminal", "--json"])
                 .output() 
             {
                 if output.status.success() {
@@ -297,12 +276,163 @@ impl WaveIntegration {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SplitDirection {
     Horizontal,
     Vertical,
 }
 
+impl std::str::FromStr for SplitDirection {
+    type Err = anyhow::Error;
+
+    /// `horizontal`/`h` or `vertical`/`v`, any case
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "horizontal" | "h" => Ok(Self::Horizontal),
+            "vertical" | "v" => Ok(Self::Vertical),
+            other => Err(anyhow::anyhow!("Invalid split direction '{}': expected horizontal or vertical", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for SplitDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Horizontal => "horizontal",
+            Self::Vertical => "vertical",
+        })
+    }
+}
+
+/// Something that can split the current terminal into panes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaneHost {
+    Wave,
+    Tmux,
+}
+
+impl PaneHost {
+    /// Wave wins when both are present (tmux running inside a Wave tab)
+    pub fn detect() -> Option<Self> {
+        let var = |name: &str| env::var(name).ok();
+        Self::from_env(var("TERM_PROGRAM").as_deref(), var("WAVETERM").as_deref(), var("TMUX").as_deref())
+    }
+
+    /// `detect` over the values of `TERM_PROGRAM`, `WAVETERM` and `TMUX`
+    pub fn from_env(term_program: Option<&str>, waveterm: Option<&str>, tmux: Option<&str>) -> Option<Self> {
+        if term_program.is_some_and(|term| term.contains("wave")) || waveterm.is_some() {
+            Some(Self::Wave)
+        } else if tmux.is_some_and(|tmux| !tmux.is_empty()) {
+            Some(Self::Tmux)
+        } else {
+            None
+        }
+    }
+
+    pub fn multiplexer(self) -> Box<dyn Multiplexer> {
+        match self {
+            Self::Wave => Box::new(WaveMultiplexer),
+            Self::Tmux => Box::new(TmuxMultiplexer),
+        }
+    }
+}
+
+/// Opens agents in new tabs and split panes of the terminal anf is running in.
+/// Each one runs `anf spawn <agent>`; the id returned is the multiplexer's own.
+pub trait Multiplexer: fmt::Debug + Send + Sync {
+    /// For messages: "Wave" or "tmux"
+    fn name(&self) -> &str;
+
+    fn open_tab<'a>(&'a self, agent_id: &'a str, context_path: Option<&'a str>) -> BoxFuture<'a, anyhow::Result<String>>;
+
+    fn split_pane<'a>(&'a self, agent_id: &'a str, direction: SplitDirection) -> BoxFuture<'a, anyhow::Result<String>>;
+}
+
+fn spawn_command(agent_id: &str) -> String {
+    format!("anf spawn {}", agent_id)
+}
+
+/// The trimmed stdout of `program args`, or its stderr as the error after `failure`
+async fn run_for_id(program: &str, args: &[String], failure: &str) -> anyhow::Result<String> {
+    let output = AsyncCommand::new(program).args(args).output().await?;
+    if output.status.success() {
+        Ok(String::from_utf8(output.stdout)?.trim().to_string())
+    } else {
+        Err(anyhow::anyhow!("{}: {}", failure, String::from_utf8_lossy(&output.stderr)))
+    }
+}
+
+#[derive(Debug)]
+pub struct WaveMultiplexer;
+
+impl WaveMultiplexer {
+    fn tab_args(agent_id: &str, context_path: Option<&str>) -> Vec<String> {
+        let mut args = vec!["tab".to_string(), "create".to_string(), "--title".to_string(), format!("🤖 {}", agent_id)];
+        if let Some(path) = context_path {
+            args.extend(["--cwd".to_string(), path.to_string()]);
+        }
+        args.extend(["--command".to_string(), spawn_command(agent_id)]);
+        args
+    }
+
+    fn split_args(agent_id: &str, direction: SplitDirection) -> Vec<String> {
+        ["pane", "split", "--direction", &direction.to_string(), "--command", &spawn_command(agent_id)]
+            .map(str::to_string)
+            .to_vec()
+    }
+}
+
+impl Multiplexer for WaveMultiplexer {
+    fn name(&self) -> &str {
+        "Wave"
+    }
+
+    fn open_tab<'a>(&'a self, agent_id: &'a str, context_path: Option<&'a str>) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move { run_for_id("wave", &Self::tab_args(agent_id, context_path), "Failed to create Wave tab").await })
+    }
+
+    fn split_pane<'a>(&'a self, agent_id: &'a str, direction: SplitDirection) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move { run_for_id("wave", &Self::split_args(agent_id, direction), "Failed to split pane").await })
+    }
+}
+
+/// Tabs are tmux windows; `-P -F` makes tmux print the new window or pane id
+#[derive(Debug)]
+pub struct TmuxMultiplexer;
+
+impl TmuxMultiplexer {
+    fn window_args(agent_id: &str, context_path: Option<&str>) -> Vec<String> {
+        let mut args = ["new-window", "-P", "-F", "#{window_id}", "-n", agent_id].map(str::to_string).to_vec();
+        if let Some(path) = context_path {
+            args.extend(["-c".to_string(), path.to_string()]);
+        }
+        args.push(spawn_command(agent_id));
+        args
+    }
+
+    fn split_args(agent_id: &str, direction: SplitDirection) -> Vec<String> {
+        let flag = match direction {
+            SplitDirection::Horizontal => "-h",
+            SplitDirection::Vertical => "-v",
+        };
+        ["split-window", flag, "-P", "-F", "#{pane_id}", &spawn_command(agent_id)].map(str::to_string).to_vec()
+    }
+}
+
+impl Multiplexer for TmuxMultiplexer {
+    fn name(&self) -> &str {
+        "tmux"
+    }
+
+    fn open_tab<'a>(&'a self, agent_id: &'a str, context_path: Option<&'a str>) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move { run_for_id("tmux", &Self::window_args(agent_id, context_path), "Failed to open tmux window").await })
+    }
+
+    fn split_pane<'a>(&'a self, agent_id: &'a str, direction: SplitDirection) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move { run_for_id("tmux", &Self::split_args(agent_id, direction), "Failed to split tmux pane").await })
+    }
+}
+
 #[derive(Debug)]
 pub struct WaveDisplayInfo {
     pub width: u16,
@@ -331,6 +461,12 @@ pub struct WaveUI {
     display_info: WaveDisplayInfo,
 }
 
+impl Default for WaveUI {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl WaveUI {
     pub fn new() -> Self {
         let integration = WaveIntegration::new(None);
@@ -423,6 +559,51 @@ mod tests {
         assert!(integration.config.enable_tab_management);
     }
 
+    #[test]
+    fn test_split_direction_from_str() {
+        assert_eq!("horizontal".parse::<SplitDirection>().unwrap(), SplitDirection::Horizontal);
+        assert_eq!(" Vertical ".parse::<SplitDirection>().unwrap(), SplitDirection::Vertical);
+        assert_eq!("h".parse::<SplitDirection>().unwrap(), SplitDirection::Horizontal);
+
+        let err = "diagonal".parse::<SplitDirection>().unwrap_err().to_string();
+        assert_eq!(err, "Invalid split direction 'diagonal': expected horizontal or vertical");
+    }
+
+    #[tokio::test]
+    async fn test_split_outside_wave_and_tmux_is_a_noop() {
+        let integration = WaveIntegration::new(None);
+        let message = integration.split_in(None, "rust-pro", SplitDirection::Vertical).await.unwrap();
+        assert_eq!(message, "Not running inside Wave Terminal or tmux; no pane split for rust-pro");
+    }
+
+    #[test]
+    fn test_multiplexer_follows_the_environment() {
+        assert_eq!(PaneHost::from_env(Some("waveterm"), None, None), Some(PaneHost::Wave));
+        assert_eq!(PaneHost::from_env(None, Some("1"), None), Some(PaneHost::Wave));
+        assert_eq!(PaneHost::from_env(Some("iTerm.app"), None, Some("/tmp/tmux-1000/default,42,0")), Some(PaneHost::Tmux));
+        // tmux inside a Wave tab still opens Wave tabs
+        assert_eq!(PaneHost::from_env(Some("waveterm"), None, Some("/tmp/tmux-1000/default,42,0")), Some(PaneHost::Wave));
+        assert_eq!(PaneHost::from_env(Some("iTerm.app"), None, Some("")), None);
+        assert_eq!(PaneHost::from_env(None, None, None), None);
+
+        let integration = WaveIntegration::new(None);
+        assert_eq!(integration.multiplexer_for(Some(PaneHost::Tmux)).unwrap().name(), "tmux");
+        assert_eq!(integration.multiplexer_for(Some(PaneHost::Wave)).unwrap().name(), "Wave");
+        let err = integration.multiplexer_for(None).unwrap_err().to_string();
+        assert!(err.starts_with("Not running inside Wave Terminal or tmux"), "{}", err);
+    }
+
+    #[test]
+    fn test_tmux_runs_the_same_spawn_command_as_wave() {
+        assert_eq!(
+            TmuxMultiplexer::window_args("rust-pro", Some("/src/app")),
+            ["new-window", "-P", "-F", "#{window_id}", "-n", "rust-pro", "-c", "/src/app", "anf spawn rust-pro"]
+        );
+        assert_eq!(TmuxMultiplexer::split_args("coder", SplitDirection::Vertical), ["split-window", "-v", "-P", "-F", "#{pane_id}", "anf spawn coder"]);
+        assert_eq!(WaveMultiplexer::split_args("coder", SplitDirection::Vertical).last().unwrap(), "anf spawn coder");
+        assert_eq!(WaveMultiplexer::tab_args("coder", None).last().unwrap(), "anf spawn coder");
+    }
+
     #[test]
     fn test_wave_ui_creation() {
         let ui = WaveUI::new();
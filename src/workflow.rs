@@ -0,0 +1,398 @@
+// ANF Workflows - Multi-step agent runs loaded from ~/.anf/workflows/<name>.toml
+// Steps declare their dependencies; the runner submits them in dependency order.
+// A prompt can use `{{name}}` for a value from the workflow's `[vars]` table
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Workflow {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub vars: HashMap<String, String>,
+    #[serde(default)]
+    pub steps: Vec<WorkflowStep>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowStep {
+    pub id: String,
+    pub agent: String,
+    pub prompt: String,
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+}
+
+impl Workflow {
+    pub fn dir() -> PathBuf {
+        crate::paths::anf_path("workflows")
+    }
+
+    /// Load by name from the workflows directory, or directly from a `.toml` path
+    pub fn load(name: &str) -> anyhow::Result<Self> {
+        let path = if name.ends_with(".toml") || Path::new(name).is_file() {
+            PathBuf::from(name)
+        } else {
+            Self::dir().join(format!("{}.toml", name))
+        };
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("Failed to read workflow {}: {}", path.display(), e))?;
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> anyhow::Result<Self> {
+        toml::from_str(contents).map_err(|e| anyhow::anyhow!("Invalid workflow: {}", e))
+    }
+
+    /// Steps sorted so each comes after its dependencies; ties keep declaration order
+    pub fn execution_order(&self) -> anyhow::Result<Vec<&WorkflowStep>> {
+        let ids: HashSet<&str> = self.steps.iter().map(|s| s.id.as_str()).collect();
+        for step in &self.steps {
+            if let Some(missing) = step.depends_on.iter().find(|d| !ids.contains(d.as_str())) {
+                return Err(anyhow::anyhow!("Step '{}' depends on unknown step '{}'", step.id, missing));
+            }
+        }
+
+        let mut remaining: HashMap<&str, usize> = self.steps.iter()
+            .map(|s| (s.id.as_str(), s.depends_on.len()))
+            .collect();
+        let mut order = Vec::with_capacity(self.steps.len());
+        while order.len() < self.steps.len() {
+            let next = self.steps.iter()
+                .find(|s| remaining.get(s.id.as_str()) == Some(&0))
+                .ok_or_else(|| anyhow::anyhow!("Workflow '{}' has a dependency cycle", self.name))?;
+            remaining.remove(next.id.as_str());
+            for step in &self.steps {
+                if step.depends_on.contains(&next.id) {
+                    if let Some(count) = remaining.get_mut(step.id.as_str()) {
+                        *count -= 1;
+                    }
+                }
+            }
+            order.push(next);
+        }
+        Ok(order)
+    }
+
+    /// `step`'s prompt with its `{{name}}` variables filled in from `vars`.
+    /// Other `{{...}}` text, such as the daemon's `{{dep:<id>.result}}`, is kept.
+    pub fn render_prompt(&self, step: &WorkflowStep) -> anyhow::Result<String> {
+        let mut prompt = step.prompt.clone();
+        for name in variables(&step.prompt) {
+            let value = self.vars.get(name)
+                .ok_or_else(|| anyhow::anyhow!("Step '{}' uses undefined variable {{{{{}}}}}", step.id, name))?;
+            prompt = prompt.replace(&format!("{{{{{}}}}}", name), value);
+        }
+        Ok(prompt)
+    }
+
+    /// Everything that would stop the run or spoil its output, without running it:
+    /// unknown agents, undefined variables, bad dependencies, an unwritable `output_dir`
+    pub fn check(&self, agents: &[String], output_dir: Option<&Path>) -> Vec<String> {
+        let mut problems = Vec::new();
+        for step in &self.steps {
+            if !agents.contains(&step.agent) {
+                problems.push(format!("Step '{}' uses unknown agent '{}'", step.id, step.agent));
+            }
+            if let Err(e) = self.render_prompt(step) {
+                problems.push(e.to_string());
+            }
+        }
+        if let Err(e) = self.execution_order() {
+            problems.push(e.to_string());
+        }
+        if let Some(problem) = output_dir.and_then(unwritable) {
+            problems.push(problem);
+        }
+        problems
+    }
+
+    /// Run the steps in execution order through `ask`, publishing a `StepEvent` at
+    /// each transition. With `confirmation`, a step waits for `answer` before it
+    /// runs. The first failing step ends the run with its error.
+    pub async fn run<A, Fut>(
+        &self,
+        mut confirmation: Option<StepConfirmation>,
+        mut answer: impl FnMut() -> anyhow::Result<String>,
+        mut ask: A,
+        events: &broadcast::Sender<StepEvent>,
+    ) -> anyhow::Result<()>
+    where
+        A: FnMut(&WorkflowStep) -> Fut,
+        Fut: Future<Output = anyhow::Result<String>>,
+    {
+        let steps = self.execution_order()?;
+        // Every prompt is rendered before the first is sent, so a typo can't fail the run halfway
+        let steps = steps.into_iter()
+            .map(|step| Ok(WorkflowStep { prompt: self.render_prompt(step)?, ..step.clone() }))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let total = steps.len();
+        let publish = |step: &WorkflowStep, index: usize, state: StepState, detail: Option<String>| {
+            // Nobody listening is fine
+            let _ = events.send(StepEvent {
+                workflow: self.name.clone(),
+                step: step.id.clone(),
+                agent: step.agent.clone(),
+                index: index + 1,
+                total,
+                state,
+                detail,
+                at: chrono::Utc::now(),
+            });
+        };
+
+        for (index, step) in steps.iter().enumerate() {
+            if let Some(confirmation) = confirmation.as_mut() {
+                let mut reply = String::new();
+                if confirmation.needs_prompt() {
+                    publish(step, index, StepState::AwaitingApproval, Some(step.prompt.clone()));
+                    reply = answer()?;
+                }
+                if confirmation.decide(&reply) == StepDecision::Skip {
+                    publish(step, index, StepState::Skipped, None);
+                    continue;
+                }
+            }
+
+            publish(step, index, StepState::Started, None);
+            match ask(step).await {
+                Ok(output) => publish(step, index, StepState::Completed, Some(output)),
+                Err(e) => {
+                    publish(step, index, StepState::Failed, Some(e.to_string()));
+                    return Err(e);
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Names of the `{{name}}` variables in `prompt`, in order of appearance
+fn variables(prompt: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = prompt;
+    while let Some(start) = rest.find("{{") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("}}") else { break };
+        let name = &rest[..end];
+        if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            names.push(name);
+            rest = &rest[end + 2..];
+        }
+    }
+    names
+}
+
+/// Why files can't be written under `dir`, judged by its nearest existing ancestor
+fn unwritable(dir: &Path) -> Option<String> {
+    let existing = dir.ancestors().find(|path| path.exists())?;
+    match std::fs::metadata(existing) {
+        Ok(metadata) if !metadata.is_dir() => Some(format!("Output directory {}: {} is not a directory", dir.display(), existing.display())),
+        Ok(metadata) if metadata.permissions().readonly() => Some(format!("Output directory {}: {} is read-only", dir.display(), existing.display())),
+        Ok(_) => None,
+        Err(e) => Some(format!("Output directory {}: {}", dir.display(), e)),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepState {
+    /// Waiting on a `--step-confirm` answer
+    AwaitingApproval,
+    Started,
+    Completed,
+    Failed,
+    Skipped,
+}
+
+/// One step transition, for progress displays and NDJSON output
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StepEvent {
+    pub workflow: String,
+    pub step: String,
+    pub agent: String,
+    /// 1-based position in execution order, out of `total`
+    pub index: usize,
+    pub total: usize,
+    pub state: StepState,
+    /// The output once completed, the error once failed, the prompt while awaiting approval
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub at: chrono::DateTime<chrono::Utc>,
+}
+
+/// What to do with a step once the user has answered (or not been asked)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepDecision {
+    Run,
+    Skip,
+}
+
+/// Per-step approval for `anf run --step-confirm`
+#[derive(Debug, Clone, Default)]
+pub struct StepConfirmation {
+    approve_all: bool,
+}
+
+impl StepConfirmation {
+    /// `--yes` approves every step up front
+    pub fn approve_all() -> Self {
+        Self { approve_all: true }
+    }
+
+    /// Whether the user has to be asked about the next step
+    pub fn needs_prompt(&self) -> bool {
+        !self.approve_all
+    }
+
+    /// Interpret a y/N/a answer; anything unrecognised (including empty) skips
+    pub fn decide(&mut self, answer: &str) -> StepDecision {
+        if self.approve_all {
+            return StepDecision::Run;
+        }
+        match answer.trim().to_ascii_lowercase().as_str() {
+            "y" | "yes" => StepDecision::Run,
+            "a" | "all" => {
+                self.approve_all = true;
+                StepDecision::Run
+            },
+            _ => StepDecision::Skip,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORKFLOW: &str = r#"
+name = "review"
+
+[[steps]]
+id = "test"
+agent = "test-automator"
+prompt = "Write tests for the fixes"
+depends_on = ["fix"]
+
+[[steps]]
+id = "analyze"
+agent = "code-reviewer"
+prompt = "Review src/"
+
+[[steps]]
+id = "fix"
+agent = "rust-pro"
+prompt = "Fix the findings"
+depends_on = ["analyze"]
+"#;
+
+    #[test]
+    fn test_execution_order_follows_dependencies() {
+        let workflow = Workflow::parse(WORKFLOW).unwrap();
+        let order: Vec<&str> = workflow.execution_order().unwrap().iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(order, vec!["analyze", "fix", "test"]);
+
+        let cyclic = Workflow::parse(&WORKFLOW.replace("prompt = \"Review src/\"", "prompt = \"x\"\ndepends_on = [\"test\"]")).unwrap();
+        assert!(cyclic.execution_order().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_emits_ordered_step_events() {
+        let workflow = Workflow::parse(WORKFLOW).unwrap();
+        let (events, mut received) = broadcast::channel(16);
+        let mut answers = vec!["a", "n"];
+
+        let asked = std::cell::RefCell::new(Vec::new());
+        workflow.run(
+            Some(StepConfirmation::default()),
+            || Ok(answers.pop().unwrap().to_string()),
+            |step| {
+                asked.borrow_mut().push(step.id.clone());
+                let id = step.id.clone();
+                async move { if id == "test" { Err(anyhow::anyhow!("no test runner")) } else { Ok(format!("{} done", id)) } }
+            },
+            &events,
+        ).await.unwrap_err();
+
+        let mut seen = Vec::new();
+        while let Ok(event) = received.try_recv() {
+            seen.push((event.step, event.state, event.index, event.detail));
+        }
+        use StepState::*;
+        assert_eq!(seen, vec![
+            ("analyze".to_string(), AwaitingApproval, 1, Some("Review src/".to_string())),
+            ("analyze".to_string(), Skipped, 1, None),
+            ("fix".to_string(), AwaitingApproval, 2, Some("Fix the findings".to_string())),
+            ("fix".to_string(), Started, 2, None),
+            ("fix".to_string(), Completed, 2, Some("fix done".to_string())),
+            ("test".to_string(), Started, 3, None),
+            ("test".to_string(), Failed, 3, Some("no test runner".to_string())),
+        ]);
+        assert_eq!(asked.into_inner(), vec!["fix", "test"]);
+    }
+
+    #[tokio::test]
+    async fn test_check_reports_every_problem_and_run_submits_nothing() {
+        let workflow = Workflow::parse(r#"
+name = "release"
+
+[vars]
+crate = "anf"
+
+[[steps]]
+id = "notes"
+agent = "writer"
+prompt = "Draft notes for {{crate}} {{version}}, after {{dep:abc.result}}"
+
+[[steps]]
+id = "review"
+agent = "code-reviewer"
+prompt = "Review the {{crate}} notes"
+depends_on = ["notes"]
+"#).unwrap();
+        let agents = vec!["code-reviewer".to_string()];
+        assert_eq!(workflow.check(&agents, None), vec![
+            "Step 'notes' uses unknown agent 'writer'",
+            "Step 'notes' uses undefined variable {{version}}",
+        ]);
+        assert_eq!(workflow.render_prompt(&workflow.steps[1]).unwrap(), "Review the anf notes");
+
+        let file = std::env::temp_dir().join(format!("anf-check-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&file, "").unwrap();
+        assert_eq!(workflow.check(&agents, Some(&file.join("out"))).len(), 3);
+        std::fs::remove_file(&file).unwrap();
+
+        let (events, _) = broadcast::channel(16);
+        let asked = std::cell::Cell::new(0);
+        let error = workflow.run(None, || Ok(String::new()), |_| {
+            asked.set(asked.get() + 1);
+            async { Ok(String::new()) }
+        }, &events).await.unwrap_err();
+        assert_eq!(error.to_string(), "Step 'notes' uses undefined variable {{version}}");
+        assert_eq!(asked.get(), 0);
+    }
+
+    #[test]
+    fn test_step_confirmation_state_machine() {
+        let mut confirm = StepConfirmation::default();
+        assert!(confirm.needs_prompt());
+        assert_eq!(confirm.decide("y"), StepDecision::Run);
+        assert_eq!(confirm.decide("n"), StepDecision::Skip);
+        assert_eq!(confirm.decide(""), StepDecision::Skip);
+        assert!(confirm.needs_prompt());
+
+        assert_eq!(confirm.decide("a"), StepDecision::Run);
+        assert!(!confirm.needs_prompt());
+        // Remaining steps run without asking, whatever the input
+        assert_eq!(confirm.decide("n"), StepDecision::Run);
+
+        let mut yes = StepConfirmation::approve_all();
+        assert!(!yes.needs_prompt());
+        assert_eq!(yes.decide(""), StepDecision::Run);
+    }
+}
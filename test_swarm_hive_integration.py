@@ -14,6 +14,7 @@ from pathlib import Path
 from core.agents.agent_manager import AgentManager
 from core.coordination.swarm_manager import SwarmManager, SwarmTopology
 from core.coordination.hive_intelligence import HiveIntelligence, HiveDecisionMethod, HiveMemoryType
+from core.coordination.hive_memory import InMemoryBackend
 from core.coordination.swarm_hive_coordinator import SwarmHiveCoordinator, CoordinationTask, CoordinationMode
 
 async def test_swarm_functionality():
@@ -63,12 +64,44 @@ async def test_swarm_functionality():
     await swarm_manager.dissolve_swarm("ios_dev_swarm")
     print("🧹 Swarm dissolved\n")
 
+async def test_swarm_status_counts_queued_tasks():
+    """Tasks beyond the swarm's coordination slots show as queued until they start"""
+    print("\n⏳ Testing Swarm Task Queue")
+    print("=" * 50)
+    
+    swarm_manager = SwarmManager()
+    swarm = await swarm_manager.create_swarm("queue_swarm", SwarmTopology.MESH, [], {})
+    release = asyncio.Event()
+    
+    async def held(swarm, task):
+        await release.wait()
+        return {"status": "completed"}
+    
+    swarm_manager.coordination_patterns[SwarmTopology.MESH] = held
+    tasks = [
+        asyncio.create_task(swarm_manager.coordinate_swarm_task("queue_swarm", {"type": f"job_{i}"}))
+        for i in range(swarm.max_running + 2)
+    ]
+    await asyncio.sleep(0.05)
+    status = swarm_manager.get_swarm_status("queue_swarm")
+    assert (status["running_tasks"], status["task_queue_size"]) == (swarm.max_running, 2), status
+    print(f"✅ {status['running_tasks']} running, {status['task_queue_size']} queued")
+    
+    release.set()
+    await asyncio.gather(*tasks)
+    status = swarm_manager.get_swarm_status("queue_swarm")
+    assert (status["running_tasks"], status["task_queue_size"], status["completed_tasks"]) == (0, 0, swarm.max_running + 2), status
+    print("✅ Queue drained as tasks started")
+    
+    await swarm_manager.dissolve_swarm("queue_swarm")
+
 async def test_hive_intelligence():
     """Test hive intelligence and collective decision-making"""
     print("\n🧠 Testing Hive Intelligence")
     print("=" * 50)
     
-    hive = HiveIntelligence()
+    # In memory, so runs don't write to ~/.anf or see each other's fragments
+    hive = HiveIntelligence(memory_backend=InMemoryBackend())
     
     # Initialize hive nodes
     agents_and_capabilities = [
@@ -141,7 +174,7 @@ async def test_swarm_hive_hybrid():
     
     # Initialize full coordinator
     agent_manager = AgentManager()
-    coordinator = SwarmHiveCoordinator(agent_manager)
+    coordinator = SwarmHiveCoordinator(agent_manager, memory_backend=InMemoryBackend())
     
     # Test different coordination modes
     test_tasks = [
@@ -213,7 +246,7 @@ async def test_adaptive_coordination():
     print("=" * 50)
     
     agent_manager = AgentManager()
-    coordinator = SwarmHiveCoordinator(agent_manager)
+    coordinator = SwarmHiveCoordinator(agent_manager, memory_backend=InMemoryBackend())
     
     # Create adaptive tasks with different characteristics
     adaptive_tasks = [
@@ -262,7 +295,7 @@ async def test_agent_manager_integration():
     print("=" * 50)
     
     # Initialize enhanced agent manager
-    coordinator = SwarmHiveCoordinator()
+    coordinator = SwarmHiveCoordinator(memory_backend=InMemoryBackend())
     agent_manager = AgentManager(swarm_hive_coordinator=coordinator)
     
     # Test swarm-hive recommendations
@@ -317,7 +350,7 @@ async def test_system_status_monitoring():
     print("\n📊 Testing System Status Monitoring")
     print("=" * 50)
     
-    coordinator = SwarmHiveCoordinator()
+    coordinator = SwarmHiveCoordinator(memory_backend=InMemoryBackend())
     
     # Get comprehensive status
     status = coordinator.get_coordination_status()
@@ -344,6 +377,7 @@ async def main():
     
     # Run all test suites
     await test_swarm_functionality()
+    await test_swarm_status_counts_queued_tasks()
     await test_hive_intelligence()
     await test_swarm_hive_hybrid()
     await test_adaptive_coordination()